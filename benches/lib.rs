@@ -1,81 +1,199 @@
-#![feature(test)]
-
-extern crate test;
+//! Benchmarks running on stable Rust, with bincode as a baseline for
+//! the same data. Run with `cargo bench`.
+
+#[macro_use]
+extern crate criterion;
+#[macro_use]
+extern crate serde;
+extern crate bincode;
 extern crate bitsparrow;
 
+use std::collections::HashMap;
+
 use bitsparrow::*;
+use criterion::{black_box, Criterion, Throughput};
 
-use test::Bencher;
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Customer {
+    name: String,
+    email: String,
+}
 
-#[bench]
-fn encode_u64(b: &mut Bencher) {
-    b.iter(|| {
-        Encoder::encode(::std::u64::MAX)
-    })
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Item {
+    sku: u32,
+    quantity: u16,
+    price: f64,
 }
 
-#[bench]
-fn encode_f64(b: &mut Bencher) {
-    b.iter(|| {
-        Encoder::encode(3.141592653589793f64)
-    })
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Order {
+    id: u64,
+    paid: bool,
+    customer: Customer,
+    items: Vec<Item>,
 }
 
-#[bench]
-fn encode_slice(b: &mut Bencher) {
-    b.iter(|| {
-        Encoder::encode(b"hello world!")
-    })
+impl BitEncode for Customer {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.string(&self.name).string(&self.email);
+    }
 }
 
-#[bench]
-fn encode_tuple(b: &mut Bencher) {
-    b.iter(|| {
-        Encoder::encode(("hello world!", 3.14f32, false))
-    })
+impl<'src> BitDecode<'src> for Customer {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(Customer {
+            name: d.read()?,
+            email: d.read()?,
+        })
+    }
 }
 
-#[bench]
-fn encode_complex_slice(b: &mut Bencher) {
-    b.iter(|| {
-        Encoder::encode(&[3.14f32, 2.15, 1.16])
-    })
+impl BitEncode for Item {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.uint32(self.sku).uint16(self.quantity).float64(self.price);
+    }
+
+    fn size_hint() -> usize {
+        14
+    }
 }
 
-#[bench]
-fn decode_complex_vec(b: &mut Bencher) {
-    let buffer = Encoder::encode(&[3.14f32, 2.15, 1.16]);
+impl<'src> BitDecode<'src> for Item {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(Item {
+            sku: d.uint32()?,
+            quantity: d.uint16()?,
+            price: d.float64()?,
+        })
+    }
+}
+
+impl BitEncode for Order {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.uint64(self.id).bool(self.paid).write(&self.customer).write(&self.items);
+    }
+}
 
-    b.iter(|| {
-        let _foo: Vec<f32> = Decoder::decode(&buffer).unwrap();
-    })
+impl<'src> BitDecode<'src> for Order {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(Order {
+            id: d.uint64()?,
+            paid: d.bool()?,
+            customer: d.read()?,
+            items: d.read()?,
+        })
+    }
 }
 
-#[bench]
-fn decode_tuple(b: &mut Bencher) {
+fn order() -> Order {
+    Order {
+        id: 9001,
+        paid: true,
+        customer: Customer {
+            name: "Jack Sparrow".into(),
+            email: "jack@blackpearl.sea".into(),
+        },
+        items: (0..10).map(|i| Item { sku: 1000 + i, quantity: i as u16, price: 3.14 * i as f64 }).collect(),
+    }
+}
+
+fn primitives(c: &mut Criterion) {
+    c.bench_function("encode_u64", |b| b.iter(|| Encoder::encode(black_box(u64::MAX))));
+    c.bench_function("encode_f64", |b| b.iter(|| Encoder::encode(black_box(3.141592653589793f64))));
+    c.bench_function("encode_tuple", |b| b.iter(|| Encoder::encode(black_box(("hello world!", 3.14f32, false)))));
+
+    let buffer = Encoder::encode(u64::MAX);
+    c.bench_function("decode_u64", |b| b.iter(|| Decoder::new(black_box(&buffer)).uint64().unwrap()));
+
+    let buffer = Encoder::encode(3.141592653589793f64);
+    c.bench_function("decode_f64", |b| b.iter(|| Decoder::new(black_box(&buffer)).float64().unwrap()));
+
     let buffer = Encoder::encode((10u64, 3.14f32, true));
+    c.bench_function("decode_tuple", |b| b.iter(|| {
+        Decoder::decode::<(u64, f32, bool)>(black_box(&buffer)).unwrap()
+    }));
+}
 
-    b.iter(|| {
-        let _foo: (u64, f32, bool) = Decoder::decode(&buffer).unwrap();
-    })
+fn strings(c: &mut Criterion) {
+    let words: Vec<String> = (0..100).map(|i| format!("sparrow number {}", i)).collect();
+    let mut group = c.benchmark_group("strings");
+
+    group.bench_function("bitsparrow_encode", |b| b.iter(|| Encoder::encode(black_box(&words))));
+    group.bench_function("bincode_encode", |b| b.iter(|| bincode::serialize(black_box(&words)).unwrap()));
+
+    let buffer = Encoder::encode(&words);
+    group.bench_function("bitsparrow_decode", |b| b.iter(|| {
+        Decoder::decode::<Vec<String>>(black_box(&buffer)).unwrap()
+    }));
+    group.bench_function("bitsparrow_decode_borrowed", |b| b.iter(|| {
+        Decoder::decode::<Vec<&str>>(black_box(&buffer)).unwrap()
+    }));
+
+    let buffer = bincode::serialize(&words).unwrap();
+    group.bench_function("bincode_decode", |b| b.iter(|| {
+        bincode::deserialize::<Vec<String>>(black_box(&buffer)).unwrap()
+    }));
+    group.finish();
 }
 
-#[bench]
-fn decode_u64(b: &mut Bencher) {
-    let buffer = Encoder::encode(::std::u64::MAX);
+fn nested_structs(c: &mut Criterion) {
+    let order = order();
+    let mut group = c.benchmark_group("nested_structs");
+
+    group.bench_function("bitsparrow_encode", |b| b.iter(|| Encoder::encode(black_box(&order))));
+    group.bench_function("bincode_encode", |b| b.iter(|| bincode::serialize(black_box(&order)).unwrap()));
+
+    let buffer = Encoder::encode(&order);
+    group.bench_function("bitsparrow_decode", |b| b.iter(|| {
+        Decoder::decode::<Order>(black_box(&buffer)).unwrap()
+    }));
 
-    b.iter(|| {
-        let mut decoder = Decoder::new(&buffer);
-        decoder.uint64().unwrap()
-    })
+    let buffer = bincode::serialize(&order).unwrap();
+    group.bench_function("bincode_decode", |b| b.iter(|| {
+        bincode::deserialize::<Order>(black_box(&buffer)).unwrap()
+    }));
+    group.finish();
 }
 
-#[bench]
-fn decode_f64(b: &mut Bencher) {
-    let buffer = Encoder::encode(3.141592653589793f64);
+fn numeric_slices(c: &mut Criterion) {
+    let numbers: Vec<f64> = (0..10_000).map(|i| i as f64 * 0.5).collect();
+    let mut group = c.benchmark_group("numeric_slices");
+
+    group.throughput(Throughput::Bytes(numbers.len() as u64 * 8));
+    group.bench_function("bitsparrow_encode", |b| b.iter(|| Encoder::encode(black_box(&numbers))));
+    group.bench_function("bincode_encode", |b| b.iter(|| bincode::serialize(black_box(&numbers)).unwrap()));
+
+    let buffer = Encoder::encode(&numbers);
+    group.bench_function("bitsparrow_decode", |b| b.iter(|| {
+        Decoder::decode::<Vec<f64>>(black_box(&buffer)).unwrap()
+    }));
 
-    b.iter(|| {
-        let mut decoder = Decoder::new(&buffer);
-        decoder.float64().unwrap()
-    })
+    let buffer = bincode::serialize(&numbers).unwrap();
+    group.bench_function("bincode_decode", |b| b.iter(|| {
+        bincode::deserialize::<Vec<f64>>(black_box(&buffer)).unwrap()
+    }));
+    group.finish();
 }
+
+fn maps(c: &mut Criterion) {
+    let map: HashMap<String, u64> = (0..1000).map(|i| (format!("key{}", i), i)).collect();
+    let mut group = c.benchmark_group("maps");
+
+    group.bench_function("bitsparrow_encode", |b| b.iter(|| Encoder::encode(black_box(&map))));
+    group.bench_function("bincode_encode", |b| b.iter(|| bincode::serialize(black_box(&map)).unwrap()));
+
+    let buffer = Encoder::encode(&map);
+    group.bench_function("bitsparrow_decode", |b| b.iter(|| {
+        Decoder::decode::<HashMap<String, u64>>(black_box(&buffer)).unwrap()
+    }));
+
+    let buffer = bincode::serialize(&map).unwrap();
+    group.bench_function("bincode_decode", |b| b.iter(|| {
+        bincode::deserialize::<HashMap<String, u64>>(black_box(&buffer)).unwrap()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, primitives, strings, nested_structs, numeric_slices, maps);
+criterion_main!(benches);
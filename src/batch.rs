@@ -0,0 +1,153 @@
+//! Batches of small messages stored in a single buffer. A batch starts
+//! with a `size` holding the number of messages, followed by every
+//! message as `bytes`, so each one can be decoded, or skipped, on its
+//! own.
+//!
+//! ```
+//! use bitsparrow::batch::Batch;
+//!
+//! let mut batch = Batch::new();
+//! batch.push(("foo", 1u32)).push(("bar", 2u32));
+//!
+//! let buffer = batch.end();
+//! let mut reader = Batch::read(&buffer).unwrap();
+//!
+//! assert_eq!(reader.len(), 2);
+//! assert_eq!(reader.decode_next::<(&str, u32)>().unwrap().unwrap(), ("foo", 1));
+//! assert_eq!(reader.decode_next::<(&str, u32)>().unwrap().unwrap(), ("bar", 2));
+//! assert!(reader.decode_next::<(&str, u32)>().is_none());
+//! ```
+
+use encode::{Encoder, SizeCounter, BitEncode};
+use decode::{Decoder, BitDecode};
+use sink::Sink;
+use utils::Result;
+
+/// Accumulates encoded messages, see the module documentation.
+#[derive(Default)]
+pub struct Batch {
+    count: usize,
+    body: Vec<u8>,
+}
+
+impl Batch {
+    /// Create an empty `Batch`.
+    #[inline]
+    pub fn new() -> Batch {
+        Batch::default()
+    }
+
+    /// Create an empty `Batch` with room for `capacity` bytes
+    /// of messages.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Batch {
+        Batch {
+            count: 0,
+            body: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Encode a message into the batch.
+    pub fn push<E: BitEncode>(&mut self, val: E) -> &mut Self {
+        let len = SizeCounter::count(&val);
+
+        Encoder::with_sink(&mut self.body).size(len).write(val);
+        self.count += 1;
+
+        self
+    }
+
+    /// Number of messages in the batch.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns `true` if no messages have been pushed yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Encode the batch into a new buffer, leaving it empty.
+    pub fn end(&mut self) -> Vec<u8> {
+        let buffer = Encoder::encode(&*self);
+
+        self.count = 0;
+        self.body.clear();
+
+        buffer
+    }
+
+    /// Start reading a batch from `data`.
+    pub fn read<'src>(data: &'src [u8]) -> Result<BatchReader<'src>> {
+        let mut decoder = Decoder::new(data);
+        let remaining = decoder.size()?;
+
+        Ok(BatchReader {
+            decoder,
+            remaining,
+        })
+    }
+}
+
+/// A `Batch` can be stored as a value of another message.
+impl BitEncode for Batch {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.size(self.count).append_raw(&self.body);
+    }
+}
+
+/// Iterator over the payloads of messages in a batch, created by
+/// `Batch::read`. Iteration stops after the first error.
+pub struct BatchReader<'src> {
+    decoder: Decoder<'src>,
+    remaining: usize,
+}
+
+impl<'src> BatchReader<'src> {
+    /// Number of messages left to read.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.remaining
+    }
+
+    /// Returns `true` if all messages have been read.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Decode the next message, which has to fill its payload entirely.
+    #[inline]
+    pub fn decode_next<D: BitDecode<'src>>(&mut self) -> Option<Result<D>> {
+        self.next().map(|payload| payload.and_then(Decoder::decode))
+    }
+
+    /// Returns `true` if all messages have been read and there is
+    /// nothing left in the buffer.
+    #[inline]
+    pub fn end(&self) -> bool {
+        self.remaining == 0 && self.decoder.end()
+    }
+}
+
+impl<'src> Iterator for BatchReader<'src> {
+    type Item = Result<&'src [u8]>;
+
+    fn next(&mut self) -> Option<Result<&'src [u8]>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let payload = self.decoder.bytes();
+
+        self.remaining = match payload {
+            Ok(_)  => self.remaining - 1,
+            Err(_) => 0,
+        };
+
+        Some(payload)
+    }
+}
@@ -0,0 +1,643 @@
+//! Typed channels sending and receiving values of a single type over
+//! a stream, such as a TCP connection or a pipe. Every message is
+//! framed as a `size` prefix followed by the encoded value, so the
+//! receiving end knows where one message ends and the next begins.
+//!
+//! ```
+//! use bitsparrow::channel::{TypedSender, TypedReceiver};
+//!
+//! let mut sender = TypedSender::new(Vec::new());
+//!
+//! sender.send(&("login", 42u32)).unwrap();
+//! sender.send(&("logout", 42u32)).unwrap();
+//!
+//! let stream = sender.into_inner();
+//! let mut receiver = TypedReceiver::<(String, u32), _>::new(&stream[..]);
+//!
+//! assert_eq!(receiver.recv().unwrap(), Some(("login".into(), 42)));
+//! assert_eq!(receiver.recv().unwrap(), Some(("logout".into(), 42)));
+//! assert_eq!(receiver.recv().unwrap(), None);
+//! ```
+//!
+//! With the `tokio-util` feature, `Codec` implements the same framing
+//! for `FramedRead` and `FramedWrite` on asynchronous streams, while
+//! `StreamDecoder` decodes it from chunks pushed as they arrive, such
+//! as those of a Fetch response body in the browser.
+//!
+//! For tests of protocol logic, `duplex` connects two endpoints in
+//! memory, with every message still going through encoding, framing
+//! and decoding as it would over a socket:
+//!
+//! ```
+//! use std::thread;
+//! use bitsparrow::channel;
+//!
+//! let (mut client, mut server) = channel::duplex::<String, u32>();
+//!
+//! let handle = thread::spawn(move || {
+//!     while let Some(name) = server.recv().unwrap() {
+//!         server.send(&(name.len() as u32)).unwrap();
+//!     }
+//! });
+//!
+//! client.send(&"sparrow".to_string()).unwrap();
+//!
+//! assert_eq!(client.recv().unwrap(), Some(7));
+//!
+//! drop(client);
+//! handle.join().unwrap();
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::sync::{Arc, Condvar, Mutex};
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use sink::Slice;
+use source::Reader;
+use utils::{Error, Result};
+
+/// Default limit of the size of a single message, protecting against
+/// peers announcing huge messages.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Largest number of bytes taken by a `size` prefix.
+const MAX_PREFIX_SIZE: usize = 10;
+
+/// Sending end of a typed channel, writing messages to an `io::Write`.
+///
+/// Every message is written, prefix included, with a single call to
+/// `write_all`, so readers of the stream never see a frame that has
+/// been only partially written by a writer that doesn't split writes.
+pub struct TypedSender<T, W> {
+    writer: W,
+    buffer: Vec<u8>,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: BitEncode, W: Write> TypedSender<T, W> {
+    /// Create a sender writing to `writer`.
+    #[inline]
+    pub fn new(writer: W) -> TypedSender<T, W> {
+        TypedSender {
+            writer,
+            buffer: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Encode and write a message.
+    pub fn send(&mut self, message: &T) -> io::Result<()> {
+        // Leave room for the prefix in front of the message
+        self.buffer.clear();
+        self.buffer.resize(MAX_PREFIX_SIZE, 0);
+        Encoder::with_sink(&mut self.buffer).write(message);
+
+        let mut prefix = [0; MAX_PREFIX_SIZE];
+        let len = Encoder::with_sink(Slice::new(&mut prefix)).size(self.buffer.len() - MAX_PREFIX_SIZE).end()?;
+        let start = MAX_PREFIX_SIZE - len;
+
+        self.buffer[start..MAX_PREFIX_SIZE].copy_from_slice(&prefix[..len]);
+        self.writer.write_all(&self.buffer[start..])
+    }
+
+    /// Flush the writer.
+    #[inline]
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Get a reference to the writer.
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the writer.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Obtain the writer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<T, W> fmt::Debug for TypedSender<T, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedSender").finish()
+    }
+}
+
+/// Receiving end of a typed channel, reading messages from an `io::Read`.
+///
+/// Reads are not buffered, wrap unbuffered readers such as `TcpStream`
+/// in a `BufReader`.
+pub struct TypedReceiver<T, R> {
+    reader: R,
+    buffer: Vec<u8>,
+    max_size: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: for<'a> BitDecode<'a>, R: Read> TypedReceiver<T, R> {
+    /// Create a receiver reading from `reader`, accepting messages of up
+    /// to `DEFAULT_MAX_MESSAGE_SIZE` bytes.
+    #[inline]
+    pub fn new(reader: R) -> TypedReceiver<T, R> {
+        TypedReceiver::with_max_size(reader, DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    /// Create a receiver reading from `reader`, accepting messages of up
+    /// to `max_size` bytes.
+    #[inline]
+    pub fn with_max_size(reader: R, max_size: usize) -> TypedReceiver<T, R> {
+        TypedReceiver {
+            reader,
+            buffer: Vec::new(),
+            max_size,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Read and decode a message, returning `None` if the reader ended
+    /// in between messages. Fails with `LimitExceeded` if the message is
+    /// larger than the limit, and with `ReadingOutOfBounds` if the reader
+    /// ended within it. Messages following an error can't be received.
+    pub fn recv(&mut self) -> Result<Option<T>> {
+        let mut d = Decoder::with_source(Reader::new(&mut self.reader));
+
+        if d.end() {
+            return Ok(None);
+        }
+
+        let len = d.size()?;
+
+        if len > self.max_size {
+            return Err(Error::LimitExceeded);
+        }
+
+        self.buffer.resize(len, 0);
+        d.read_exact_into(&mut self.buffer)?;
+
+        Decoder::decode(&self.buffer).map(Some)
+    }
+
+    /// Get a reference to the reader.
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Get a mutable reference to the reader.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Obtain the reader.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<T, R> fmt::Debug for TypedReceiver<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedReceiver")
+            .field("max_size", &self.max_size)
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct PipeState {
+    buffer: VecDeque<u8>,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    ready: Condvar,
+}
+
+impl Pipe {
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, PipeState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+/// Create an in-memory byte stream, see `PipeWriter` and `PipeReader`.
+pub fn pipe() -> (PipeWriter, PipeReader) {
+    let pipe = Arc::new(Pipe::default());
+
+    (PipeWriter { pipe: pipe.clone() }, PipeReader { pipe })
+}
+
+/// Writing end of an in-memory stream created with `pipe`. Writes
+/// never block, as the stream isn't bounded.
+pub struct PipeWriter {
+    pipe: Arc<Pipe>,
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.pipe.lock();
+
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        state.buffer.extend(buf);
+        self.pipe.ready.notify_all();
+
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.pipe.close();
+    }
+}
+
+impl fmt::Debug for PipeWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PipeWriter").finish()
+    }
+}
+
+/// Reading end of an in-memory stream created with `pipe`. Reads block
+/// until data is written, and return `0` once the writer is dropped
+/// and everything written has been read.
+pub struct PipeReader {
+    pipe: Arc<Pipe>,
+}
+
+impl PipeReader {
+    /// Number of bytes written but not read yet.
+    #[inline]
+    pub fn available(&self) -> usize {
+        self.pipe.lock().buffer.len()
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.pipe.lock();
+
+        while state.buffer.is_empty() && !state.closed && !buf.is_empty() {
+            state = self.pipe.ready.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+
+        let len = buf.len().min(state.buffer.len());
+
+        for (byte, value) in buf.iter_mut().zip(state.buffer.drain(..len)) {
+            *byte = value;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.pipe.close();
+    }
+}
+
+impl fmt::Debug for PipeReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PipeReader")
+            .field("available", &self.available())
+            .finish()
+    }
+}
+
+/// Create two endpoints connected in memory, the first sending `A` and
+/// receiving `B`, the second the other way around.
+pub fn duplex<A, B>() -> (Duplex<A, B>, Duplex<B, A>) where
+    A: BitEncode + for<'a> BitDecode<'a>,
+    B: BitEncode + for<'a> BitDecode<'a>,
+{
+    let (a_writer, a_reader) = pipe();
+    let (b_writer, b_reader) = pipe();
+
+    let a = Duplex {
+        sender: TypedSender::new(a_writer),
+        receiver: TypedReceiver::new(b_reader),
+    };
+    let b = Duplex {
+        sender: TypedSender::new(b_writer),
+        receiver: TypedReceiver::new(a_reader),
+    };
+
+    (a, b)
+}
+
+/// Endpoint of an in-memory connection created with `duplex`, sending
+/// messages of type `S` and receiving messages of type `R`.
+#[derive(Debug)]
+pub struct Duplex<S, R> {
+    sender: TypedSender<S, PipeWriter>,
+    receiver: TypedReceiver<R, PipeReader>,
+}
+
+impl<S: BitEncode, R: for<'a> BitDecode<'a>> Duplex<S, R> {
+    /// Send a message to the other endpoint. Fails with `BrokenPipe`
+    /// if the other endpoint has been dropped.
+    #[inline]
+    pub fn send(&mut self, message: &S) -> io::Result<()> {
+        self.sender.send(message)
+    }
+
+    /// Receive a message, blocking until one arrives. Returns `None`
+    /// once the other endpoint has been dropped and all its messages
+    /// have been received.
+    #[inline]
+    pub fn recv(&mut self) -> Result<Option<R>> {
+        self.receiver.recv()
+    }
+
+    /// Receive a message if one has been sent, without waiting. Messages
+    /// are written to the pipe whole, so one that has started arriving
+    /// can be read without blocking.
+    #[inline]
+    pub fn try_recv(&mut self) -> Result<Option<R>> {
+        match self.receiver.get_ref().available() {
+            0 => Ok(None),
+            _ => self.receiver.recv(),
+        }
+    }
+}
+
+/// Read the `size` prefix of a frame, returning its length and the
+/// length of the frame, or `None` if the prefix is incomplete.
+fn frame_header(data: &[u8], max_size: usize) -> Result<Option<(usize, usize)>> {
+    let mut d = Decoder::new(data);
+    let len = match d.size() {
+        Ok(len) => len,
+        Err(Error::ReadingOutOfBounds) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    if len > max_size {
+        return Err(Error::LimitExceeded);
+    }
+
+    Ok(Some((d.position(), len)))
+}
+
+/// Decoder of messages framed like `TypedSender` does, from chunks of a
+/// stream arriving one by one, such as `Uint8Array`s read from the
+/// `ReadableStream` body of a Fetch response in a browser. Only the
+/// message being received is buffered, not the entire stream.
+///
+/// ```
+/// use bitsparrow::channel::{TypedSender, StreamDecoder};
+///
+/// let mut sender = TypedSender::new(Vec::new());
+/// for id in 0..10u32 {
+///     sender.send(&(id, "update")).unwrap();
+/// }
+/// let body = sender.into_inner();
+///
+/// let mut decoder = StreamDecoder::<(u32, String)>::new();
+/// let mut received = Vec::new();
+///
+/// for chunk in body.chunks(7) {
+///     decoder.push(chunk);
+///
+///     while let Some(message) = decoder.pop().unwrap() {
+///         received.push(message);
+///     }
+/// }
+///
+/// decoder.finish().unwrap();
+/// assert_eq!(received.len(), 10);
+/// ```
+pub struct StreamDecoder<T> {
+    buffer: Vec<u8>,
+    offset: usize,
+    max_size: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for StreamDecoder<T> {
+    fn default() -> Self {
+        StreamDecoder::new()
+    }
+}
+
+impl<T> StreamDecoder<T> {
+    /// Create a decoder accepting messages of up to
+    /// `DEFAULT_MAX_MESSAGE_SIZE` bytes.
+    #[inline]
+    pub fn new() -> StreamDecoder<T> {
+        StreamDecoder::with_max_size(DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    /// Create a decoder accepting messages of up to `max_size` bytes.
+    #[inline]
+    pub fn with_max_size(max_size: usize) -> StreamDecoder<T> {
+        StreamDecoder {
+            buffer: Vec::new(),
+            offset: 0,
+            max_size,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of bytes received but not decoded yet.
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.offset
+    }
+
+    /// Add the next chunk of the stream.
+    pub fn push(&mut self, chunk: &[u8]) -> &mut Self {
+        if self.offset == self.buffer.len() {
+            self.buffer.clear();
+            self.offset = 0;
+        } else if self.offset > self.buffer.len() / 2 {
+            self.buffer.drain(..self.offset);
+            self.offset = 0;
+        }
+
+        self.buffer.extend_from_slice(chunk);
+
+        self
+    }
+
+    /// Check that the stream ended in between messages, failing with
+    /// `ReadingOutOfBounds` otherwise.
+    #[inline]
+    pub fn finish(&self) -> Result<()> {
+        match self.buffered() {
+            0 => Ok(()),
+            _ => Err(Error::ReadingOutOfBounds),
+        }
+    }
+}
+
+impl<T: for<'a> BitDecode<'a>> StreamDecoder<T> {
+    /// Decode the next message, returning `None` if it hasn't been
+    /// received in full yet. Fails with `LimitExceeded` if the message
+    /// is larger than the limit, messages following it can't be decoded.
+    /// A message that fails to decode is skipped, so the messages
+    /// following it still can be.
+    pub fn pop(&mut self) -> Result<Option<T>> {
+        let rest = &self.buffer[self.offset..];
+        let (prefix, len) = match frame_header(rest, self.max_size)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        if rest.len() - prefix < len {
+            return Ok(None);
+        }
+
+        let frame = &rest[prefix..prefix + len];
+
+        self.offset += prefix + len;
+
+        Decoder::decode(frame).map(Some)
+    }
+}
+
+impl<T> fmt::Debug for StreamDecoder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamDecoder")
+            .field("buffered", &self.buffered())
+            .field("max_size", &self.max_size)
+            .finish()
+    }
+}
+
+#[cfg(feature = "tokio-util")]
+pub use self::codec::Codec;
+
+#[cfg(feature = "tokio-util")]
+mod codec {
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    use tokio_util::bytes::{Buf, BufMut, BytesMut};
+    use tokio_util::codec;
+
+    use encode::{Encoder, BitEncode};
+    use decode::{Decoder, BitDecode};
+    use utils::{Error, Result};
+
+    use super::{DEFAULT_MAX_MESSAGE_SIZE, MAX_PREFIX_SIZE, frame_header};
+
+    /// Codec framing messages the same way as `TypedSender` and
+    /// `TypedReceiver`, for `FramedRead`, `FramedWrite` and `Framed`
+    /// of `tokio-util`. Requires the `tokio-util` feature.
+    pub struct Codec<T> {
+        max_size: usize,
+        _marker: PhantomData<fn(T) -> T>,
+    }
+
+    impl<T> Codec<T> {
+        /// Create a codec accepting messages of up to
+        /// `DEFAULT_MAX_MESSAGE_SIZE` bytes.
+        #[inline]
+        pub fn new() -> Codec<T> {
+            Codec::with_max_size(DEFAULT_MAX_MESSAGE_SIZE)
+        }
+
+        /// Create a codec accepting messages of up to `max_size` bytes.
+        #[inline]
+        pub fn with_max_size(max_size: usize) -> Codec<T> {
+            Codec {
+                max_size,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    impl<T> Default for Codec<T> {
+        fn default() -> Self {
+            Codec::new()
+        }
+    }
+
+    impl<T> Clone for Codec<T> {
+        fn clone(&self) -> Self {
+            Codec::with_max_size(self.max_size)
+        }
+    }
+
+    impl<T> fmt::Debug for Codec<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Codec")
+                .field("max_size", &self.max_size)
+                .finish()
+        }
+    }
+
+    impl<'a, T: BitEncode> codec::Encoder<&'a T> for Codec<T> {
+        type Error = Error;
+
+        fn encode(&mut self, message: &'a T, dst: &mut BytesMut) -> Result<()> {
+            let body = Encoder::encode(message);
+
+            dst.reserve(MAX_PREFIX_SIZE + body.len());
+            dst.put_slice(&Encoder::new().size(body.len()).end());
+            dst.put_slice(&body);
+
+            Ok(())
+        }
+    }
+
+    impl<T: BitEncode> codec::Encoder<T> for Codec<T> {
+        type Error = Error;
+
+        #[inline]
+        fn encode(&mut self, message: T, dst: &mut BytesMut) -> Result<()> {
+            codec::Encoder::encode(self, &message, dst)
+        }
+    }
+
+    impl<T: for<'a> BitDecode<'a>> codec::Decoder for Codec<T> {
+        type Item = T;
+        type Error = Error;
+
+        fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>> {
+            let (prefix, len) = match frame_header(src, self.max_size)? {
+                Some(header) => header,
+                None => return Ok(None),
+            };
+
+            if src.len() < prefix + len {
+                src.reserve(prefix + len - src.len());
+
+                return Ok(None);
+            }
+
+            src.advance(prefix);
+
+            let frame = src.split_to(len);
+
+            Decoder::decode(&frame).map(Some)
+        }
+    }
+}
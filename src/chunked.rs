@@ -0,0 +1,290 @@
+//! Splitting large payloads, such as snapshots, into chunks small
+//! enough for transports limiting the size of a message, and putting
+//! them back together on the other end.
+//!
+//! Every chunk carries the hash of the entire payload, which identifies
+//! the transfer, along with its sequence number and a flag marking the
+//! terminal chunk. Chunks of several transfers can be interleaved and
+//! arrive in any order, the `Reassembler` returns the payload once all
+//! of its chunks arrived and its hash matches.
+//!
+//! ```
+//! use bitsparrow::Encoder;
+//! use bitsparrow::chunked::{self, Reassembler};
+//!
+//! let snapshot = Encoder::encode(vec![42u32; 100_000]);
+//! let chunks: Vec<Vec<u8>> = chunked::split(&snapshot, 64 * 1024).collect();
+//!
+//! assert_eq!(chunks.len(), 7);
+//! assert!(chunks.iter().all(|chunk| chunk.len() <= 64 * 1024));
+//!
+//! let mut reassembler = Reassembler::new(1024 * 1024);
+//!
+//! for chunk in chunks[1..].iter().rev() {
+//!     assert_eq!(reassembler.push(chunk).unwrap(), None);
+//! }
+//!
+//! assert_eq!(reassembler.push(&chunks[0]).unwrap(), Some(snapshot));
+//! ```
+
+use std::collections::HashMap;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use seq::Seq;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// Upper bound of the number of bytes a chunk adds to its data.
+pub const CHUNK_OVERHEAD: usize = 8 + 9 + 1 + 9;
+
+/// Hash identifying a payload, 64-bit FNV-1a of its bytes. It's stable
+/// across platforms and versions of this crate, but not cryptographic,
+/// authenticity of payloads has to be verified separately.
+pub fn content_hash(data: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+/// Single chunk of a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk<'a> {
+    /// `content_hash` of the entire payload.
+    pub hash: u64,
+    /// Position of the chunk in the payload, starting at zero.
+    pub seq: Seq,
+    /// Set on the terminal chunk of the payload.
+    pub last: bool,
+    /// Bytes of the payload held by the chunk.
+    pub data: &'a [u8],
+}
+
+/// Split a payload into encoded chunks of at most `max_size` bytes
+/// each. Empty payloads produce a single empty chunk. Panics if
+/// `max_size` isn't larger than `CHUNK_OVERHEAD`.
+pub fn split(payload: &[u8], max_size: usize) -> Chunks<'_> {
+    assert!(max_size > CHUNK_OVERHEAD, "chunk size must be larger than CHUNK_OVERHEAD");
+
+    Chunks {
+        hash: content_hash(payload),
+        rest: payload,
+        seq: Seq(0),
+        data_size: max_size - CHUNK_OVERHEAD,
+        done: false,
+    }
+}
+
+/// Iterator over encoded chunks of a payload, see `split`.
+pub struct Chunks<'a> {
+    hash: u64,
+    rest: &'a [u8],
+    seq: Seq,
+    data_size: usize,
+    done: bool,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.done {
+            return None;
+        }
+
+        let (data, rest) = self.rest.split_at(self.rest.len().min(self.data_size));
+        let chunk = Chunk {
+            hash: self.hash,
+            seq: self.seq,
+            last: rest.is_empty(),
+            data,
+        };
+
+        self.rest = rest;
+        self.seq = self.seq.next();
+        self.done = chunk.last;
+
+        Some(Encoder::encode(chunk))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match self.done {
+            true => 0,
+            false => 1.max(self.rest.len().div_ceil(self.data_size)),
+        };
+
+        (len, Some(len))
+    }
+}
+
+impl<'a> ExactSizeIterator for Chunks<'a> {}
+
+#[derive(Default)]
+struct Transfer {
+    chunks: HashMap<u64, Vec<u8>>,
+    total: Option<u64>,
+}
+
+/// Collects chunks of payloads, see the module documentation.
+pub struct Reassembler {
+    transfers: HashMap<u64, Transfer>,
+    buffered: usize,
+    max_size: usize,
+}
+
+impl Reassembler {
+    /// Create a `Reassembler` buffering at most `max_size` bytes of
+    /// chunks across all incomplete transfers. Every buffered chunk is
+    /// charged `CHUNK_OVERHEAD` bytes on top of its data, which bounds
+    /// the number of pending transfers and chunks as well.
+    #[inline]
+    pub fn new(max_size: usize) -> Reassembler {
+        Reassembler {
+            transfers: HashMap::new(),
+            buffered: 0,
+            max_size,
+        }
+    }
+
+    /// Number of bytes buffered by incomplete transfers, including the
+    /// overhead charged per chunk.
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// Number of incomplete transfers.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.transfers.len()
+    }
+
+    /// Drop all chunks of a transfer, e.g. one that timed out.
+    pub fn discard(&mut self, hash: u64) {
+        if let Some(transfer) = self.transfers.remove(&hash) {
+            self.buffered -= transfer.chunks.values().map(|data| data.len() + CHUNK_OVERHEAD).sum::<usize>();
+        }
+    }
+
+    /// Decode and add an encoded chunk, returning the payload if it was
+    /// the last one missing.
+    #[inline]
+    pub fn push(&mut self, buffer: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.push_chunk(Decoder::decode(buffer)?)
+    }
+
+    /// Add a chunk, returning the payload if it was the last one missing.
+    /// Fails with `LimitExceeded` if buffering the chunk would exceed the
+    /// limit, and with `InvalidData` if the chunk contradicts chunks
+    /// received before it, or the complete payload doesn't match its
+    /// hash. Only an empty payload has an empty chunk, so empty chunks
+    /// other than a sole terminal one are `InvalidData` as well. The
+    /// transfer is discarded on error.
+    pub fn push_chunk(&mut self, chunk: Chunk) -> Result<Option<Vec<u8>>> {
+        let result = self.insert(chunk);
+
+        if result.is_err() {
+            self.discard(chunk.hash);
+        }
+
+        result
+    }
+
+    fn insert(&mut self, chunk: Chunk) -> Result<Option<Vec<u8>>> {
+        let seq = chunk.seq.0;
+
+        if chunk.last && seq == 0 && !self.transfers.contains_key(&chunk.hash) {
+            if content_hash(chunk.data) != chunk.hash {
+                return Err(Error::InvalidData);
+            }
+
+            return Ok(Some(chunk.data.to_vec()));
+        }
+
+        if chunk.data.is_empty() {
+            return Err(Error::InvalidData);
+        }
+
+        if self.buffered + chunk.data.len() + CHUNK_OVERHEAD > self.max_size {
+            return Err(Error::LimitExceeded);
+        }
+
+        let transfer = self.transfers.entry(chunk.hash).or_default();
+
+        if chunk.last {
+            let total = seq.checked_add(1).ok_or(Error::InvalidData)?;
+
+            if transfer.total.is_some_and(|known| known != total) || transfer.chunks.keys().any(|&s| s >= total) {
+                return Err(Error::InvalidData);
+            }
+
+            transfer.total = Some(total);
+        } else if transfer.total.is_some_and(|total| seq >= total - 1) {
+            return Err(Error::InvalidData);
+        }
+
+        if transfer.chunks.contains_key(&seq) {
+            return Ok(None);
+        }
+
+        transfer.chunks.insert(seq, chunk.data.to_vec());
+        self.buffered += chunk.data.len() + CHUNK_OVERHEAD;
+
+        match transfer.total {
+            Some(total) if transfer.chunks.len() as u64 == total => {},
+            _ => return Ok(None),
+        }
+
+        let mut transfer = self.transfers.remove(&chunk.hash).expect("transfer exists");
+        let len = transfer.chunks.values().map(Vec::len).sum();
+        let mut payload = Vec::with_capacity(len);
+
+        self.buffered -= len + transfer.chunks.len() * CHUNK_OVERHEAD;
+
+        for seq in 0..transfer.chunks.len() as u64 {
+            payload.extend_from_slice(&transfer.chunks.remove(&seq).ok_or(Error::InvalidData)?);
+        }
+
+        if content_hash(&payload) != chunk.hash {
+            return Err(Error::InvalidData);
+        }
+
+        Ok(Some(payload))
+    }
+}
+
+/// `Chunk` is stored as the `u64` hash, the sequence number as a
+/// varint, the terminal flag and the data as `bytes`.
+impl<'a> BitEncode for Chunk<'a> {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.uint64(self.hash).write(self.seq).bool(self.last).bytes(self.data);
+    }
+}
+
+impl<'src> BitDecode<'src> for Chunk<'src> {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(Chunk {
+            hash: d.uint64()?,
+            seq: d.read()?,
+            last: d.bool()?,
+            data: d.bytes()?,
+        })
+    }
+}
+
+impl<'a> BitVerify for Chunk<'a> {
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        d.uint64()?;
+        Seq::verify(d)?;
+        d.bool()?;
+        d.skip_bytes()
+    }
+}
@@ -0,0 +1,120 @@
+//! Compression of payloads with zstd and a dictionary trained on
+//! sample messages beforehand. Small messages barely compress on their
+//! own, but a dictionary shared by both peers can hold the field names,
+//! enum tags and other bytes they have in common.
+//!
+//! Compressed payloads are stored in a `Container`, which records the
+//! id of the dictionary used, so it can be picked from `Dictionaries`
+//! when decoding. Requires the `zstd` feature.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Container};
+//! use bitsparrow::compress::{Dictionary, Dictionaries};
+//!
+//! let samples: Vec<Vec<u8>> = (0..1000u32)
+//!     .map(|id| Encoder::encode((id, "status", "online", "region", "eu-west")))
+//!     .collect();
+//!
+//! let dictionary = Dictionary::train(7, &samples, 4096).unwrap();
+//! let buffer = Container::new(1).encode_compressed((42u32, "status", "online"), &dictionary).unwrap();
+//!
+//! let mut dictionaries = Dictionaries::new();
+//! dictionaries.insert(dictionary);
+//!
+//! let (container, value): (Container, (u32, String, String)) =
+//!     Container::decode_compressed(&buffer, &dictionaries).unwrap();
+//!
+//! assert_eq!(container.dictionary(), Some(7));
+//! assert_eq!(value.0, 42);
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+
+use zstd::bulk::{Compressor, Decompressor};
+use zstd::zstd_safe::CParameter;
+
+/// Compression level used by `Dictionary::compress`.
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// Default limit of the size of decompressed payloads, protecting
+/// against payloads that decompress to huge buffers.
+pub const DEFAULT_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+/// zstd dictionary with an id both peers agreed on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    id: u32,
+    data: Vec<u8>,
+}
+
+impl Dictionary {
+    /// Create a `Dictionary` from data trained beforehand, e.g. with
+    /// `Dictionary::train` or the `zstd --train` command.
+    #[inline]
+    pub fn new(id: u32, data: Vec<u8>) -> Dictionary {
+        Dictionary { id, data }
+    }
+
+    /// Train a dictionary of at most `max_size` bytes on sample buffers,
+    /// such as encoded messages typical for a protocol. Fails if there
+    /// are too few samples to train on.
+    pub fn train<T: AsRef<[u8]>>(id: u32, samples: &[T], max_size: usize) -> io::Result<Dictionary> {
+        Ok(Dictionary::new(id, zstd::dict::from_samples(samples, max_size)?))
+    }
+
+    /// Id of the dictionary.
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Trained dictionary data, e.g. to persist it.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Compress a buffer at `DEFAULT_LEVEL`. The id of the dictionary
+    /// is left out of the zstd frame, since it's kept by the envelope.
+    pub fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut compressor = Compressor::with_dictionary(DEFAULT_LEVEL, &self.data)?;
+        compressor.set_parameter(CParameter::DictIdFlag(false))?;
+        compressor.compress(data)
+    }
+
+    /// Decompress a buffer compressed with this dictionary. Fails if it
+    /// would decompress to more than `max_size` bytes.
+    pub fn decompress(&self, data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+        Decompressor::with_dictionary(&self.data)?.decompress(data, max_size)
+    }
+}
+
+/// Set of dictionaries by their id, to decompress payloads recording
+/// which one was used.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionaries {
+    dictionaries: HashMap<u32, Dictionary>,
+}
+
+impl Dictionaries {
+    /// Create an empty set of dictionaries.
+    #[inline]
+    pub fn new() -> Dictionaries {
+        Dictionaries::default()
+    }
+
+    /// Add a dictionary, replacing one with the same id.
+    #[inline]
+    pub fn insert(&mut self, dictionary: Dictionary) -> &mut Self {
+        self.dictionaries.insert(dictionary.id, dictionary);
+
+        self
+    }
+
+    /// Get the dictionary with an id.
+    #[inline]
+    pub fn get(&self, id: u32) -> Option<&Dictionary> {
+        self.dictionaries.get(&id)
+    }
+}
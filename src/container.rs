@@ -0,0 +1,551 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use utils::{crc32, Error, Result};
+use WIRE_VERSION;
+
+#[cfg(feature = "zstd")]
+use compress::{Dictionary, Dictionaries, DEFAULT_MAX_SIZE};
+
+/// Magic bytes every container starts with.
+pub const MAGIC: [u8; 4] = *b"BSPR";
+
+const FLAG_CHECKSUM: u8 = 0b00000001;
+const FLAG_WIRE_VERSION: u8 = 0b00000010;
+const FLAG_COMPRESSED: u8 = 0b00000100;
+const FLAG_INDEX: u8 = 0b00001000;
+
+// u32 length of the index at the end of an indexed payload
+const INDEX_TRAILER_SIZE: usize = 4;
+
+// magic + version + flags
+const HEADER_SIZE: usize = 7;
+
+/// Container wraps an encoded value with a small header, making it
+/// suitable for persisting BitSparrow data on disk. The header consists
+/// of the `MAGIC` bytes, a `u16` format version, a flags byte, and
+/// optionally the `u16` `WIRE_VERSION`, the `u32` id of the dictionary
+/// the payload is compressed with (see the `compress` module) and a
+/// CRC-32 checksum of the stored payload. Instead of a single value,
+/// the payload can also hold named sections followed by an index of
+/// them, see `SectionWriter`.
+///
+/// ```
+/// use bitsparrow::Container;
+///
+/// let buffer = Container::new(3).checksum(true).encode(("foo", 42u32));
+///
+/// let (container, value): (Container, (&str, u32)) = Container::decode(&buffer).unwrap();
+///
+/// assert_eq!(container.version(), 3);
+/// assert_eq!(value, ("foo", 42));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Container {
+    version: u16,
+    flags: u8,
+    wire_version: u16,
+    dictionary: u32,
+}
+
+impl Container {
+    /// Create a new `Container` header with a given format version.
+    #[inline]
+    pub fn new(version: u16) -> Container {
+        Container {
+            version,
+            flags: 0,
+            wire_version: WIRE_VERSION,
+            dictionary: 0,
+        }
+    }
+
+    /// Enable or disable the CRC-32 checksum of the payload.
+    #[inline]
+    pub fn checksum(mut self, enabled: bool) -> Container {
+        if enabled {
+            self.flags |= FLAG_CHECKSUM;
+        } else {
+            self.flags &= !FLAG_CHECKSUM;
+        }
+
+        self
+    }
+
+    /// Enable or disable embedding the `WIRE_VERSION` in the header.
+    /// Decoding rejects containers embedding a newer version with
+    /// `UnsupportedWireVersion`.
+    #[inline]
+    pub fn wire_version(mut self, enabled: bool) -> Container {
+        if enabled {
+            self.flags |= FLAG_WIRE_VERSION;
+        } else {
+            self.flags &= !FLAG_WIRE_VERSION;
+        }
+
+        self
+    }
+
+    /// Wire format version embedded in the header, if any.
+    #[inline]
+    pub fn embedded_wire_version(&self) -> Option<u16> {
+        match self.flags & FLAG_WIRE_VERSION {
+            0 => None,
+            _ => Some(self.wire_version),
+        }
+    }
+
+    /// Format version stored in the header.
+    #[inline]
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Id of the dictionary the payload is compressed with, if it's
+    /// compressed.
+    #[inline]
+    pub fn dictionary(&self) -> Option<u32> {
+        match self.flags & FLAG_COMPRESSED {
+            0 => None,
+            _ => Some(self.dictionary),
+        }
+    }
+
+    /// Returns `true` if the payload is followed by a checksum.
+    #[inline]
+    pub fn has_checksum(&self) -> bool {
+        self.flags & FLAG_CHECKSUM != 0
+    }
+
+    /// Returns `true` if the payload holds sections followed by an index.
+    #[inline]
+    pub fn has_index(&self) -> bool {
+        self.flags & FLAG_INDEX != 0
+    }
+
+    /// Encode a value with a header into a new buffer.
+    pub fn encode<E: BitEncode>(&self, val: E) -> Vec<u8> {
+        let mut container = *self;
+        container.flags &= !(FLAG_COMPRESSED | FLAG_INDEX);
+        container.with_payload(&Encoder::encode(val))
+    }
+
+    /// Start writing a container with named sections, see `SectionWriter`.
+    #[inline]
+    pub fn sections(&self) -> SectionWriter {
+        let mut container = *self;
+        container.flags &= !FLAG_COMPRESSED;
+        container.flags |= FLAG_INDEX;
+
+        SectionWriter {
+            container,
+            payload: Vec::new(),
+            index: Encoder::new(),
+            count: 0,
+        }
+    }
+
+    /// Read the header and the index from a buffer with named sections,
+    /// without decoding any of the sections. Fails with `InvalidData`
+    /// if the container has no index, or `DictionaryRequired` if the
+    /// payload is compressed.
+    pub fn read_index(data: &[u8]) -> Result<(Container, Index)> {
+        let (container, payload) = Container::read_header(data)?;
+
+        if !container.has_index() {
+            return Err(Error::InvalidData);
+        }
+
+        if container.dictionary().is_some() {
+            return Err(Error::DictionaryRequired);
+        }
+
+        let offset = data.len() - payload.len();
+        let index_end = payload.len().checked_sub(INDEX_TRAILER_SIZE).ok_or(Error::ReadingOutOfBounds)?;
+        let index_len = Decoder::new_at(payload, index_end).uint32()? as usize;
+        let index_start = index_end.checked_sub(index_len).ok_or(Error::ReadingOutOfBounds)?;
+
+        let mut d = Decoder::new(&payload[index_start..index_end]);
+        let count = d.size()?;
+        let mut sections = Vec::with_capacity(count.min(index_len));
+
+        for _ in 0..count {
+            let name = d.string()?;
+            let start = d.size()?;
+            let end = start.checked_add(d.size()?).ok_or(Error::ReadingOutOfBounds)?;
+
+            if end > index_start {
+                return Err(Error::ReadingOutOfBounds);
+            }
+
+            sections.push((name.to_owned(), offset + start..offset + end));
+        }
+
+        if !d.end() {
+            return Err(Error::BufferNotEmpty);
+        }
+
+        Ok((container, Index { sections, wire_version: container.wire_version }))
+    }
+
+    fn with_payload(&self, payload: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(HEADER_SIZE + 4 + payload.len());
+
+        buffer.extend_from_slice(&MAGIC);
+        buffer.extend_from_slice(&Encoder::new().uint16(self.version).uint8(self.flags).end());
+
+        if self.embedded_wire_version().is_some() {
+            buffer.extend_from_slice(&Encoder::encode(WIRE_VERSION));
+        }
+
+        if let Some(dictionary) = self.dictionary() {
+            buffer.extend_from_slice(&Encoder::encode(dictionary));
+        }
+
+        if self.has_checksum() {
+            buffer.extend_from_slice(&Encoder::encode(crc32(payload)));
+        }
+
+        buffer.extend_from_slice(payload);
+        buffer
+    }
+
+    /// Read the header from a buffer and decode the value following it.
+    /// The entire buffer has to be consumed by the value. Fails with
+    /// `DictionaryRequired` if the payload is compressed.
+    pub fn decode<'src, D: BitDecode<'src>>(data: &'src [u8]) -> Result<(Container, D)> {
+        let (container, payload) = Container::read_header(data)?;
+
+        if container.dictionary().is_some() {
+            return Err(Error::DictionaryRequired);
+        }
+
+        Ok((container, container.read_payload(payload)?))
+    }
+
+    fn read_header(data: &[u8]) -> Result<(Container, &[u8])> {
+        if data.len() < HEADER_SIZE {
+            return Err(Error::ReadingOutOfBounds);
+        }
+
+        if data[..4] != MAGIC {
+            return Err(Error::InvalidData);
+        }
+
+        let mut d = Decoder::new_at(data, 4);
+        let mut container = Container {
+            version: d.uint16()?,
+            flags: d.uint8()?,
+            wire_version: WIRE_VERSION,
+            dictionary: 0,
+        };
+
+        if container.flags & !(FLAG_CHECKSUM | FLAG_WIRE_VERSION | FLAG_COMPRESSED | FLAG_INDEX) != 0 {
+            return Err(Error::InvalidData);
+        }
+
+        if container.flags & FLAG_WIRE_VERSION != 0 {
+            container.wire_version = d.uint16()?;
+        }
+
+        if container.flags & FLAG_COMPRESSED != 0 {
+            container.dictionary = d.uint32()?;
+        }
+
+        let checksum = if container.has_checksum() { Some(d.uint32()?) } else { None };
+        let payload = d.take_rest();
+
+        if checksum.is_some_and(|checksum| crc32(payload) != checksum) {
+            return Err(Error::InvalidData);
+        }
+
+        Ok((container, payload))
+    }
+
+    fn read_payload<'src, D: BitDecode<'src>>(&self, payload: &'src [u8]) -> Result<D> {
+        let mut d = Decoder::new(payload);
+        d.set_wire_version(self.wire_version)?;
+
+        let value = d.read()?;
+
+        if !d.end() {
+            return Err(Error::BufferNotEmpty);
+        }
+
+        Ok(value)
+    }
+
+    /// Encode a value with a header into a new buffer, compressing the
+    /// payload with a zstd dictionary. The id of the dictionary is
+    /// stored in the header. Requires the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    pub fn encode_compressed<E: BitEncode>(&self, val: E, dictionary: &Dictionary) -> io::Result<Vec<u8>> {
+        let payload = dictionary.compress(&Encoder::encode(val))?;
+        let mut container = *self;
+        container.flags |= FLAG_COMPRESSED;
+        container.dictionary = dictionary.id();
+
+        Ok(container.with_payload(&payload))
+    }
+
+    /// Read the header from a buffer and decode an owned value from the
+    /// payload following it, decompressing it with the dictionary whose
+    /// id is stored in the header if it's compressed. Fails with
+    /// `InvalidData` if there is no dictionary with that id, or the
+    /// payload doesn't decompress within `compress::DEFAULT_MAX_SIZE`.
+    /// Requires the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    pub fn decode_compressed<D>(data: &[u8], dictionaries: &Dictionaries) -> Result<(Container, D)> where
+        D: for<'a> BitDecode<'a>,
+    {
+        let (container, payload) = Container::read_header(data)?;
+
+        let id = match container.dictionary() {
+            Some(id) => id,
+            None => return Ok((container, container.read_payload(payload)?)),
+        };
+
+        let payload = dictionaries
+            .get(id)
+            .ok_or(Error::InvalidData)?
+            .decompress(payload, DEFAULT_MAX_SIZE)
+            .map_err(|_| Error::InvalidData)?;
+
+        Ok((container, container.read_payload(&payload)?))
+    }
+
+    /// Write a value with a header to a writer.
+    pub fn write<W: Write, E: BitEncode>(&self, mut writer: W, val: E) -> io::Result<()> {
+        writer.write_all(&self.encode(val))
+    }
+
+    /// Read a header and an owned value from a reader, consuming it
+    /// to the end.
+    pub fn read<R: Read, D>(mut reader: R) -> io::Result<(Container, D)> where
+        D: for<'a> BitDecode<'a>,
+    {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        Ok(Container::decode(&buffer)?)
+    }
+
+    /// Write a value with a header to a file, creating it if necessary.
+    pub fn write_file<P: AsRef<Path>, E: BitEncode>(&self, path: P, val: E) -> io::Result<()> {
+        self.write(File::create(path)?, val)
+    }
+
+    /// Read a header and an owned value from a file.
+    pub fn read_file<P: AsRef<Path>, D>(path: P) -> io::Result<(Container, D)> where
+        D: for<'a> BitDecode<'a>,
+    {
+        Container::read(File::open(path)?)
+    }
+}
+
+/// Writer of a container holding named sections, each an independently
+/// encoded value, followed by an index mapping the names to the byte
+/// ranges of the sections. Reading the index with `Container::read_index`
+/// gives random access to any section without decoding the ones before
+/// it. The index is encoded as a `size` count of entries, each with the
+/// `string` name and the `size` offset and length of the section in the
+/// payload, followed by its own length as a `u32`.
+///
+/// ```
+/// use bitsparrow::Container;
+///
+/// let buffer = Container::new(1)
+///     .checksum(true)
+///     .sections()
+///     .section("meta", ("snapshot", 7u32))
+///     .section("scores", vec![10u32, 20, 30])
+///     .end();
+///
+/// let (container, index) = Container::read_index(&buffer).unwrap();
+///
+/// assert!(container.has_index());
+/// assert_eq!(index.names().collect::<Vec<_>>(), ["meta", "scores"]);
+///
+/// let scores: Vec<u32> = index.decode(&buffer, "scores").unwrap().unwrap();
+/// assert_eq!(scores, [10, 20, 30]);
+/// ```
+///
+/// Section names should be unique, the index refers to the first
+/// section of a given name. Containers with sections can't be read with
+/// `Container::decode`, which fails with `BufferNotEmpty`.
+#[derive(Debug)]
+pub struct SectionWriter {
+    container: Container,
+    payload: Vec<u8>,
+    index: Encoder,
+    count: usize,
+}
+
+impl SectionWriter {
+    /// Append a section with a given name.
+    pub fn section<E: BitEncode>(&mut self, name: &str, val: E) -> &mut Self {
+        let start = self.payload.len();
+
+        Encoder::with_sink(&mut self.payload).write(val);
+
+        self.index.string(name).size(start).size(self.payload.len() - start);
+        self.count += 1;
+        self
+    }
+
+    /// Write the index and the header, returning the finished container.
+    pub fn end(&mut self) -> Vec<u8> {
+        let entries = self.index.end();
+        let index = Encoder::new().size(self.count).end();
+        let mut payload = ::std::mem::take(&mut self.payload);
+
+        payload.extend_from_slice(&index);
+        payload.extend_from_slice(&entries);
+        payload.extend_from_slice(&Encoder::encode((index.len() + entries.len()) as u32));
+        self.count = 0;
+
+        self.container.with_payload(&payload)
+    }
+}
+
+/// Index of the named sections of a container, read with
+/// `Container::read_index`. Byte ranges are relative to the start of
+/// the whole container buffer, so they can also be passed directly to
+/// `decode_section` and `decode_sections`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    sections: Vec<(String, Range<usize>)>,
+    wire_version: u16,
+}
+
+impl Index {
+    /// Number of sections.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.sections.len()
+    }
+
+    /// Returns `true` if there are no sections.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    /// Byte range of the section with a given name.
+    pub fn get(&self, name: &str) -> Option<Range<usize>> {
+        self.sections
+            .iter()
+            .find(|&(section, _)| section == name)
+            .map(|(_, range)| range.clone())
+    }
+
+    /// Names of the sections, in the order they were written.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sections.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Names and byte ranges of the sections, in the order they were
+    /// written.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Range<usize>)> {
+        self.sections.iter().map(|(name, range)| (name.as_str(), range.clone()))
+    }
+
+    /// Decode the section with a given name from the container buffer
+    /// the index was read from, returning `None` if there is no such
+    /// section. The section is decoded with the wire version embedded
+    /// in the container, if any.
+    pub fn decode<'src, D: BitDecode<'src>>(&self, data: &'src [u8], name: &str) -> Result<Option<D>> {
+        match self.get(name) {
+            Some(range) => decode_range(data, range, self.wire_version).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Decode a value from a section of a buffer, given its byte range.
+/// The entire section has to be consumed by the value. Sections are
+/// decoded independently of each other and of the rest of the buffer,
+/// each with its own `Decoder`, so they can be decoded in parallel,
+/// for example with `std::thread::scope` when they differ in type:
+///
+/// ```
+/// use std::thread;
+/// use bitsparrow::Encoder;
+/// use bitsparrow::container::decode_section;
+///
+/// let names = Encoder::encode(vec!["north", "south"]);
+/// let scores = Encoder::encode(vec![10u32, 20, 30]);
+/// let split = names.len();
+/// let buffer = [&names[..], &scores[..]].concat();
+///
+/// let (names, scores) = thread::scope(|scope| {
+///     let names = scope.spawn(|| decode_section::<Vec<&str>>(&buffer, 0..split));
+///     let scores = decode_section::<Vec<u32>>(&buffer, split..buffer.len());
+///
+///     (names.join().unwrap(), scores)
+/// });
+///
+/// assert_eq!(names.unwrap(), ["north", "south"]);
+/// assert_eq!(scores.unwrap(), [10, 20, 30]);
+/// ```
+///
+/// Fails with `ReadingOutOfBounds` if the range doesn't fit the buffer.
+/// Sections are decoded with the current `WIRE_VERSION`, use
+/// `Index::decode` for containers embedding an older one.
+#[inline]
+pub fn decode_section<'src, D: BitDecode<'src>>(data: &'src [u8], range: Range<usize>) -> Result<D> {
+    decode_range(data, range, WIRE_VERSION)
+}
+
+fn decode_range<'src, D: BitDecode<'src>>(data: &'src [u8], range: Range<usize>, wire_version: u16) -> Result<D> {
+    let section = data.get(range).ok_or(Error::ReadingOutOfBounds)?;
+    let mut d = Decoder::new(section);
+    d.set_wire_version(wire_version)?;
+
+    let value = d.read()?;
+
+    if !d.end() {
+        return Err(Error::BufferNotEmpty);
+    }
+
+    Ok(value)
+}
+
+/// Decode values of the same type from sections of a buffer in parallel
+/// on the rayon thread pool, see `decode_section`. The values are
+/// returned in the order of the ranges. Requires the `rayon` feature.
+///
+/// ```
+/// use bitsparrow::Encoder;
+/// use bitsparrow::container::decode_sections;
+///
+/// let mut buffer = Vec::new();
+/// let mut ranges = Vec::new();
+///
+/// for chunk in 0..8u32 {
+///     let start = buffer.len();
+///     buffer.extend_from_slice(&Encoder::encode((chunk, vec![chunk; 100])));
+///     ranges.push(start..buffer.len());
+/// }
+///
+/// let chunks = decode_sections::<(u32, Vec<u32>)>(&buffer, &ranges).unwrap();
+///
+/// assert_eq!(chunks.len(), 8);
+/// assert_eq!(chunks[5], (5, vec![5; 100]));
+/// ```
+#[cfg(feature = "rayon")]
+pub fn decode_sections<'src, D>(data: &'src [u8], ranges: &[Range<usize>]) -> Result<Vec<D>> where
+    D: BitDecode<'src> + Send,
+{
+    ranges
+        .par_iter()
+        .map(|range| decode_section(data, range.clone()))
+        .collect()
+}
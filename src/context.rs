@@ -0,0 +1,105 @@
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use sink::Sink;
+use source::Source;
+use utils::Result;
+
+/// Types that need external state to be decoded, such as a string
+/// table, an interner or an arena. The context is passed in mutably
+/// to `Decoder::read_with` and has to be passed on to fields that need
+/// it as well.
+///
+/// Every `BitDecode` type implements `BitDecodeWith` for any context,
+/// ignoring it, so fields that don't need the context can be read
+/// either way.
+///
+/// ```
+/// use bitsparrow::{Encoder, Decoder, BitDecodeWith, Source, Result};
+///
+/// struct Symbols(Vec<&'static str>);
+///
+/// struct Event {
+///     name: &'static str,
+///     count: u32,
+/// }
+///
+/// impl<'src> BitDecodeWith<'src, Symbols> for Event {
+///     fn decode_with<S: Source<'src>>(d: &mut Decoder<'src, S>, symbols: &mut Symbols) -> Result<Self> {
+///         let index = d.uint16()? as usize;
+///
+///         Ok(Event {
+///             name: symbols.0[index],
+///             count: d.read_with(symbols)?,
+///         })
+///     }
+/// }
+///
+/// let mut symbols = Symbols(vec!["open", "close"]);
+/// let buffer = Encoder::new().uint16(1).uint32(3).end();
+///
+/// let event: Event = Decoder::new(&buffer).read_with(&mut symbols).unwrap();
+///
+/// assert_eq!(event.name, "close");
+/// assert_eq!(event.count, 3);
+/// ```
+pub trait BitDecodeWith<'src, C: ?Sized>: Sized + 'src {
+    fn decode_with<S: Source<'src>>(d: &mut Decoder<'src, S>, ctx: &mut C) -> Result<Self>;
+}
+
+impl<'src, C: ?Sized, D: BitDecode<'src>> BitDecodeWith<'src, C> for D {
+    #[inline]
+    fn decode_with<S: Source<'src>>(d: &mut Decoder<'src, S>, _: &mut C) -> Result<Self> {
+        D::decode(d)
+    }
+}
+
+/// Types that need external state to be encoded, such as a dictionary
+/// being built or compression state. The context is passed in mutably
+/// to `Encoder::write_with` and has to be passed on to fields that need
+/// it as well.
+///
+/// Every `BitEncode` type implements `BitEncodeWith` for any context,
+/// ignoring it.
+///
+/// ```
+/// use bitsparrow::{Encoder, BitEncodeWith, Sink};
+///
+/// struct Symbols(Vec<&'static str>);
+///
+/// struct Event {
+///     name: &'static str,
+///     count: u32,
+/// }
+///
+/// impl BitEncodeWith<Symbols> for Event {
+///     fn encode_with<S: Sink>(&self, e: &mut Encoder<S>, symbols: &mut Symbols) {
+///         let index = match symbols.0.iter().position(|&name| name == self.name) {
+///             Some(index) => index,
+///             None => {
+///                 symbols.0.push(self.name);
+///                 symbols.0.len() - 1
+///             }
+///         };
+///
+///         e.uint16(index as u16).write_with(&self.count, symbols);
+///     }
+/// }
+///
+/// let mut symbols = Symbols(vec!["open"]);
+/// let buffer = Encoder::new()
+///                 .write_with(&Event { name: "close", count: 3 }, &mut symbols)
+///                 .end();
+///
+/// assert_eq!(buffer, &[0x00, 0x01, 0x00, 0x00, 0x00, 0x03]);
+/// assert_eq!(symbols.0, &["open", "close"]);
+/// ```
+pub trait BitEncodeWith<C: ?Sized> {
+    fn encode_with<S: Sink>(&self, e: &mut Encoder<S>, ctx: &mut C);
+}
+
+impl<C: ?Sized, E: BitEncode + ?Sized> BitEncodeWith<C> for E {
+    #[inline]
+    fn encode_with<S: Sink>(&self, e: &mut Encoder<S>, _: &mut C) {
+        self.encode(e);
+    }
+}
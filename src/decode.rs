@@ -1,146 +1,1166 @@
-use std::{mem, ptr};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::num::{Saturating, Wrapping};
+use std::ops::{Bound, Range, RangeInclusive};
+use std::rc::Rc;
+use std::sync::Arc;
 use std::str::from_utf8;
+use std::time::{Duration, Instant};
 
-use utils::{SIZE_MASKS, Error, Result};
+use utils::{SIZE_MASKS, BoolSlot, Error, Result};
+use {WIRE_VERSION, MAX_SAFE_INTEGER};
+use verify::BitVerify;
+use context::BitDecodeWith;
+use trace::Trace;
+use source::{Source, Slice, Chain, Reader};
+use scratch;
 
-/// Decoder reads from a binary slice buffer (`&[u8]`) and exposes
-/// methods to read BitSparrow types from it in the same order they
-/// were encoded by the `Encoder`.
-pub struct Decoder<'src> {
+/// Decoder reads from a binary slice buffer (`&[u8]`), or any other
+/// `Source` chosen with `Decoder::with_source`, and exposes methods to
+/// read BitSparrow types from it in the same order they were encoded
+/// by the `Encoder`. See the `source` module.
+///
+/// Cloning a `Decoder` creates an independent one at the same position,
+/// which can be used to look ahead or try decoding a different type:
+///
+/// ```
+/// use bitsparrow::{Encoder, Decoder};
+///
+/// let buffer = Encoder::encode((1u16, "foo"));
+/// let mut decoder = Decoder::new(&buffer);
+///
+/// let peeked = decoder.clone().uint16().unwrap();
+///
+/// assert_eq!(peeked, 1);
+/// assert_eq!(decoder.read::<(u16, &str)>().unwrap(), (1, "foo"));
+/// ```
+#[derive(Clone)]
+pub struct Decoder<'src, S = Slice<'src>> {
+    source: S,
+    bools: Option<BoolSlot>,
+    version: u16,
+    wire_version: u16,
+    depth: usize,
+    max_depth: usize,
+    max_elements: usize,
+    allocated: usize,
+    max_allocation: usize,
+    deadline: Option<Instant>,
+    ticks: u32,
+    shrink: bool,
+    scratch: bool,
+    trace: Option<Box<Trace>>,
+    _src: PhantomData<&'src [u8]>,
+}
+
+/// Shows the state of the source, along with the byte `bool`s are
+/// currently read from and how many of its bits have been read.
+impl<'src, S: fmt::Debug> fmt::Debug for Decoder<'src, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bools = self.bools.map(|slot| (slot.end - 1, slot.shift + 1));
+
+        f.debug_struct("Decoder")
+            .field("source", &self.source)
+            .field("bools", &bools)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+/// Default limit of nesting levels, see `Decoder::set_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+// Number of steps between checks of the clock, see `Decoder::set_deadline`
+const DEADLINE_INTERVAL: u32 = 1024;
+
+// Most bytes reserved up front for a collection, so an untrusted `size`
+// prefix can't allocate more than this before any element is read
+const MAX_PREALLOC: usize = 4096;
+
+// Number of elements of `T` to reserve for a collection of `size`
+#[inline]
+fn prealloc<T>(size: usize) -> usize {
+    size.min(MAX_PREALLOC / size_of::<T>().max(1))
+}
+
+pub trait BitDecode<'src>: Sized + 'src {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self>;
+}
+
+// Same as `traced!`, additionally recording the read if the `Decoder`
+// is tracing, see `Decoder::trace`.
+macro_rules! recorded {
+    ($decoder:expr, $ty:expr, $body:expr) => ({
+        let start = $decoder.source.position();
+        let result = traced!($decoder.source.position(), $ty, $body);
+
+        if let Some(ref mut trace) = $decoder.trace {
+            trace.record($ty, start, $decoder.source.position() - start, &result);
+        }
+
+        result
+    })
+}
+
+macro_rules! read_bytes {
+    ($decoder:expr, $t:ident) => ({
+        let mut bytes = [0; ::std::mem::size_of::<$t>()];
+
+        $decoder.source.read_into(&mut bytes).map(|_| $t::from_be_bytes(bytes))
+    })
+}
+
+impl<'src> Decoder<'src> {
+    /// Create a new `Decoder` reading from a `&[u8]` slice buffer.
+    #[inline]
+    pub fn new(data: &[u8]) -> Decoder {
+        Decoder::with_source(Slice::new(data))
+    }
+
+    /// Create a new `Decoder` reading from `offset` bytes into a
+    /// `&[u8]` slice buffer. Positions, including those reported with
+    /// errors, are still counted from the start of the buffer. Reading
+    /// fails if `offset` is past the end of the buffer.
+    #[inline]
+    pub fn new_at(data: &'src [u8], offset: usize) -> Decoder<'src> {
+        Decoder::with_source(Slice::new_at(data, offset))
+    }
+
+    #[inline]
+    pub fn decode<D: BitDecode<'src>>(data: &'src [u8]) -> Result<D> {
+        let mut d = Decoder::new(data);
+        let value = try!(BitDecode::decode(&mut d));
+        if !d.end() {
+            return Err(Error::BufferNotEmpty);
+        }
+        Ok(value)
+    }
+
+    /// Check that the entire buffer contains a valid value of a type,
+    /// without decoding it. See `BitVerify` for details.
+    #[inline]
+    pub fn validate<V: BitVerify + ?Sized>(data: &'src [u8]) -> Result<()> {
+        let mut d = Decoder::new(data);
+        d.verify::<V>()?;
+        if !d.end() {
+            return Err(Error::BufferNotEmpty);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the entire buffer has been read, otherwise
+    /// returns `false`.
+    #[inline]
+    pub fn end(&self) -> bool {
+        self.source.is_empty()
+    }
+
+    /// Start reading from the beginning of the buffer again.
+    #[inline]
+    pub fn rewind(&mut self) -> &mut Self {
+        self.source.rewind();
+        self.reset_bools();
+
+        self
+    }
+
+    /// Continue reading from a position in the buffer, e.g. one
+    /// obtained from `position` earlier. Fails with `ReadingOutOfBounds`
+    /// if it's past the end of the buffer.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().uint16(1).string("foo").end();
+    /// let mut decoder = Decoder::new(&buffer);
+    ///
+    /// assert_eq!(decoder.uint16().unwrap(), 1);
+    ///
+    /// let position = decoder.position();
+    ///
+    /// assert_eq!(decoder.string().unwrap(), "foo");
+    /// assert_eq!(decoder.seek(position).unwrap().string().unwrap(), "foo");
+    /// assert!(decoder.seek(10).is_err());
+    /// ```
+    #[inline]
+    pub fn seek(&mut self, position: usize) -> Result<&mut Self> {
+        self.source.seek(position)?;
+        self.reset_bools();
+
+        Ok(self)
+    }
+
+    /// Read all bytes left in the buffer, e.g. an opaque payload
+    /// trailing the values read so far.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().uint16(9001).append_raw(b"payload").end();
+    /// let mut decoder = Decoder::new(&buffer);
+    ///
+    /// assert_eq!(decoder.uint16().unwrap(), 9001);
+    /// assert_eq!(decoder.take_rest(), b"payload");
+    /// assert_eq!(true, decoder.end());
+    /// ```
+    #[inline]
+    pub fn take_rest(&mut self) -> &'src [u8] {
+        let rest = self.source.remaining();
+
+        // Nothing can be skipped if the decoder started past the end
+        if !rest.is_empty() {
+            self.source.skip(rest.len()).expect("remaining bytes can be skipped");
+        }
+
+        rest
+    }
+
+    /// Read all bytes left in the buffer into a new `Vec<u8>`.
+    #[inline]
+    pub fn take_rest_vec(&mut self) -> Vec<u8> {
+        self.take_rest().to_vec()
+    }
+
+    /// Bytes left in the buffer, without consuming them.
+    #[inline]
+    pub(crate) fn remaining(&self) -> &'src [u8] {
+        self.source.remaining()
+    }
+
+    /// Iterate over values of a type stored one after another until
+    /// the end of the buffer, e.g. records of a log file. Iteration
+    /// stops after the first error, `Messages::offset` tells where the
+    /// value that failed to decode starts.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().string("foo").string("bar").uint8(5).end();
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// let mut messages = decoder.messages::<&str>();
+    ///
+    /// assert_eq!(messages.next().unwrap().unwrap(), "foo");
+    /// assert_eq!(messages.next().unwrap().unwrap(), "bar");
+    /// assert!(messages.next().unwrap().is_err());
+    /// assert_eq!(messages.offset(), 8);
+    /// assert!(messages.next().is_none());
+    /// ```
+    #[inline]
+    pub fn messages<'a, D: BitDecode<'src>>(&'a mut self) -> Messages<'a, 'src, D> {
+        Messages {
+            offset: self.source.position(),
+            decoder: self,
+            recovery: Recovery::Stop,
+            done: false,
+            _value: PhantomData,
+        }
+    }
+}
+
+/// What `Messages` does after a value fails to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Recovery {
+    /// Return the error and end the iteration.
+    #[default]
+    Stop,
+    /// Every value is stored with `Encoder::sized`. Return the error and
+    /// continue with the value following the envelope of the failed one.
+    /// Iteration still ends if an envelope extends past the buffer.
+    SkipToNextFrame,
+    /// Return the error and continue right where decoding stopped.
+    /// Iteration ends if the failed value didn't consume any bytes.
+    YieldError,
+}
+
+/// Iterator over values stored one after another, see `Decoder::messages`.
+pub struct Messages<'a, 'src: 'a, D> {
+    decoder: &'a mut Decoder<'src>,
+    offset: usize,
+    recovery: Recovery,
+    done: bool,
+    _value: PhantomData<D>,
+}
+
+impl<'a, 'src, D> Messages<'a, 'src, D> {
+    /// Choose what happens after a value fails to decode, ending the
+    /// iteration by default.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder, Recovery};
+    ///
+    /// let buffer = Encoder::new().sized("foo").sized(&b"\xFF"[..]).sized("bar").end();
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// let names: Vec<_> = decoder.messages::<&str>()
+    ///                         .recovery(Recovery::SkipToNextFrame)
+    ///                         .filter_map(Result::ok)
+    ///                         .collect();
+    ///
+    /// assert_eq!(names, &["foo", "bar"]);
+    /// ```
+    #[inline]
+    pub fn recovery(mut self, recovery: Recovery) -> Self {
+        self.recovery = recovery;
+
+        self
+    }
+
+    /// Position in the buffer where the last value returned, or the
+    /// value that failed to decode, starts.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a, 'src, D: BitDecode<'src>> Messages<'a, 'src, D> {
+    fn read_framed(&mut self) -> Result<D> {
+        let len = match self.decoder.size() {
+            Ok(len) => len,
+            Err(err) => {
+                self.done = true;
+                return Err(err);
+            }
+        };
+
+        let start = self.decoder.position();
+        let result = self.decoder.read().and_then(|value| {
+            if self.decoder.position() - start > len {
+                return Err(Error::InvalidData);
+            }
+
+            Ok(value)
+        });
+
+        let next = start.checked_add(len).ok_or(Error::ReadingOutOfBounds);
+
+        if let Err(err) = next.and_then(|next| self.decoder.seek(next).map(|_| ())) {
+            self.done = true;
+
+            return result.and(Err(err));
+        }
+
+        result
+    }
+}
+
+impl<'a, 'src, D: BitDecode<'src>> Iterator for Messages<'a, 'src, D> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        if self.done || self.decoder.end() {
+            return None;
+        }
+
+        self.offset = self.decoder.position();
+
+        if self.recovery == Recovery::SkipToNextFrame {
+            return Some(self.read_framed());
+        }
+
+        let result = self.decoder.read();
+
+        self.done = result.is_err() && match self.recovery {
+            Recovery::YieldError => self.decoder.position() == self.offset,
+            _ => true,
+        };
+
+        Some(result)
+    }
+}
+
+/// Iterator over entries of a map, see `Decoder::map_entries`.
+pub struct MapEntries<'a, 'src: 'a, K, V, S: 'a = Slice<'src>> {
+    decoder: &'a mut Decoder<'src, S>,
     index: usize,
-    data: &'src [u8],
-    ptr: *const u8,
-    bool_index: usize,
-    bool_shift: u8,
+    size: usize,
+    _entry: PhantomData<(K, V)>,
 }
 
-pub trait BitDecode<'src>: Sized + 'src {
-    fn decode(&mut Decoder<'src>) -> Result<Self>;
-}
+impl<'a, 'src, K, V, S> MapEntries<'a, 'src, K, V, S> {
+    /// Number of entries left to read.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size - self.index
+    }
+
+    /// Returns `true` if all entries have been read.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, 'src, K, V, S> Iterator for MapEntries<'a, 'src, K, V, S> where
+    K: BitDecode<'src>,
+    V: BitDecode<'src>,
+    S: Source<'src>,
+{
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Result<(K, V)>> {
+        if self.index == self.size {
+            return None;
+        }
+
+        let index = self.index;
+        let result = self.decoder.nested(<(K, V)>::decode);
+
+        if result.is_ok() {
+            self.index += 1;
+        } else {
+            self.index = self.size;
+        }
+
+        Some(result.map_err(|err| err.at_index(index)))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.len()))
+    }
+}
+
+impl<'src> Decoder<'src, Chain<'src>> {
+    /// Returns `true` if all chunks have been read, otherwise
+    /// returns `false`.
+    #[inline]
+    pub fn end(&self) -> bool {
+        self.source.is_empty()
+    }
+
+    /// Read all bytes left in the chunks into a new `Vec<u8>`.
+    #[inline]
+    pub fn take_rest_vec(&mut self) -> Vec<u8> {
+        let len = self.source.remaining();
+
+        self.source.read_vec(len).expect("remaining bytes can be read")
+    }
+}
+
+impl<'src, R: ::std::io::Read> Decoder<'src, Reader<R>> {
+    /// Returns `true` if the reader has no more data, otherwise
+    /// returns `false`. See `Reader::is_empty`.
+    #[inline]
+    pub fn end(&mut self) -> bool {
+        self.source.is_empty()
+    }
+
+    /// Read everything up to the end of the reader into a new `Vec<u8>`.
+    #[inline]
+    pub fn take_rest_vec(&mut self) -> Result<Vec<u8>> {
+        self.source.read_to_end()
+    }
+}
+
+impl<'src, S: Source<'src>> Decoder<'src, S> {
+    /// Create a new `Decoder` reading from a `Source`.
+    #[inline]
+    pub fn with_source(source: S) -> Self {
+        Decoder {
+            source,
+            bools: None,
+            version: u16::MAX,
+            wire_version: WIRE_VERSION,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_elements: usize::MAX,
+            allocated: 0,
+            max_allocation: usize::MAX,
+            deadline: None,
+            ticks: 0,
+            shrink: false,
+            scratch: false,
+            trace: None,
+            _src: PhantomData,
+        }
+    }
+
+    /// Obtain the source, discarding the state of the `Decoder`.
+    #[inline]
+    pub fn into_source(self) -> S {
+        self.source
+    }
+
+    /// Number of bytes read from the source so far.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.source.position()
+    }
+
+    /// Skip over a value of a type, checking that it is valid without
+    /// decoding it. See `BitVerify` for details.
+    #[inline]
+    pub fn verify<V: BitVerify + ?Sized>(&mut self) -> Result<()> {
+        V::verify(self)
+    }
+
+    #[inline]
+    pub fn read<D: BitDecode<'src>>(&mut self) -> Result<D> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::trace_span!(
+            "read",
+            ty = ::std::any::type_name::<D>(),
+            offset = self.source.position()
+        ).entered();
+
+        let result = BitDecode::decode(self);
+
+        #[cfg(feature = "tracing")]
+        {
+            if let Err(ref err) = result {
+                ::tracing::trace!(offset = self.source.position(), error = %err, "decoding failed");
+            }
+        }
+
+        result
+    }
+
+    /// Read a value stored with `Encoder::sized`, skipping any bytes
+    /// left in its envelope. Fails with `InvalidData` if the value
+    /// extends past the envelope.
+    pub fn read_sized<D: BitDecode<'src>>(&mut self) -> Result<D> {
+        let len = self.size()?;
+        let start = self.source.position();
+        let value = self.read()?;
+        let read = self.source.position() - start;
+
+        if read > len {
+            return Err(Error::InvalidData);
+        }
+
+        self.source.skip(len - read)?;
+
+        Ok(value)
+    }
+
+    /// Skip `len` bytes reserved with `Encoder::reserved`, without
+    /// checking their contents, so peers that already use them can
+    /// still be read.
+    #[inline]
+    pub fn skip_reserved(&mut self, len: usize) -> Result<&mut Self> {
+        self.source.skip(len)?;
+
+        Ok(self)
+    }
+
+    /// Copy the next `buf.len()` bytes, stored without a `size` prefix
+    /// (e.g. with `Encoder::append_raw`), into a buffer owned by the
+    /// caller. Fails with `ReadingOutOfBounds` if fewer bytes are left.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().append_raw(&[0xAB; 32]).uint8(1).end();
+    /// let mut decoder = Decoder::new(&buffer);
+    /// let mut hash = [0; 32];
+    ///
+    /// decoder.read_exact_into(&mut hash).unwrap();
+    ///
+    /// assert_eq!(hash, [0xAB; 32]);
+    /// assert!(decoder.read_exact_into(&mut hash).is_err());
+    /// ```
+    #[inline]
+    pub fn read_exact_into(&mut self, buf: &mut [u8]) -> Result<()> {
+        let read = recorded!(self, "raw", self.source.read_into(buf).map(|_| &*buf));
+
+        read.map(|_| ())
+    }
+
+    /// Skip padding stored with `Encoder::align`, until the position
+    /// is a multiple of `n`. Panics if `n` is zero.
+    #[inline]
+    pub fn align(&mut self, n: usize) -> Result<&mut Self> {
+        assert!(n != 0, "alignment must not be zero");
+
+        match self.source.position() % n {
+            0   => Ok(self),
+            rem => self.skip_reserved(n - rem),
+        }
+    }
+
+    /// Read a value along with the range of bytes it occupies in the
+    /// source. A `bool` stacked on the byte of a previous one occupies
+    /// no new bytes, its range is empty.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().uint16(9001).string("foo").end();
+    /// let mut decoder = Decoder::new(&buffer);
+    ///
+    /// assert_eq!(decoder.read_spanned::<u16>().unwrap(), (9001, 0..2));
+    /// assert_eq!(decoder.read_spanned::<&str>().unwrap(), ("foo", 2..6));
+    /// ```
+    #[inline]
+    pub fn read_spanned<D: BitDecode<'src>>(&mut self) -> Result<(D, Range<usize>)> {
+        let start = self.source.position();
+        let value = self.read()?;
+
+        Ok((value, start..self.source.position()))
+    }
+
+    /// Read a named field of a composite type. Errors are annotated
+    /// with the path to the field, see `Error::at_field`.
+    ///
+    /// ```
+    /// use bitsparrow::{Decoder, BitDecode, Source, Result};
+    ///
+    /// struct Foo {
+    ///     bar: Vec<Bar>,
+    /// }
+    ///
+    /// struct Bar {
+    ///     baz: u16,
+    /// }
+    ///
+    /// impl<'src> BitDecode<'src> for Foo {
+    ///     fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+    ///         Ok(Foo { bar: d.read_field("bar")? })
+    ///     }
+    /// }
+    ///
+    /// impl<'src> BitDecode<'src> for Bar {
+    ///     fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+    ///         Ok(Bar { baz: d.read_field("baz")? })
+    ///     }
+    /// }
+    ///
+    /// let buffer = &[0x02, 0x00, 0x01, 0x00];
+    /// let err = Decoder::new(buffer).read::<Foo>().map_err(|e| e.in_type("Foo")).err().unwrap();
+    ///
+    /// assert_eq!(err.path().unwrap(), "Foo.bar[1].baz");
+    /// ```
+    #[inline]
+    pub fn read_field<D: BitDecode<'src>>(&mut self, name: &str) -> Result<D> {
+        self.read().map_err(|err| err.at_field(name))
+    }
+
+    /// Read a value of a type that needs a context to be decoded.
+    /// See `BitDecodeWith` for details.
+    #[inline]
+    pub fn read_with<D, C>(&mut self, ctx: &mut C) -> Result<D> where
+        D: BitDecodeWith<'src, C>,
+        C: ?Sized,
+    {
+        D::decode_with(self, ctx)
+    }
+
+    /// Read a named field of a composite type with a context, errors
+    /// are annotated the same way as by `read_field`.
+    #[inline]
+    pub fn read_field_with<D, C>(&mut self, name: &str, ctx: &mut C) -> Result<D> where
+        D: BitDecodeWith<'src, C>,
+        C: ?Sized,
+    {
+        self.read_with(ctx).map_err(|err| err.at_field(name))
+    }
+
+    /// Start recording every primitive read, see the `trace` module.
+    /// Reads recorded so far are kept.
+    #[inline]
+    pub fn trace(&mut self) -> &mut Self {
+        if self.trace.is_none() {
+            self.trace = Some(Box::default());
+        }
+
+        self
+    }
+
+    /// Obtain the reads recorded so far and stop recording. Returns
+    /// `None` unless `trace` was called.
+    #[inline]
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take().map(|trace| *trace)
+    }
+
+    /// Set the protocol version the buffer was written with. By default
+    /// the `Decoder` assumes the latest version (`u16::MAX`).
+    #[inline]
+    pub fn set_version(&mut self, version: u16) -> &mut Self {
+        self.version = version;
+
+        self
+    }
+
+    /// Set the version of the wire format the buffer was written with,
+    /// as read from an envelope embedding it. By default the `Decoder`
+    /// assumes `WIRE_VERSION`. Fails with `UnsupportedWireVersion` if
+    /// the version is newer than that.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// // Version 1 stored byte arrays like slices, with a `size` prefix
+    /// let buffer = Encoder::new().bytes(&[1, 2, 3]).end();
+    /// let mut decoder = Decoder::new(&buffer);
+    ///
+    /// decoder.set_wire_version(1).unwrap();
+    ///
+    /// assert_eq!(decoder.read::<[u8; 3]>().unwrap(), [1, 2, 3]);
+    /// ```
+    #[inline]
+    pub fn set_wire_version(&mut self, version: u16) -> Result<&mut Self> {
+        if version > WIRE_VERSION {
+            return Err(Error::UnsupportedWireVersion { version });
+        }
+
+        self.wire_version = version;
+
+        Ok(self)
+    }
+
+    /// Version of the wire format the buffer was written with, letting
+    /// `BitDecode` implementations adapt to buffers of older releases.
+    #[inline]
+    pub fn wire_version(&self) -> u16 {
+        self.wire_version
+    }
+
+    /// Set how many levels deep values can be nested in each other
+    /// before decoding fails with `Error::LimitExceeded`, guarding
+    /// recursive types against overflowing the stack on malicious
+    /// buffers. Every collection and pointer (`Vec`, `HashMap`, `Box`,
+    /// ...) counts as a level, as does every call to `nested`. Defaults
+    /// to `DEFAULT_MAX_DEPTH`.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder, Error};
+    ///
+    /// let buffer = Encoder::encode(vec![vec![vec![1u16]]]);
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// decoder.set_max_depth(2);
+    ///
+    /// let err = decoder.read::<Vec<Vec<Vec<u16>>>>().unwrap_err();
+    ///
+    /// assert_eq!(err.path().unwrap(), "[0][0]");
+    ///
+    /// match *err.inner() {
+    ///     Error::LimitExceeded => {},
+    ///     ref other => panic!("unexpected error: {:?}", other),
+    /// }
+    /// ```
+    #[inline]
+    pub fn set_max_depth(&mut self, depth: usize) -> &mut Self {
+        self.max_depth = depth;
+
+        self
+    }
+
+    /// Set the largest number of elements a single collection (`Vec`,
+    /// `HashMap`, ...) can hold before decoding fails with
+    /// `Error::LimitExceeded`, checked before any of them is read.
+    /// Unlimited by default.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder, Error};
+    ///
+    /// let buffer = Encoder::encode(&[1u16, 2, 3]);
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// decoder.set_max_elements(2);
+    ///
+    /// match decoder.read::<Vec<u16>>() {
+    ///     Err(Error::LimitExceeded) => {},
+    ///     other => panic!("unexpected result: {:?}", other),
+    /// }
+    /// ```
+    #[inline]
+    pub fn set_max_elements(&mut self, elements: usize) -> &mut Self {
+        self.max_elements = elements;
+
+        self
+    }
+
+    /// Set the largest number of bytes owned values can allocate in total
+    /// before decoding fails with `Error::LimitExceeded`, bounding the
+    /// memory taken by many small fields that are each within the other
+    /// limits. Counted are the buffers of `String`s and `Vec<u8>`s, the
+    /// elements of collections (`Vec`, `HashMap`, ...) and the values of
+    /// pointers (`Box`, `Rc`, `Arc`), checked before they're allocated.
+    /// Unlimited by default.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder, Error};
+    ///
+    /// let buffer = Encoder::encode(vec!["foo"; 100]);
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// decoder.set_max_allocation(1024);
+    ///
+    /// match decoder.read::<Vec<String>>() {
+    ///     Err(Error::LimitExceeded) => {},
+    ///     other => panic!("unexpected result: {:?}", other),
+    /// }
+    /// ```
+    #[inline]
+    pub fn set_max_allocation(&mut self, bytes: usize) -> &mut Self {
+        self.max_allocation = bytes;
+
+        self
+    }
+
+    /// Number of bytes allocated for owned values decoded so far, as
+    /// counted for the limit set with `set_max_allocation`.
+    #[inline]
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// Count `bytes` about to be allocated for an owned value, failing
+    /// with `Error::LimitExceeded` if they exceed the limit set with
+    /// `set_max_allocation`.
+    #[inline]
+    pub(crate) fn allocate(&mut self, bytes: usize) -> Result<()> {
+        match self.allocated.checked_add(bytes) {
+            Some(allocated) if allocated <= self.max_allocation => {
+                self.allocated = allocated;
+
+                Ok(())
+            },
+            _ => Err(Error::LimitExceeded),
+        }
+    }
+
+    /// Set a point in time after which decoding fails with
+    /// `Error::BudgetExceeded`, so that buffers which are slow to decode
+    /// while being within all other limits, such as huge collections of
+    /// empty values, can't stall the thread. The clock is checked every
+    /// `1024` collection elements or nesting levels, simple values are
+    /// never slow enough to need it. No deadline by default.
+    ///
+    /// The clock is not available on `wasm32-unknown-unknown`, where
+    /// decoding with a deadline panics.
+    ///
+    /// ```
+    /// use std::time::Instant;
+    /// use bitsparrow::{Encoder, Decoder, Error};
+    ///
+    /// // A billion empty elements in a handful of bytes
+    /// let buffer = Encoder::new().size(1_000_000_000).end();
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// decoder.set_deadline(Some(Instant::now()));
+    ///
+    /// match decoder.read::<Vec<()>>() {
+    ///     Err(Error::BudgetExceeded) => {},
+    ///     other => panic!("unexpected result: {:?}", other),
+    /// }
+    /// ```
+    #[inline]
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) -> &mut Self {
+        self.deadline = deadline;
+        self.ticks = 0;
+
+        self
+    }
 
-macro_rules! read_bytes {
-    ($decoder:expr, $t:ident) => ({
-        let size = mem::size_of::<$t>();
-        let end = $decoder.index + size;
-        if end > $decoder.data.len() {
-            return Err(Error::ReadingOutOfBounds);
+    /// Set a deadline of `budget` from now, see `set_deadline`.
+    #[inline]
+    pub fn set_time_budget(&mut self, budget: Duration) -> &mut Self {
+        self.set_deadline(Instant::now().checked_add(budget))
+    }
+
+    /// Count a step of decoding, failing with `Error::BudgetExceeded`
+    /// every `DEADLINE_INTERVAL` steps if the deadline has passed.
+    #[inline]
+    pub(crate) fn tick(&mut self) -> Result<()> {
+        if let Some(deadline) = self.deadline {
+            let ticks = self.ticks;
+
+            self.ticks = ticks.wrapping_add(1);
+
+            if ticks.is_multiple_of(DEADLINE_INTERVAL) && Instant::now() >= deadline {
+                return Err(Error::BudgetExceeded);
+            }
         }
 
-        unsafe {
-            let mut value: $t = mem::uninitialized();
-            let ptr = &mut value as *mut $t as *mut u8;
+        Ok(())
+    }
 
-            ptr::copy_nonoverlapping(
-                $decoder.data.as_ptr().offset($decoder.index as isize),
-                ptr,
-                size
-            );
+    /// Shrink the capacity of owned collections (`Vec`, `String`,
+    /// `HashMap`, ...) to fit their contents after decoding them, at
+    /// the cost of a reallocation, reducing memory used by values kept
+    /// around for long. Boxed slices (`Box<[T]>`, `Box<str>`) always
+    /// fit their contents. Disabled by default.
+    #[inline]
+    pub fn set_shrink_to_fit(&mut self, shrink: bool) -> &mut Self {
+        self.shrink = shrink;
 
-            $decoder.index = end;
+        self
+    }
 
-            Ok($t::from_be(value))
-        }
-    })
-}
+    /// Decode `String`s and `Vec<u8>`s into buffers taken from the pool
+    /// of the current thread instead of allocating new ones, see the
+    /// `scratch` module. Data that can be borrowed from the source is
+    /// checked to be valid UTF-8 before it's copied. Disabled by default.
+    #[inline]
+    pub fn set_scratch_buffers(&mut self, scratch: bool) -> &mut Self {
+        self.scratch = scratch;
 
+        self
+    }
 
-impl<'src> Decoder<'src> {
-    /// Create a new `Decoder` reading from a `&[u8]` slice buffer.
+    /// Run `f` one nesting level deeper, failing with `Error::LimitExceeded`
+    /// instead if that would exceed the limit set with `set_max_depth`.
+    /// `BitDecode` implementations of recursive types that don't recurse
+    /// through a collection or pointer should read themselves with it.
     #[inline]
-    pub fn new(data: &[u8]) -> Decoder {
-        Decoder {
-            index: 0,
-            data: data,
-            ptr: data.as_ptr(),
-            bool_index: ::std::usize::MAX,
-            bool_shift: 0,
+    pub fn nested<T, F>(&mut self, f: F) -> Result<T> where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if self.depth >= self.max_depth {
+            return Err(Error::LimitExceeded);
         }
+
+        self.tick()?;
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+
+        result
     }
 
+    /// Protocol version the buffer was written with.
     #[inline]
-    pub fn decode<D: BitDecode<'src>>(data: &'src [u8]) -> Result<D> {
-        let mut d = Decoder::new(data);
-        let value = try!(BitDecode::decode(&mut d));
-        if !d.end() {
-            return Err(Error::BufferNotEmpty);
-        }
-        Ok(value)
+    pub fn version(&self) -> u16 {
+        self.version
     }
 
+    /// Read a field that was introduced in protocol version `since`.
+    /// If the buffer was written with an older version, nothing is read
+    /// and the default value is returned instead.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// // Written by a release that didn't have the second field yet
+    /// let buffer = Encoder::new().uint16(9001).end();
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// decoder.set_version(1);
+    ///
+    /// assert_eq!(9001u16, decoder.read().unwrap());
+    /// assert_eq!(0u32, decoder.read_since(2).unwrap());
+    /// assert_eq!(true, decoder.end());
+    /// ```
     #[inline]
-    pub fn read<D: BitDecode<'src>>(&mut self) -> Result<D> {
+    pub fn read_since<D: BitDecode<'src> + Default>(&mut self, since: u16) -> Result<D> {
+        if self.version < since {
+            return Ok(D::default());
+        }
+
         BitDecode::decode(self)
     }
 
+    /// Read a sequence of values, as encoded from a slice or a `Vec`,
+    /// appending them to an existing `Vec` instead of allocating a new
+    /// one. Returns the number of values read. On error, values read
+    /// before it are left in the `Vec`, and the index of the value that
+    /// failed is added to the path of the error.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().write(&[1u16, 2]).write(&[3u16]).end();
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// let mut values: Vec<u16> = Vec::with_capacity(16);
+    ///
+    /// assert_eq!(2, decoder.read_into(&mut values).unwrap());
+    /// assert_eq!(1, decoder.read_into(&mut values).unwrap());
+    /// assert_eq!(values, &[1, 2, 3]);
+    /// ```
+    pub fn read_into<D: BitDecode<'src>>(&mut self, vec: &mut Vec<D>) -> Result<usize> {
+        let size = self.collection_size()?;
+
+        self.allocate(size.saturating_mul(size_of::<D>()))?;
+        vec.reserve(prealloc::<D>(size));
+
+        self.nested(|d| {
+            for index in 0..size {
+                d.tick()?;
+
+                match D::decode(d) {
+                    Ok(item) => vec.push(item),
+                    Err(err) => return Err(err.at_index(index)),
+                }
+            }
+
+            Ok(size)
+        })
+    }
+
+    /// Read exactly `n` values of a type stored one after another,
+    /// without a `size` prefix, for formats where the count is stored
+    /// elsewhere.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().uint8(3).uint16(1).uint16(2).uint16(3).end();
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// let count = decoder.uint8().unwrap() as usize;
+    ///
+    /// assert_eq!(decoder.read_n::<u16>(count).unwrap(), &[1, 2, 3]);
+    /// assert_eq!(true, decoder.end());
+    /// ```
+    pub fn read_n<D: BitDecode<'src>>(&mut self, n: usize) -> Result<Vec<D>> {
+        self.allocate(n.saturating_mul(size_of::<D>()))?;
+
+        let mut vec = Vec::with_capacity(prealloc::<D>(n));
+
+        for index in 0..n {
+            self.tick()?;
+
+            match D::decode(self) {
+                Ok(item) => vec.push(item),
+                Err(err) => return Err(err.at_index(index)),
+            }
+        }
+
+        Ok(vec)
+    }
+
+    /// Read a sequence of key and value pairs, inserting them into an
+    /// existing `HashMap`. Returns the number of pairs read. On error,
+    /// pairs read before it are left in the `HashMap`.
+    pub fn read_map_into<K, V, H>(&mut self, map: &mut HashMap<K, V, H>) -> Result<usize> where
+        K: BitDecode<'src> + Eq + Hash,
+        V: BitDecode<'src>,
+        H: BuildHasher,
+    {
+        let size = self.collection_size()?;
+
+        self.allocate(size.saturating_mul(size_of::<(K, V)>()))?;
+        map.reserve(prealloc::<(K, V)>(size));
+
+        self.nested(|d| {
+            for index in 0..size {
+                d.tick()?;
+
+                match <(K, V)>::decode(d) {
+                    Ok((key, value)) => { map.insert(key, value); },
+                    Err(err) => return Err(err.at_index(index)),
+                }
+            }
+
+            Ok(size)
+        })
+    }
+
+    /// Iterate over key and value pairs of a map one by one, e.g. one
+    /// written with `Encoder::map_header`, without building a `HashMap`.
+    /// Fails if the number of entries can't be read, iteration stops
+    /// after the first entry that fails to decode.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().map_header(2).uint16(1).string("foo").uint16(2).string("bar").end();
+    /// let mut decoder = Decoder::new(&buffer);
+    ///
+    /// let mut entries = decoder.map_entries::<u16, &str>().unwrap();
+    ///
+    /// assert_eq!(entries.len(), 2);
+    /// assert_eq!(entries.next().unwrap().unwrap(), (1, "foo"));
+    /// assert_eq!(entries.next().unwrap().unwrap(), (2, "bar"));
+    /// assert!(entries.next().is_none());
+    /// ```
+    pub fn map_entries<'a, K, V>(&'a mut self) -> Result<MapEntries<'a, 'src, K, V, S>> where
+        K: BitDecode<'src>,
+        V: BitDecode<'src>,
+    {
+        let size = self.collection_size()?;
+
+        Ok(MapEntries {
+            decoder: self,
+            index: 0,
+            size,
+            _entry: PhantomData,
+        })
+    }
+
     /// Read a `u8` from the buffer and progress the internal index.
     #[inline]
     pub fn uint8(&mut self) -> Result<u8> {
-        if self.index >= self.data.len() {
-            return Err(Error::ReadingOutOfBounds);
-        }
-        let uint8 = unsafe { *self.ptr.offset(self.index as isize) };
-        self.index += 1;
-        return Ok(uint8);
+        recorded!(self, "u8", self.read_u8())
+    }
+
+    #[inline(always)]
+    fn read_u8(&mut self) -> Result<u8> {
+        self.source.read_byte()
     }
 
     /// Read a `u16` from the buffer and progress the internal index.
     #[inline]
     pub fn uint16(&mut self) -> Result<u16> {
-        read_bytes!(self, u16)
+        recorded!(self, "u16", read_bytes!(self, u16))
     }
 
     /// Read a `u32` from the buffer and progress the internal index.
     #[inline]
     pub fn uint32(&mut self) -> Result<u32> {
-        read_bytes!(self, u32)
+        recorded!(self, "u32", read_bytes!(self, u32))
     }
 
     /// Read a `u64` from the buffer and progress the internal index.
     #[inline]
     pub fn uint64(&mut self) -> Result<u64> {
-        read_bytes!(self, u64)
+        recorded!(self, "u64", read_bytes!(self, u64))
+    }
+
+    /// Read an unsigned integer written with `Encoder::js_safe_uint`,
+    /// failing with `LimitExceeded` if it's larger than `MAX_SAFE_INTEGER`.
+    #[inline]
+    pub fn js_safe_uint(&mut self) -> Result<u64> {
+        recorded!(self, "u64", self.read_varint().and_then(|val| match val {
+            0..=MAX_SAFE_INTEGER => Ok(val),
+            _ => Err(Error::LimitExceeded),
+        }))
     }
 
     /// Read an `i8` from the buffer and progress the internal index.
     #[inline]
     pub fn int8(&mut self) -> Result<i8> {
-        let uint8 = try!(self.uint8());
-
-        Ok(uint8 as i8)
+        recorded!(self, "i8", self.read_u8().map(|uint8| uint8 as i8))
     }
 
     /// Read an `i16` from the buffer and progress the internal index.
     #[inline]
     pub fn int16(&mut self) -> Result<i16> {
-        read_bytes!(self, i16)
+        recorded!(self, "i16", read_bytes!(self, i16))
     }
 
     /// Read an `i32` from the buffer and progress the internal index.
     #[inline]
     pub fn int32(&mut self) -> Result<i32> {
-        read_bytes!(self, i32)
+        recorded!(self, "i32", read_bytes!(self, i32))
     }
 
     /// Read an `i64` from the buffer and progress the internal index.
     #[inline]
     pub fn int64(&mut self) -> Result<i64> {
-        read_bytes!(self, i64)
+        recorded!(self, "i64", read_bytes!(self, i64))
     }
 
     /// Read a `float32` from the buffer and progress the internal index.
     #[inline]
     pub fn float32(&mut self) -> Result<f32> {
-        let uint32 = try!(self.uint32());
-
-        Ok(unsafe { mem::transmute(uint32) })
+        recorded!(self, "f32", read_bytes!(self, u32).map(f32::from_bits))
     }
 
     /// Read a `float64` from the buffer and progress the internal index.
     #[inline]
     pub fn float64(&mut self) -> Result<f64> {
-        let uint64 = try!(self.uint64());
-
-        Ok(unsafe { mem::transmute(uint64) })
+        recorded!(self, "f64", read_bytes!(self, u64).map(f64::from_bits))
     }
 
     /// Read a `bool` from the buffer and progress the internal index. If
@@ -171,18 +1191,50 @@ impl<'src> Decoder<'src> {
     /// ```
     #[inline]
     pub fn bool(&mut self) -> Result<bool> {
-        if self.bool_index == self.index && self.bool_shift < 7 {
-            self.bool_shift += 1;
-            let bits = self.data[self.index - 1];
-            let bool_bit = 1 << self.bool_shift;
-            return Ok(bits & bool_bit == bool_bit);
+        recorded!(self, "bool", self.read_bool())
+    }
+
+    /// Read `count` bits stored with `Encoder::bits`, stacked on the same
+    /// byte as preceding `bool`s and bits if there's room for all of them.
+    /// Panics if `count` is not between 1 and 8.
+    ///
+    /// ```
+    /// use bitsparrow::Decoder;
+    ///
+    /// let mut decoder = Decoder::new(&[0b00111101, 0b00001111]);
+    ///
+    /// assert_eq!(decoder.bits(3).unwrap(), 0b101);
+    /// assert_eq!(decoder.bool().unwrap(), true);
+    /// assert_eq!(decoder.bits(2).unwrap(), 0b11);
+    /// assert_eq!(decoder.bits(4).unwrap(), 0b1111);
+    /// ```
+    #[inline]
+    pub fn bits(&mut self, count: u8) -> Result<u8> {
+        assert!((1..=8).contains(&count), "bit count must be between 1 and 8");
+
+        recorded!(self, "bits", self.read_bits(count))
+    }
+
+    #[inline(always)]
+    fn read_bits(&mut self, count: u8) -> Result<u8> {
+        let mask = 0xFF >> (8 - count);
+        let position = self.source.position();
+
+        if let Some(ref mut slot) = self.bools {
+            if slot.fits(position, count) {
+                return Ok((slot.bits >> slot.advance(count)) & mask);
+            }
         }
 
-        let bits = try!(self.uint8());
-        self.bool_index = self.index;
-        self.bool_shift = 0;
+        let bits = self.read_u8()?;
+        self.bools = Some(BoolSlot::new(self.source.position(), bits, count));
+
+        Ok(bits & mask)
+    }
 
-        Ok(bits & 1 == 1)
+    #[inline(always)]
+    fn read_bool(&mut self) -> Result<bool> {
+        self.read_bits(1).map(|bit| bit == 1)
     }
 
     /// Read a `usize` from the buffer and progress the index. Detailed
@@ -190,22 +1242,79 @@ impl<'src> Decoder<'src> {
     /// [the homepage](http://bitsparrow.io).
     #[inline(always)]
     pub fn size(&mut self) -> Result<usize> {
-        let high = try!(self.uint8());
+        recorded!(self, "size", self.read_size())
+    }
+
+    /// Read the number of elements of a collection, checking it against
+    /// the limit set with `set_max_elements`.
+    #[inline]
+    pub(crate) fn collection_size(&mut self) -> Result<usize> {
+        let size = self.size()?;
+
+        if size > self.max_elements {
+            return Err(Error::LimitExceeded);
+        }
+
+        Ok(size)
+    }
+
+    /// Read the `size` prefix byte arrays had before `WIRE_VERSION` 2,
+    /// if reading an older wire version, checking that it's `len`.
+    #[inline]
+    pub(crate) fn legacy_array_size(&mut self, len: usize) -> Result<()> {
+        if self.wire_version < 2 && self.size()? != len {
+            return Err(Error::InvalidData);
+        }
+
+        Ok(())
+    }
+
+    /// Forget the byte `bool`s were last read from, after moving
+    /// to another position.
+    #[inline]
+    fn reset_bools(&mut self) {
+        self.bools = None;
+    }
+
+    #[inline(always)]
+    fn read_size(&mut self) -> Result<usize> {
+        let size = self.read_varint()?;
+
+        // JS peers can't represent larger sizes
+        #[cfg(feature = "wasm-opt")]
+        {
+            if size > MAX_SAFE_INTEGER {
+                return Err(Error::LimitExceeded);
+            }
+        }
+
+        // Don't truncate sizes larger than `usize` on 32-bit targets
+        if size > usize::MAX as u64 {
+            return Err(Error::LimitExceeded);
+        }
+
+        Ok(size as usize)
+    }
+
+    /// Read a `u64` stored in the same variable length format as `size`.
+    #[inline(always)]
+    pub(crate) fn read_varint(&mut self) -> Result<u64> {
+        let high = self.read_u8()?;
 
         // 1 byte (no signature)
         if (high & 128) == 0 {
-            return Ok(high as usize);
+            return Ok(high as u64);
         }
 
         let mut ext_bytes = (!high).leading_zeros() as usize;
-        let mut size = (high ^ SIZE_MASKS[ext_bytes]) as usize;
+        let mut val = (high ^ SIZE_MASKS[ext_bytes]) as u64;
 
         while ext_bytes != 0 {
             ext_bytes -= 1;
-            size = (size << 8) | try!(self.uint8()) as usize;
+            val = (val << 8) | self.read_u8()? as u64;
         }
 
-        Ok(size)
+        Ok(val)
     }
 
     /// Read an arbitary sized binary data from the buffer and
@@ -216,23 +1325,14 @@ impl<'src> Decoder<'src> {
     /// you need to read.
     #[inline]
     pub fn bytes(&mut self) -> Result<&'src [u8]> {
-        // Order of addition is important here!
-        // Calling `size` will modify the `index`.
-        let len = try!(self.size());
-        let end = len + self.index;
-
-        if end > self.data.len() {
-            return Err(Error::ReadingOutOfBounds);
-        }
-
-        let bytes = unsafe { ::std::slice::from_raw_parts(
-            self.ptr.offset(self.index as isize),
-            len
-        ) };
+        recorded!(self, "bytes", self.read_slice())
+    }
 
-        self.index = end;
+    #[inline(always)]
+    fn read_slice(&mut self) -> Result<&'src [u8]> {
+        let len = self.read_size()?;
 
-        Ok(bytes)
+        self.source.borrow(len)
     }
 
     /// Read an arbitary sized owned `String` from the buffer and
@@ -243,14 +1343,97 @@ impl<'src> Decoder<'src> {
     /// many bytes you need to read.
     #[inline]
     pub fn string(&mut self) -> Result<&'src str> {
-        from_utf8(try!(self.bytes())).map_err(Into::into)
+        recorded!(self, "string", self.read_str())
     }
 
-    /// Returns `true` if the entire buffer has been read, otherwise
-    /// returns `false`.
+    #[inline(always)]
+    fn read_str(&mut self) -> Result<&'src str> {
+        from_utf8(self.read_slice()?).map_err(Into::into)
+    }
+
+    /// Read owned binary data, copying it into a scratch buffer if
+    /// enabled and the data can be borrowed from the source.
+    #[inline(always)]
+    fn read_owned_vec(&mut self) -> Result<Vec<u8>> {
+        let len = self.read_size()?;
+
+        self.allocate(len)?;
+
+        if !self.scratch {
+            return self.source.read_vec(len);
+        }
+
+        match self.source.borrow(len) {
+            Ok(slice) => Ok(scratch::copy(slice)),
+            Err(Error::NotBorrowable) => self.source.read_vec(len),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Read an owned string, checking borrowed data is valid UTF-8 before
+    /// copying it into a scratch buffer if enabled.
+    #[inline(always)]
+    fn read_owned_str(&mut self) -> Result<String> {
+        let len = self.read_size()?;
+
+        self.allocate(len)?;
+
+        if !self.scratch {
+            return String::from_utf8(self.source.read_vec(len)?).map_err(Into::into);
+        }
+
+        match self.source.borrow(len) {
+            Ok(slice) => Ok(scratch::copy_str(from_utf8(slice)?)),
+            Err(Error::NotBorrowable) => String::from_utf8(self.source.read_vec(len)?).map_err(Into::into),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Skip over a string, checking it's valid UTF-8. Strings that can't
+    /// be borrowed from the source have to be read into a buffer.
     #[inline]
-    pub fn end(&self) -> bool {
-        self.index >= self.data.len()
+    pub(crate) fn skip_str(&mut self) -> Result<()> {
+        self.read_cow_str().map(|_| ())
+    }
+
+    /// Read a string, borrowing it from the source if possible.
+    pub(crate) fn read_cow_str(&mut self) -> Result<Cow<'src, str>> {
+        match self.read_cow_bytes()? {
+            Cow::Borrowed(slice) => Ok(Cow::Borrowed(from_utf8(slice)?)),
+            Cow::Owned(vec) => Ok(Cow::Owned(String::from_utf8(vec)?)),
+        }
+    }
+
+    /// Read binary data, borrowing it from the source if possible.
+    pub(crate) fn read_cow_bytes(&mut self) -> Result<Cow<'src, [u8]>> {
+        let len = self.read_size()?;
+
+        self.read_raw(len)
+    }
+
+    /// Read `len` bytes without a `size` prefix, borrowing them from
+    /// the source if possible.
+    pub(crate) fn read_raw(&mut self, len: usize) -> Result<Cow<'src, [u8]>> {
+        match self.borrow_raw(len) {
+            Ok(slice) => Ok(Cow::Borrowed(slice)),
+            Err(Error::NotBorrowable) => self.source.read_vec(len).map(Cow::Owned),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Borrow `len` bytes without a `size` prefix from the source. Fails
+    /// with `NotBorrowable`, without consuming anything, if it can't.
+    #[inline]
+    pub(crate) fn borrow_raw(&mut self, len: usize) -> Result<&'src [u8]> {
+        self.source.borrow(len)
+    }
+
+    /// Skip over a size prefixed binary data without reading it.
+    #[inline]
+    pub(crate) fn skip_bytes(&mut self) -> Result<()> {
+        let len = self.read_size()?;
+
+        self.source.skip(len)
     }
 }
 
@@ -258,7 +1441,7 @@ macro_rules! impl_decodable {
     ($func:ident, $t:ty) => {
         impl<'src> BitDecode<'src> for $t {
             #[inline]
-            fn decode(d: &mut Decoder<'src>) -> Result<Self> {
+            fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
                 d.$func()
             }
         }
@@ -279,70 +1462,205 @@ impl_decodable!(size, usize);
 
 impl<'src> BitDecode<'src> for &'src [u8] {
     #[inline]
-    fn decode(d: &mut Decoder<'src>) -> Result<Self> {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
         d.bytes()
     }
 }
 
-impl<'src> BitDecode<'src> for Vec<u8> {
+/// Byte arrays are read without a `size` prefix. If the wire version
+/// is set to 1 or older with `Decoder::set_wire_version`, they are
+/// expected to be prefixed with a `size` equal to `N` instead.
+impl<'src, const N: usize> BitDecode<'src> for [u8; N] {
     #[inline]
-    fn decode(d: &mut Decoder<'src>) -> Result<Self> {
-        // Order of addition is important here!
-        // Calling `size` will modify the `index`.
-        let len = try!(d.size());
-        let end = len + d.index;
-
-        if end > d.data.len() {
-            return Err(Error::ReadingOutOfBounds);
-        }
-
-        let mut vec = Vec::with_capacity(len);
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let mut array = [0; N];
 
-        unsafe {
-            ::std::ptr::copy_nonoverlapping(
-                d.ptr.offset(d.index as isize),
-                vec.as_mut_ptr(),
-                len
-            );
+        d.legacy_array_size(N)?;
+        d.read_exact_into(&mut array)?;
 
-            vec.set_len(len);
-        }
+        Ok(array)
+    }
+}
 
-        d.index = end;
+impl<'src> BitDecode<'src> for Vec<u8> {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        recorded!(d, "bytes", d.read_owned_vec()).map(|mut vec| {
+            if d.shrink {
+                vec.shrink_to_fit();
+            }
 
-        Ok(vec)
+            vec
+        })
     }
 }
 
 impl<'src> BitDecode<'src> for &'src str {
     #[inline]
-    fn decode(d: &mut Decoder<'src>) -> Result<&'src str> {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<&'src str> {
         d.string()
     }
 }
 
 impl<'src> BitDecode<'src> for String {
     #[inline]
-    fn decode(d: &mut Decoder<'src>) -> Result<Self> {
-        String::from_utf8(try!(BitDecode::decode(d))).map_err(Into::into)
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let mut string = recorded!(d, "string", d.read_owned_str())?;
+
+        if d.shrink {
+            string.shrink_to_fit();
+        }
+
+        Ok(string)
     }
 }
 
 impl<'src, D: BitDecode<'src>> BitDecode<'src> for Vec<D> {
     #[inline]
-    fn decode(d: &mut Decoder<'src>) -> Result<Self> {
-        let size = try!(d.size());
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let mut vec = Vec::new();
 
-        let mut vec = Vec::with_capacity(size);
+        d.read_into(&mut vec)?;
 
-        for _ in 0..size {
-            vec.push(try!(D::decode(d)));
+        if d.shrink {
+            vec.shrink_to_fit();
         }
 
         Ok(vec)
     }
 }
 
+impl<'src, K, V, H> BitDecode<'src> for HashMap<K, V, H> where
+    K: BitDecode<'src> + Eq + Hash,
+    V: BitDecode<'src>,
+    H: BuildHasher + Default + 'src,
+{
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let mut map = HashMap::with_hasher(H::default());
+
+        d.read_map_into(&mut map)?;
+
+        if d.shrink {
+            map.shrink_to_fit();
+        }
+
+        Ok(map)
+    }
+}
+
+macro_rules! impl_wrapper {
+    ($( $w:ident ),*) => {
+        $(
+            impl<'src, T: BitDecode<'src>> BitDecode<'src> for $w<T> {
+                #[inline]
+                fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+                    T::decode(d).map($w)
+                }
+            }
+        )*
+    }
+}
+
+impl_wrapper!(Wrapping, Saturating);
+
+impl<'src, T: BitDecode<'src>> BitDecode<'src> for Range<T> {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(Range {
+            start: T::decode(d)?,
+            end: T::decode(d)?,
+        })
+    }
+}
+
+impl<'src, T: BitDecode<'src>> BitDecode<'src> for RangeInclusive<T> {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let start = T::decode(d)?;
+        let end = T::decode(d)?;
+
+        Ok(RangeInclusive::new(start, end))
+    }
+}
+
+impl<'src, T: BitDecode<'src>> BitDecode<'src> for Bound<T> {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        match d.uint8()? {
+            0   => T::decode(d).map(Bound::Included),
+            1   => T::decode(d).map(Bound::Excluded),
+            2   => Ok(Bound::Unbounded),
+            tag => Err(Error::InvalidEnumVariant { tag: tag as usize }),
+        }
+    }
+}
+
+impl<'src> BitDecode<'src> for () {
+    #[inline(always)]
+    fn decode<S: Source<'src>>(_: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl<'src, T: ?Sized + 'src> BitDecode<'src> for PhantomData<T> {
+    #[inline(always)]
+    fn decode<S: Source<'src>>(_: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(PhantomData)
+    }
+}
+
+macro_rules! impl_pointer {
+    ($( $p:ident ),*) => {
+        $(
+            impl<'src, T: BitDecode<'src>> BitDecode<'src> for $p<T> {
+                #[inline]
+                fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+                    d.allocate(size_of::<T>())?;
+                    d.nested(|d| T::decode(d).map($p::new))
+                }
+            }
+
+            impl<'src> BitDecode<'src> for $p<str> {
+                #[inline]
+                fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+                    recorded!(d, "string", d.read_cow_str().and_then(|cow| {
+                        d.allocate(cow.len())?;
+
+                        Ok(match cow {
+                            Cow::Borrowed(string) => $p::from(string),
+                            Cow::Owned(string) => $p::from(string),
+                        })
+                    }))
+                }
+            }
+
+            impl<'src> BitDecode<'src> for $p<[u8]> {
+                #[inline]
+                fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+                    recorded!(d, "bytes", d.read_cow_bytes().and_then(|cow| {
+                        d.allocate(cow.len())?;
+
+                        Ok(match cow {
+                            Cow::Borrowed(bytes) => $p::from(bytes),
+                            Cow::Owned(bytes) => $p::from(bytes),
+                        })
+                    }))
+                }
+            }
+
+            impl<'src, T: BitDecode<'src>> BitDecode<'src> for $p<[T]> {
+                #[inline]
+                fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+                    Vec::<T>::decode(d).map($p::from)
+                }
+            }
+        )*
+    }
+}
+
+impl_pointer!(Box, Rc, Arc);
+
 macro_rules! impl_tuple {
     ($( $l:ident ),*) => {
         impl<'src, $($l),*> BitDecode<'src> for ($($l),*) where
@@ -351,7 +1669,7 @@ macro_rules! impl_tuple {
             )*
         {
             #[inline(always)]
-            fn decode(d: &mut Decoder<'src>) -> Result<Self> {
+            fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
                 Ok(( $( try!($l::decode(d)) ),* ))
             }
         }
@@ -0,0 +1,109 @@
+//! Hex dumps of encoded buffers for debugging. Every line shows the
+//! offset, 16 bytes as hex and the same bytes as ASCII. Ranges of bytes
+//! can be labeled, e.g. with spans obtained from
+//! `Decoder::read_spanned`, and are marked below the line they start on.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::dump::Dump;
+//!
+//! let buffer = Encoder::new().uint16(9001).string("sparrow").end();
+//! let mut decoder = Decoder::new(&buffer);
+//!
+//! let (_, id) = decoder.read_spanned::<u16>().unwrap();
+//! let (_, name) = decoder.read_spanned::<&str>().unwrap();
+//!
+//! let dump = Dump::new(&buffer).field(id, "id").field(name, "name").to_string();
+//!
+//! assert_eq!(dump, "\
+//! 00000000  23 29 07 73 70 61 72 72  6f 77                    |#).sparrow|
+//!           ^ id [0..2]
+//!                 ^ name [2..10]
+//! ");
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+
+/// Bytes per line of a dump.
+const LINE_WIDTH: usize = 16;
+
+/// Width of the offset column, including separating spaces.
+const OFFSET_WIDTH: usize = 10;
+
+/// Formats a buffer as an annotated hex dump with `Display`,
+/// see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Dump<'a> {
+    data: &'a [u8],
+    fields: Vec<(Range<usize>, String)>,
+}
+
+impl<'a> Dump<'a> {
+    /// Create a dump of `data` without any labels.
+    #[inline]
+    pub fn new(data: &'a [u8]) -> Dump<'a> {
+        Dump {
+            data,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Label a range of bytes. Ranges starting past the end
+    /// of the buffer are not shown.
+    pub fn field<L: Into<String>>(mut self, range: Range<usize>, label: L) -> Dump<'a> {
+        self.fields.push((range, label.into()));
+        self.fields.sort_by_key(|(range, _)| range.start);
+
+        self
+    }
+}
+
+/// Column at which the hex of the byte at `index` of a line starts.
+#[inline]
+fn column(index: usize) -> usize {
+    OFFSET_WIDTH + index * 3 + (index >= LINE_WIDTH / 2) as usize
+}
+
+impl<'a> fmt::Display for Dump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (line, chunk) in self.data.chunks(LINE_WIDTH).enumerate() {
+            let offset = line * LINE_WIDTH;
+
+            write!(f, "{:08x} ", offset)?;
+
+            for index in 0..LINE_WIDTH {
+                if index == LINE_WIDTH / 2 {
+                    f.write_str(" ")?;
+                }
+
+                match chunk.get(index) {
+                    Some(byte) => write!(f, " {:02x}", byte)?,
+                    None       => f.write_str("   ")?,
+                }
+            }
+
+            f.write_str("  |")?;
+
+            for &byte in chunk {
+                let printable = byte.is_ascii_graphic() || byte == b' ';
+
+                write!(f, "{}", if printable { byte as char } else { '.' })?;
+            }
+
+            f.write_str("|\n")?;
+
+            let labels = self.fields
+                .iter()
+                .filter(|(range, _)| range.start >= offset && range.start < offset + chunk.len());
+
+            for (range, label) in labels {
+                let indent = column(range.start - offset);
+
+                writeln!(f, "{:indent$}^ {} [{}..{}]", "", label, range.start, range.end, indent = indent)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,16 +1,88 @@
-use std::{ptr, mem};
-use utils::SIZE_MASKS;
+use std::{fmt, io, mem};
+use std::collections::HashMap;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::num::{Saturating, Wrapping};
+use std::ops::{Bound, Range, RangeInclusive};
+use std::rc::Rc;
+use std::sync::Arc;
+use utils::{SIZE_MASKS, DEBUG_WINDOW, HexWindow, BoolSlot, Error, Result};
+use MAX_SAFE_INTEGER;
+use profile::{Profile, Profiler};
+use sink::{Sink, Truncate, Counter, Slice, Writer, Pooled, BufferProvider};
+use context::BitEncodeWith;
+
+#[cfg(feature = "bytes")]
+use bytes::BytesMut;
 
 /// Encoder takes in typed data and produces a binary buffer
-/// represented as `Vec<u8>`.
-pub struct Encoder {
-    data: Vec<u8>,
-    bool_index: usize,
-    bool_shift: u8,
+/// represented as `Vec<u8>`, or writes it to any other `Sink`
+/// chosen with `Encoder::with_sink`. See the `sink` module.
+pub struct Encoder<S = Vec<u8>> {
+    sink: S,
+    bools: Option<BoolSlot>,
+    profiler: Option<Box<Profiler>>,
 }
 
+/// Shows the number of bytes written, whether the next `bool` will be
+/// stacked on the last byte, and the last few bytes if the sink keeps
+/// them.
+impl<S: Sink> fmt::Debug for Encoder<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let len = self.sink.len();
+        let shift = self.bools.filter(|slot| slot.fits(len, 1)).map(|slot| slot.shift);
+
+        f.debug_struct("Encoder")
+            .field("len", &len)
+            .field("bool_shift", &shift)
+            .field("profiled", &self.profiler.is_some())
+            .field("window", &HexWindow { before: self.sink.tail(DEBUG_WINDOW), after: &[] })
+            .finish()
+    }
+}
+
+/// Checkpoint of an `Encoder`, see `Encoder::mark`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mark {
+    len: usize,
+    bools: Option<BoolSlot>,
+}
+
+impl Mark {
+    /// Number of bytes written when the mark was created.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the mark was created on an empty `Encoder`.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Encoder that only counts the bytes it would produce, with the exact
+/// same rules as the regular `Encoder`, making it possible to compute
+/// the size of a frame without allocating it.
+///
+/// ```
+/// use bitsparrow::{Encoder, SizeCounter};
+///
+/// let size = SizeCounter::default()
+///                 .string("Foo")
+///                 .bool(true)
+///                 .bool(false)
+///                 .end();
+///
+/// assert_eq!(size, 5);
+/// assert_eq!(SizeCounter::count(("Foo", true, false)), 5);
+/// assert_eq!(Encoder::encode(("Foo", true, false)).len(), 5);
+/// ```
+pub type SizeCounter = Encoder<Counter>;
+
 pub trait BitEncode {
-    fn encode(&self, &mut Encoder);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>);
 
     #[inline(always)]
     fn size_hint() -> usize {
@@ -18,38 +90,424 @@ pub trait BitEncode {
     }
 }
 
+/// Object safe counterpart of `BitEncode`, implemented for every type
+/// implementing it. `dyn DynEncode` implements `BitEncode` itself, so
+/// values of different types can be kept together and encoded as if
+/// the concrete types were known.
+///
+/// ```
+/// use bitsparrow::{Encoder, DynEncode};
+///
+/// let messages: Vec<Box<dyn DynEncode>> = vec![
+///     Box::new(42u16),
+///     Box::new("Foo"),
+///     Box::new((true, false)),
+/// ];
+///
+/// let mut encoder = Encoder::new();
+///
+/// for message in &messages {
+///     encoder.write(&**message);
+/// }
+///
+/// assert_eq!(encoder.end(), Encoder::encode((42u16, "Foo", (true, false))));
+/// ```
+pub trait DynEncode {
+    fn encode_dyn(&self, e: &mut Encoder<&mut dyn Sink>);
+
+    fn size_hint_dyn(&self) -> usize;
+}
+
+impl<T: BitEncode + ?Sized> DynEncode for T {
+    #[inline]
+    fn encode_dyn(&self, e: &mut Encoder<&mut dyn Sink>) {
+        self.encode(e);
+    }
+
+    #[inline]
+    fn size_hint_dyn(&self) -> usize {
+        T::size_hint()
+    }
+}
+
 impl Encoder {
     /// Create a new instance of the `Encoder`.
     #[inline(always)]
     pub fn new() -> Encoder {
-        Encoder {
-            data: Vec::new(),
-            bool_index: ::std::usize::MAX,
-            bool_shift: 0,
-        }
+        Encoder::with_sink(Vec::new())
     }
 
     /// Create a new instance of the `Encoder` with a preallocated buffer capacity.
     #[inline(always)]
     pub fn with_capacity(capacity: usize) -> Encoder {
-        Encoder {
-            data: Vec::with_capacity(capacity),
-            bool_index: ::std::usize::MAX,
-            bool_shift: 0,
-        }
+        Encoder::with_sink(Vec::with_capacity(capacity))
+    }
+
+    /// Create a new instance of the `Encoder` that records the size of
+    /// every message and field it encodes. See `Profile` for details.
+    #[inline]
+    pub fn profiled() -> Encoder {
+        let mut encoder = Encoder::new();
+        encoder.profiler = Some(Box::default());
+        encoder
     }
 
     #[inline]
     pub fn encode<E: BitEncode>(val: E) -> Vec<u8> {
         let mut e = Encoder::with_capacity(E::size_hint());
         val.encode(&mut e);
-        e.data
+        e.sink
+    }
+
+    /// Finish encoding, obtain the buffer and reset the encoder.
+    #[inline(always)]
+    pub fn end(&mut self) -> Vec<u8> {
+        self.reset_bools();
+
+        mem::take(&mut self.sink)
+    }
+}
+
+impl SizeCounter {
+    /// Count the bytes needed to encode a value.
+    #[inline]
+    pub fn count<E: BitEncode>(val: E) -> usize {
+        let mut e = SizeCounter::default();
+        val.encode(&mut e);
+        e.sink.len()
+    }
+
+    /// Finish counting, obtain the number of bytes and reset the counter.
+    #[inline(always)]
+    pub fn end(&mut self) -> usize {
+        self.reset_bools();
+
+        mem::take(&mut self.sink).len()
+    }
+}
+
+impl<'a> Encoder<Slice<'a>> {
+    /// Finish encoding, obtain the number of bytes written and start
+    /// over at the beginning of the buffer. Returns `BufferTooSmall`
+    /// if the encoded data did not fit.
+    #[inline]
+    pub fn end(&mut self) -> Result<usize> {
+        self.reset_bools();
+
+        let written = self.sink.written();
+        self.sink.reset();
+        written
+    }
+}
+
+impl<W: Write> Encoder<Writer<W>> {
+    /// Finish encoding, flush all data to the writer and obtain the
+    /// number of bytes written.
+    #[inline]
+    pub fn end(&mut self) -> io::Result<usize> {
+        self.reset_bools();
+        self.sink.flush()
+    }
+}
+
+impl<P: BufferProvider> Encoder<Pooled<P>> {
+    /// Create a new instance of the `Encoder` writing to buffers
+    /// acquired from `provider`. See `Pooled`.
+    #[inline]
+    pub fn with_provider(provider: P) -> Self {
+        Encoder::with_sink(Pooled::new(provider))
+    }
+
+    /// Finish encoding, obtain the buffer and acquire a new one
+    /// for what's encoded next.
+    #[inline]
+    pub fn end(&mut self) -> Vec<u8> {
+        self.reset_bools();
+        self.sink.take()
+    }
+
+    /// Give back a buffer obtained from `end` to the provider.
+    #[inline]
+    pub fn release(&mut self, buf: Vec<u8>) {
+        self.sink.release(buf);
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Encoder<BytesMut> {
+    /// Finish encoding, split off the encoded bytes and reset the encoder.
+    #[inline]
+    pub fn end(&mut self) -> BytesMut {
+        self.reset_bools();
+        self.sink.split()
+    }
+}
+
+impl<S: Truncate> Encoder<S> {
+    /// Create a checkpoint to which the `Encoder` can be rolled back.
+    #[inline]
+    pub fn mark(&self) -> Mark {
+        let len = self.sink.len();
+
+        Mark {
+            len,
+            bools: self.bools.filter(|slot| slot.end == len),
+        }
+    }
+
+    /// Discard everything written since `mark` was created, including
+    /// booleans stacked on a byte written before it. Sizes recorded by
+    /// a profiled `Encoder` are not rolled back.
+    ///
+    /// ```
+    /// use bitsparrow::Encoder;
+    ///
+    /// let mut encoder = Encoder::new();
+    /// encoder.uint8(1).bool(true);
+    ///
+    /// let mark = encoder.mark();
+    /// encoder.bool(true).string("Foo");
+    /// encoder.rollback(mark);
+    ///
+    /// assert_eq!(encoder.bool(false).bool(true).end(), &[0x01, 0b101]);
+    /// ```
+    pub fn rollback(&mut self, mark: Mark) -> &mut Self {
+        self.sink.truncate(mark.len);
+        self.bools = mark.bools;
+
+        if let Some(slot) = mark.bools {
+            self.sink.and_last(0xFF >> (7 - slot.shift));
+        }
+
+        self
+    }
+}
+
+impl<S: Sink + Default> Default for Encoder<S> {
+    #[inline]
+    fn default() -> Self {
+        Encoder::with_sink(S::default())
+    }
+}
+
+impl<S: Sink> Encoder<S> {
+    /// Create a new instance of the `Encoder` writing to a `Sink`.
+    #[inline(always)]
+    pub fn with_sink(sink: S) -> Self {
+        Encoder {
+            sink,
+            bools: None,
+            profiler: None,
+        }
+    }
+
+    /// Obtain the sink, discarding the state of the `Encoder`.
+    #[inline]
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    /// Obtain the size breakdown collected so far and stop profiling.
+    /// Returns `None` unless the `Encoder` was created with `profiled`.
+    #[inline]
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profiler.take().map(|profiler| profiler.into_profile())
     }
 
     /// Store any type implementing `BitEncode` on the buffer.
     #[inline]
     pub fn write<E: BitEncode>(&mut self, val: E) -> &mut Self {
-        val.encode(self);
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::trace_span!(
+            "write",
+            ty = ::std::any::type_name::<E>(),
+            offset = self.sink.len()
+        ).entered();
+
+        match self.profiler {
+            None    => val.encode(self),
+            Some(_) => self.write_profiled(::std::any::type_name::<E>(), |e| val.encode(e)),
+        }
+
+        self
+    }
+
+    /// Store a value of a type that needs a context to be encoded.
+    /// See `BitEncodeWith` for details.
+    #[inline]
+    pub fn write_with<E, C>(&mut self, val: &E, ctx: &mut C) -> &mut Self where
+        E: BitEncodeWith<C> + ?Sized,
+        C: ?Sized,
+    {
+        match self.profiler {
+            None    => val.encode_with(self, ctx),
+            Some(_) => self.write_profiled(::std::any::type_name::<E>(), |e| val.encode_with(e, ctx)),
+        }
+
+        self
+    }
+
+    /// Store a named field of a composite type on the buffer. This is
+    /// equivalent to `write`, but lets a profiled `Encoder` attribute
+    /// the bytes to the field.
+    #[inline]
+    pub fn field<E: BitEncode>(&mut self, name: &'static str, val: E) -> &mut Self {
+        if self.profiler.is_none() {
+            return self.write(val);
+        }
+
+        self.write_field(name, |e| { e.write(val); });
+
+        self
+    }
+
+    /// Store a named field of a composite type with a context, see
+    /// `field` and `write_with`.
+    #[inline]
+    pub fn field_with<E, C>(&mut self, name: &'static str, val: &E, ctx: &mut C) -> &mut Self where
+        E: BitEncodeWith<C> + ?Sized,
+        C: ?Sized,
+    {
+        if self.profiler.is_none() {
+            return self.write_with(val, ctx);
+        }
+
+        self.write_field(name, |e| { e.write_with(val, ctx); });
+
+        self
+    }
+
+    /// Store everything encoded by another `Encoder`, moving its buffer
+    /// instead of copying it if nothing has been written to this one
+    /// yet. Booleans stored after this will continue stacking on the
+    /// last byte of `other` if it ended with one.
+    ///
+    /// ```
+    /// use bitsparrow::Encoder;
+    ///
+    /// let mut header = Encoder::new();
+    /// header.uint8(1).bool(true);
+    ///
+    /// let buffer = Encoder::new().append(header).bool(true).string("Foo").end();
+    ///
+    /// assert_eq!(buffer, &[0x01, 0b11, 0x03, 0x46, 0x6f, 0x6f]);
+    /// ```
+    pub fn append(&mut self, other: Encoder) -> &mut Self {
+        let start = self.sink.len();
+        let end = other.sink.len();
+
+        self.bools = other.bools
+            .filter(|slot| slot.end == end)
+            .map(|slot| BoolSlot { end: start + end, ..slot });
+
+        self.sink.push_vec(other.sink);
+
+        self
+    }
+
+    /// Store a value prefixed with its encoded length in bytes, so
+    /// decoders can skip bytes they don't know about, e.g. fields added
+    /// to the end of a struct by a newer version of the protocol.
+    /// Read it with `Decoder::read_sized`.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// // Newer version with an extra field
+    /// let buffer = Encoder::new().sized(("foo", 42u32)).uint16(7).end();
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    ///
+    /// assert_eq!(decoder.read_sized::<&str>().unwrap(), "foo");
+    /// assert_eq!(decoder.uint16().unwrap(), 7);
+    /// ```
+    pub fn sized<E: BitEncode>(&mut self, val: E) -> &mut Self {
+        let len = SizeCounter::count(&val);
+
+        self.size(len).write(val)
+    }
+
+    /// Store `len` zero bytes, reserving space for fields a future
+    /// version of the protocol might add. Skip them with
+    /// `Decoder::skip_reserved`.
+    #[inline]
+    pub fn reserved(&mut self, len: usize) -> &mut Self {
+        self.repeat(0, len)
+    }
+
+    /// Store `byte` on the buffer `count` times, without a `size`
+    /// prefix, e.g. to fill a padding region.
+    ///
+    /// ```
+    /// use bitsparrow::Encoder;
+    ///
+    /// let buffer = Encoder::new().uint8(1).repeat(0xFF, 3).end();
+    ///
+    /// assert_eq!(buffer, &[1, 0xFF, 0xFF, 0xFF]);
+    /// ```
+    pub fn repeat(&mut self, byte: u8, count: usize) -> &mut Self {
+        let chunk = [byte; 64];
+        let mut left = count;
+
+        self.sink.reserve(count);
+
+        while left > 0 {
+            let len = left.min(chunk.len());
+
+            self.sink.push_slice(&chunk[..len]);
+            left -= len;
+        }
+
+        self
+    }
+
+    /// Store `val` on the buffer `count` times, without a `size`
+    /// prefix. Preceded by `size(count)` it produces the same bytes
+    /// as a `Vec` filled with `count` copies of `val`.
+    ///
+    /// ```
+    /// use bitsparrow::Encoder;
+    ///
+    /// let buffer = Encoder::new().size(3).repeat_value(&7u16, 3).end();
+    ///
+    /// assert_eq!(buffer, Encoder::encode(vec![7u16; 3]));
+    /// ```
+    pub fn repeat_value<E: BitEncode>(&mut self, val: &E, count: usize) -> &mut Self {
+        self.sink.reserve(count.saturating_mul(E::size_hint()));
+
+        for _ in 0..count {
+            BitEncode::encode(val, self);
+        }
+
+        self
+    }
+
+    /// Pad the buffer with zero bytes until its length is a multiple
+    /// of `n`, so data following can be reinterpreted in place by the
+    /// receiver. Panics if `n` is zero.
+    ///
+    /// ```
+    /// use bitsparrow::Encoder;
+    ///
+    /// let buffer = Encoder::new().uint8(1).align(4).uint32(2).end();
+    ///
+    /// assert_eq!(buffer, &[1, 0, 0, 0, 0, 0, 0, 2]);
+    /// ```
+    #[inline]
+    pub fn align(&mut self, n: usize) -> &mut Self {
+        assert!(n != 0, "alignment must not be zero");
+
+        match self.sink.len() % n {
+            0   => self,
+            rem => self.reserved(n - rem),
+        }
+    }
+
+    /// Store raw bytes on the buffer as they are, without a `size`
+    /// prefix, e.g. a payload that has been encoded beforehand.
+    #[inline]
+    pub fn append_raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.sink.push_slice(bytes);
 
         self
     }
@@ -57,7 +515,7 @@ impl Encoder {
     /// Store a `u8` on the buffer.
     #[inline]
     pub fn uint8(&mut self, val: u8) -> &mut Self {
-        self.data.push(val);
+        traced!(self.sink.len(), "u8", self.sink.push_byte(val));
 
         self
     }
@@ -86,6 +544,34 @@ impl Encoder {
         self
     }
 
+    /// Store an unsigned integer the JavaScript implementation can read
+    /// into a number without losing precision, in the same format as
+    /// `size`. Fails with `LimitExceeded`, without writing anything, if
+    /// it's larger than `MAX_SAFE_INTEGER`.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder, MAX_SAFE_INTEGER};
+    ///
+    /// let mut encoder = Encoder::new();
+    ///
+    /// encoder.js_safe_uint(9001).unwrap();
+    /// assert!(encoder.js_safe_uint(MAX_SAFE_INTEGER + 1).is_err());
+    ///
+    /// let buffer = encoder.end();
+    ///
+    /// assert_eq!(Decoder::new(&buffer).js_safe_uint().unwrap(), 9001);
+    /// ```
+    #[inline]
+    pub fn js_safe_uint(&mut self, val: u64) -> Result<&mut Self> {
+        if val > MAX_SAFE_INTEGER {
+            return Err(Error::LimitExceeded);
+        }
+
+        traced!(self.sink.len(), "u64", self.write_varint(val, 0));
+
+        Ok(self)
+    }
+
     /// Store an `i8` on the buffer.
     #[inline]
     pub fn int8(&mut self, val: i8) -> &mut Self {
@@ -162,6 +648,37 @@ impl Encoder {
         self
     }
 
+    /// Store the lowest `count` bits of `val`, stacking them on the same
+    /// byte as preceding `bool`s and bits if there's room for all of
+    /// them, otherwise starting a new byte. Fields never span two bytes.
+    /// Panics if `count` is not between 1 and 8.
+    ///
+    /// ```
+    /// use bitsparrow::Encoder;
+    ///
+    /// let buffer = Encoder::new()
+    ///                 .bits(0b101, 3)
+    ///                 .bool(true)
+    ///                 .bits(0b11, 2)
+    ///                 .bits(0b1111, 4)
+    ///                 .end();
+    ///
+    /// assert_eq!(buffer, &[0b00111101, 0b00001111]);
+    /// ```
+    #[inline]
+    pub fn bits(&mut self, val: u8, count: u8) -> &mut Self {
+        assert!((1..=8).contains(&count), "bit count must be between 1 and 8");
+
+        let bits = val & (0xFF >> (8 - count));
+        let index = self.sink.len();
+
+        traced!(self.sink.len(), "bits", {
+            self.stack_bits(index, bits, count);
+        });
+
+        self
+    }
+
     /// Store a `usize` on the buffer. This will use a variable amount of bytes
     /// depending on the value of `usize`, making it a very powerful and flexible
     /// type to send around. BitSparrow uses `size` internally to prefix `string`
@@ -171,11 +688,45 @@ impl Encoder {
     /// on [the homepage](http://bitsparrow.io).
     #[inline]
     pub fn size(&mut self, val: usize) -> &mut Self {
-        self.size_with_reserve(val, 0);
+        val.encode(self);
 
         self
     }
 
+    /// Store the number of entries of a map, which are then written one
+    /// by one as keys followed by values, without building a `HashMap`
+    /// first. Read them back with `Decoder::map_entries`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let mut encoder = Encoder::new();
+    /// encoder.map_header(2);
+    ///
+    /// for id in 1..3u16 {
+    ///     encoder.write(id).string("entry");
+    /// }
+    ///
+    /// let buffer = encoder.end();
+    /// let map: HashMap<u16, &str> = Decoder::decode(&buffer).unwrap();
+    ///
+    /// assert_eq!(map[&2], "entry");
+    /// ```
+    #[inline]
+    pub fn map_header(&mut self, len: usize) -> &mut Self {
+        self.size(len)
+    }
+
+    /// Store a `HashMap` with its entries ordered by key, see `SortedMap`.
+    #[inline]
+    pub fn sorted_map<K, V, H>(&mut self, map: &HashMap<K, V, H>) -> &mut Self where
+        K: BitEncode + Ord,
+        V: BitEncode,
+    {
+        self.write(SortedMap(map))
+    }
+
     /// Store an arbitary collection of bytes represented as `&[u8]`,
     /// easy to use by dereferencing `Vec<u8>` with `&`.
     #[inline]
@@ -193,13 +744,122 @@ impl Encoder {
         self
     }
 
-    /// Finish encoding, obtain the buffer and reset the encoder.
+    /// Store a collection of strings, same as writing a `&[&str]` or
+    /// `Vec<String>`, but reserving the capacity for all of the strings
+    /// at once using their summed lengths.
+    ///
+    /// ```
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let names = vec![String::from("foo"), String::from("bar")];
+    /// let buffer = Encoder::new().strings(&names).end();
+    ///
+    /// assert_eq!(buffer, Encoder::encode(&names));
+    /// assert_eq!(Decoder::decode::<Vec<&str>>(&buffer).unwrap(), &["foo", "bar"]);
+    /// ```
+    pub fn strings<I>(&mut self, val: I) -> &mut Self where
+        I: IntoIterator,
+        I::IntoIter: ExactSizeIterator + Clone,
+        I::Item: AsRef<str>,
+    {
+        let iter = val.into_iter();
+        let total: usize = iter.clone().map(|s| s.as_ref().len()).sum();
+
+        self.size_with_reserve(iter.len(), 0);
+        self.sink.reserve(total + iter.len());
+
+        for s in iter {
+            s.as_ref().encode(self);
+        }
+
+        self
+    }
+
+    /// Run `f` on an `Encoder` sharing the state of this one,
+    /// but writing to a `dyn Sink`.
+    fn with_dyn<F: FnOnce(&mut Encoder<&mut dyn Sink>)>(&mut self, f: F) {
+        let mut e = Encoder {
+            sink: &mut self.sink as &mut dyn Sink,
+            bools: self.bools,
+            profiler: self.profiler.take(),
+        };
+
+        f(&mut e);
+
+        self.bools = e.bools;
+        self.profiler = e.profiler;
+    }
+
     #[inline(always)]
-    pub fn end(&mut self) -> Vec<u8> {
-        self.bool_index = ::std::usize::MAX;
-        self.bool_shift = 0;
+    fn reset_bools(&mut self) {
+        self.bools = None;
+    }
+
+    fn write_profiled<F: FnOnce(&mut Self)>(&mut self, name: &'static str, write: F) {
+        let start = self.sink.len();
+
+        if self.with_profiler(|profiler| profiler.begin_message(name)) {
+            write(self);
+
+            let bytes = self.sink.len() - start;
+
+            self.with_profiler(|profiler| profiler.end_message(bytes));
+        } else {
+            write(self);
+        }
+    }
+
+    fn write_field<F: FnOnce(&mut Self)>(&mut self, name: &'static str, write: F) {
+        let start = self.sink.len();
+
+        self.with_profiler(|profiler| profiler.begin_field(name));
+        write(self);
+
+        let bytes = self.sink.len() - start;
+
+        self.with_profiler(|profiler| profiler.end_field(bytes));
+    }
+
+    #[inline]
+    fn with_profiler<T, F>(&mut self, f: F) -> T where
+        T: Default,
+        F: FnOnce(&mut Profiler) -> T,
+    {
+        match self.profiler {
+            Some(ref mut profiler) => f(profiler),
+            None                   => T::default(),
+        }
+    }
+
+    #[inline(always)]
+    fn write_bool(&mut self, val: bool) {
+        let index = self.sink.len();
+
+        self.stack_bits(index, val as u8, 1);
+    }
 
-        mem::replace(&mut self.data, Vec::new())
+    /// Stack `count` bits on the last byte if it's the one `bool`s
+    /// were last written to and they fit, otherwise write a new byte.
+    #[inline(always)]
+    fn stack_bits(&mut self, index: usize, bits: u8, count: u8) {
+        match self.bools {
+            Some(ref mut slot) if slot.fits(index, count) => {
+                let bits = bits << slot.advance(count);
+
+                slot.bits |= bits;
+                self.sink.or_last(bits);
+            },
+            _ => {
+                self.bools = Some(BoolSlot::new(index + 1, bits, count));
+                self.sink.push_byte(bits);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn write_slice(&mut self, val: &[u8]) {
+        self.size_with_reserve(val.len(), 1);
+        self.sink.push_slice(val);
     }
 
     #[inline(always)]
@@ -207,17 +867,30 @@ impl Encoder {
         if size < 128 {
             // Encoding size means data will follow, so it makes sense to reserve
             // capacity on the buffer beforehand
-            self.data.reserve(1 + size * item_size);
-            self.data.push(size as u8);
+            self.sink.reserve(1 + size * item_size);
+            self.sink.push_byte(size as u8);
+            return;
+        }
+
+        self.write_varint(size as u64, size * item_size);
+    }
+
+    /// Store a `u64` in the same variable length format as `size`,
+    /// reserving `reserve` extra bytes for data that follows it.
+    #[inline]
+    pub(crate) fn write_varint(&mut self, val: u64, reserve: usize) {
+        if val < 128 {
+            self.sink.reserve(1 + reserve);
+            self.sink.push_byte(val as u8);
             return;
         }
 
-        let mut masked = size as u64;
+        let mut masked = val;
 
         let lead = masked.leading_zeros() as usize;
         let bytes = if lead == 0 { 9 } else { 9 - (lead - 1) / 7 };
 
-        let mut buf: [u8; 9] = unsafe { mem::uninitialized() };
+        let mut buf = [0u8; 9];
 
         for i in (1 .. bytes).rev() {
             buf[i] = masked as u8;
@@ -226,22 +899,28 @@ impl Encoder {
         buf[0] = (masked as u8) | SIZE_MASKS[bytes - 1];
 
         // Same as above...
-        self.data.reserve(bytes + size * item_size);
-        self.data.extend_from_slice(&buf[0 .. bytes]);
+        self.sink.reserve(bytes + reserve);
+        self.sink.push_slice(&buf[0 .. bytes]);
     }
 }
 
 // impl BitEncode for u8 {
 //     #[inline(always)]
-//     fn encode(&self, e: &mut Encoder) {
-//         e.data.push(*self);
+//     fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+//         e.sink.push_byte(*self);
 //     }
 // }
 
 impl BitEncode for i8 {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        e.data.push(*self as u8);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "i8", e.sink.push_byte(*self as u8));
+    }
+}
+
+macro_rules! write_bytes {
+    ($e:expr, $val:expr) => {
+        $e.sink.push_slice(&$val.to_be_bytes())
     }
 }
 
@@ -249,20 +928,8 @@ macro_rules! impl_encodable {
     ($t:ty) => {
         impl BitEncode for $t {
             #[inline(always)]
-            fn encode(&self, e: &mut Encoder) {
-                unsafe {
-                    let ptr: *const u8 = mem::transmute(&self.to_be());
-
-                    let len = e.data.len();
-                    e.data.reserve(mem::size_of::<$t>());
-                    e.data.set_len(len + mem::size_of::<$t>());
-
-                    ptr::copy_nonoverlapping(
-                        ptr,
-                        e.data.as_mut_ptr().offset(len as isize),
-                        mem::size_of::<$t>()
-                    );
-                }
+            fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+                traced!(e.sink.len(), stringify!($t), write_bytes!(e, *self));
             }
 
             #[inline(always)]
@@ -282,8 +949,8 @@ impl_encodable!(i64);
 
 impl BitEncode for f32 {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(&unsafe { mem::transmute::<f32, u32>(*self) }, e);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "f32", write_bytes!(e, self.to_bits()));
     }
 
     #[inline(always)]
@@ -294,8 +961,8 @@ impl BitEncode for f32 {
 
 impl BitEncode for f64 {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(&unsafe { mem::transmute::<f64, u64>(*self) }, e);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "f64", write_bytes!(e, self.to_bits()));
     }
 
     #[inline(always)]
@@ -306,47 +973,22 @@ impl BitEncode for f64 {
 
 impl BitEncode for bool {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        let bit = *self as u8;
-        let index = e.data.len();
-
-        if e.bool_index == index && e.bool_shift < 7 {
-            e.bool_shift += 1;
-            e.data[index - 1] |= bit << e.bool_shift;
-        } else {
-            e.bool_index = index + 1;
-            e.bool_shift = 0;
-            e.data.push(bit);
-        }
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "bool", e.write_bool(*self));
     }
 }
 
 impl BitEncode for usize {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        e.size_with_reserve(*self, 0);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "size", e.size_with_reserve(*self, 0));
     }
 }
 
 impl BitEncode for [u8] {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        e.size_with_reserve(self.len(), 1);
-
-        unsafe {
-            let len = e.data.len();
-
-            // Encoder::size must reserve capacity beforehand
-            debug_assert!(e.data.capacity() >= len + self.len());
-
-            ptr::copy_nonoverlapping(
-                self.as_ptr(),
-                e.data.as_mut_ptr().offset(len as isize),
-                self.len()
-            );
-
-            e.data.set_len(len + self.len());
-        }
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "bytes", e.write_slice(self));
     }
 
     #[inline(always)]
@@ -355,65 +997,40 @@ impl BitEncode for [u8] {
     }
 }
 
-macro_rules! impl_deref {
-    ($t:ty, $size:expr) => {
-        impl<'a> BitEncode for &'a $t {
-            #[inline(always)]
-            fn encode(&self, e: &mut Encoder) {
-                BitEncode::encode(*self, e);
-            }
+impl<T: BitEncode + ?Sized> BitEncode for &T {
+    #[inline(always)]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        BitEncode::encode(*self, e);
+    }
 
-            #[inline(always)]
-            fn size_hint() -> usize {
-                $size
-            }
-        }
+    #[inline(always)]
+    fn size_hint() -> usize {
+        T::size_hint()
     }
 }
 
-impl_deref!(u16, 2);
-impl_deref!(u32, 4);
-impl_deref!(u64, 8);
-impl_deref!(i8, 1);
-impl_deref!(i16, 2);
-impl_deref!(i32, 4);
-impl_deref!(i64, 8);
-impl_deref!(f32, 4);
-impl_deref!(f64, 8);
-impl_deref!(usize, 1);
-impl_deref!(bool, 1);
-impl_deref!([u8], 16);
+/// Byte arrays, such as hashes or keys, are stored as they are
+/// without a `size` prefix, since the `Decoder` knows their length
+/// from the type. Prior to `WIRE_VERSION` 2 they were stored like
+/// `[u8]`, see `Decoder::set_wire_version` for reading those.
+impl<const N: usize> BitEncode for [u8; N] {
+    #[inline(always)]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "bytes", e.sink.push_slice(self));
+    }
+
+    #[inline(always)]
+    fn size_hint() -> usize {
+        N
+    }
+}
 
 macro_rules! impl_array {
     ($( $size:expr ),*) => {
         $(
-            impl BitEncode for [u8; $size] {
-                #[inline(always)]
-                fn encode(&self, e: &mut Encoder) {
-                    BitEncode::encode(AsRef::<[u8]>::as_ref(self), e);
-                }
-
-                #[inline(always)]
-                fn size_hint() -> usize {
-                    $size + 1
-                }
-            }
-
-            impl<'a> BitEncode for &'a [u8; $size] {
-                #[inline(always)]
-                fn encode(&self, e: &mut Encoder) {
-                    BitEncode::encode(AsRef::<[u8]>::as_ref(self), e);
-                }
-
-                #[inline(always)]
-                fn size_hint() -> usize {
-                    $size + 1
-                }
-            }
-
             impl<E: BitEncode> BitEncode for [E; $size] {
                 #[inline(always)]
-                fn encode(&self, e: &mut Encoder) {
+                fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
                     BitEncode::encode(AsRef::<[E]>::as_ref(self), e);
                 }
 
@@ -423,17 +1040,6 @@ macro_rules! impl_array {
                 }
             }
 
-            impl<'a, E: BitEncode> BitEncode for &'a [E; $size] {
-                #[inline(always)]
-                fn encode(&self, e: &mut Encoder) {
-                    BitEncode::encode(AsRef::<[E]>::as_ref(self), e);
-                }
-
-                #[inline(always)]
-                fn size_hint() -> usize {
-                    $size * E::size_hint() + 1
-                }
-            }
         )*
     }
 }
@@ -444,10 +1050,10 @@ impl_array!(
     17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32
 );
 
-impl<'a> BitEncode for &'a Vec<u8> {
+impl BitEncode for Vec<u8> {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(AsRef::<[u8]>::as_ref(*self), e);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        BitEncode::encode(AsRef::<[u8]>::as_ref(self), e);
     }
 
     #[inline(always)]
@@ -456,10 +1062,10 @@ impl<'a> BitEncode for &'a Vec<u8> {
     }
 }
 
-impl<'a> BitEncode for &'a str {
+impl BitEncode for str {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(self.as_bytes(), e);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "string", e.write_slice(self.as_bytes()));
     }
 
     #[inline(always)]
@@ -470,8 +1076,8 @@ impl<'a> BitEncode for &'a str {
 
 impl BitEncode for String {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(self.as_bytes(), e);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        traced!(e.sink.len(), "string", e.write_slice(self.as_bytes()));
     }
 
     #[inline(always)]
@@ -480,49 +1086,172 @@ impl BitEncode for String {
     }
 }
 
-impl<'a> BitEncode for &'a String {
+impl<E: BitEncode> BitEncode for [E] {
     #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(self.as_bytes(), e);
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.size_with_reserve(self.len(), E::size_hint());
+        for item in self {
+            BitEncode::encode(item, e);
+        }
     }
+}
 
+impl<E: BitEncode> BitEncode for Vec<E> {
     #[inline(always)]
-    fn size_hint() -> usize {
-        16
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        BitEncode::encode(AsRef::<[E]>::as_ref(self), e);
     }
 }
 
-impl<E: BitEncode> BitEncode for [E] {
-    #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        e.size_with_reserve(self.len(), E::size_hint());
-        for item in self {
-            BitEncode::encode(item, e);
+/// Maps are stored as a `size` followed by key and value pairs,
+/// in iteration order. See `SortedMap` for a fixed order.
+impl<K: BitEncode, V: BitEncode, H> BitEncode for HashMap<K, V, H> {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.size_with_reserve(self.len(), K::size_hint() + V::size_hint());
+
+        for (key, value) in self {
+            key.encode(e);
+            value.encode(e);
         }
     }
 }
 
-impl<'a, E: BitEncode> BitEncode for &'a [E] {
-    #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(*self, e);
+/// `HashMap` written with its entries ordered by key instead of in
+/// iteration order, so that equal maps always produce identical bytes,
+/// e.g. for deduplication or signatures. Decodes as a regular `HashMap`.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use bitsparrow::{Encoder, SortedMap};
+///
+/// let map: HashMap<u16, &str> = (0..100).map(|id| (id, "foo")).collect();
+/// let other: HashMap<u16, &str> = (0..100).rev().map(|id| (id, "foo")).collect();
+///
+/// let buffer = Encoder::encode(SortedMap(&map));
+///
+/// assert_eq!(buffer, Encoder::new().sorted_map(&other).end());
+/// assert_eq!(&buffer[1..3], &[0, 0]);
+/// ```
+#[derive(Debug)]
+pub struct SortedMap<'a, K, V, H>(pub &'a HashMap<K, V, H>);
+
+impl<'a, K: BitEncode + Ord, V: BitEncode, H> BitEncode for SortedMap<'a, K, V, H> {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        let mut entries: Vec<(&K, &V)> = self.0.iter().collect();
+
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        e.size_with_reserve(entries.len(), K::size_hint() + V::size_hint());
+
+        for (key, value) in entries {
+            key.encode(e);
+            value.encode(e);
+        }
     }
 }
 
-impl<E: BitEncode> BitEncode for Vec<E> {
-    #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(AsRef::<[E]>::as_ref(self), e);
+impl<'a> BitEncode for dyn DynEncode + 'a {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.with_dyn(|e| self.encode_dyn(e));
     }
 }
 
-impl<'a, E: BitEncode> BitEncode for &'a Vec<E> {
-    #[inline(always)]
-    fn encode(&self, e: &mut Encoder) {
-        BitEncode::encode(AsRef::<[E]>::as_ref(*self), e);
+macro_rules! impl_wrapper {
+    ($( $w:ident ),*) => {
+        $(
+            impl<T: BitEncode> BitEncode for $w<T> {
+                #[inline(always)]
+                fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+                    self.0.encode(e);
+                }
+
+                #[inline(always)]
+                fn size_hint() -> usize {
+                    T::size_hint()
+                }
+            }
+        )*
     }
 }
 
+impl_wrapper!(Wrapping, Saturating);
+
+impl<T: BitEncode> BitEncode for Range<T> {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        self.start.encode(e);
+        self.end.encode(e);
+    }
+
+    #[inline]
+    fn size_hint() -> usize {
+        2 * T::size_hint()
+    }
+}
+
+impl<T: BitEncode> BitEncode for RangeInclusive<T> {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        self.start().encode(e);
+        self.end().encode(e);
+    }
+
+    #[inline]
+    fn size_hint() -> usize {
+        2 * T::size_hint()
+    }
+}
+
+/// `Bound` is stored as a `u8` tag, `0` for `Included`, `1` for `Excluded`
+/// and `2` for `Unbounded`, followed by the value of the first two.
+impl<T: BitEncode> BitEncode for Bound<T> {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        match *self {
+            Bound::Included(ref value) => e.uint8(0).write(value),
+            Bound::Excluded(ref value) => e.uint8(1).write(value),
+            Bound::Unbounded           => e.uint8(2),
+        };
+    }
+
+    #[inline]
+    fn size_hint() -> usize {
+        1 + T::size_hint()
+    }
+}
+
+macro_rules! impl_pointer {
+    ($( $p:ident ),*) => {
+        $(
+            impl<T: BitEncode + ?Sized> BitEncode for $p<T> {
+                #[inline(always)]
+                fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+                    BitEncode::encode(&**self, e);
+                }
+
+                #[inline(always)]
+                fn size_hint() -> usize {
+                    T::size_hint()
+                }
+            }
+        )*
+    }
+}
+
+impl_pointer!(Box, Rc, Arc);
+
+impl BitEncode for () {
+    #[inline(always)]
+    fn encode<S: Sink>(&self, _: &mut Encoder<S>) {}
+}
+
+impl<T: ?Sized> BitEncode for PhantomData<T> {
+    #[inline(always)]
+    fn encode<S: Sink>(&self, _: &mut Encoder<S>) {}
+}
+
 macro_rules! impl_tuple {
     ($( $l:ident: $n:tt ),*) => {
         impl<$($l),*> BitEncode for ($($l),*) where
@@ -531,8 +1260,8 @@ macro_rules! impl_tuple {
             )*
         {
             #[inline(always)]
-            fn encode(&self, e: &mut Encoder) {
-                e.data.reserve(Self::size_hint());
+            fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+                e.sink.reserve(Self::size_hint());
 
                 $(
                     self.$n.encode(e);
@@ -544,22 +1273,6 @@ macro_rules! impl_tuple {
                 $( $l::size_hint() + )* 0
             }
         }
-
-        impl<'a, $($l),*> BitEncode for &'a ($($l),*) where
-            $(
-                $l: BitEncode,
-            )*
-        {
-            #[inline(always)]
-            fn encode(&self, e: &mut Encoder) {
-                BitEncode::encode(*self, e);
-            }
-
-            #[inline]
-            fn size_hint() -> usize {
-                $( $l::size_hint() + )* 0
-            }
-        }
     }
 }
 
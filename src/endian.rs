@@ -0,0 +1,94 @@
+//! Wrappers overriding the byte order of numeric values. BitSparrow
+//! stores numbers as big-endian, `Le` stores them as little-endian
+//! instead, e.g. to embed a register dump produced by hardware as is.
+//! `Be` makes the default explicit.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::endian::{Le, Be};
+//!
+//! let buffer = Encoder::encode((Le(0x0102u16), Be(0x0102u16)));
+//!
+//! assert_eq!(buffer, &[0x02, 0x01, 0x01, 0x02]);
+//! assert_eq!(Decoder::decode::<(Le<u16>, u16)>(&buffer).unwrap(), (Le(0x0102), 0x0102));
+//! ```
+
+use std::mem::size_of;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::Result;
+
+/// Number stored in little-endian byte order.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default, Hash)]
+pub struct Le<T>(pub T);
+
+/// Number stored in big-endian byte order, same as without a wrapper.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default, Hash)]
+pub struct Be<T>(pub T);
+
+macro_rules! impl_endian {
+    ($( $t:ty ),*) => {
+        $(
+            impl BitEncode for Le<$t> {
+                #[inline]
+                fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+                    e.append_raw(&self.0.to_le_bytes());
+                }
+
+                #[inline]
+                fn size_hint() -> usize {
+                    size_of::<$t>()
+                }
+            }
+
+            impl<'src> BitDecode<'src> for Le<$t> {
+                #[inline]
+                fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+                    let mut bytes = [0; size_of::<$t>()];
+                    bytes.copy_from_slice(&d.read_raw(size_of::<$t>())?);
+
+                    Ok(Le(<$t>::from_le_bytes(bytes)))
+                }
+            }
+
+            impl BitVerify for Le<$t> {
+                #[inline]
+                fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+                    d.skip_reserved(size_of::<$t>()).map(|_| ())
+                }
+            }
+
+            impl BitEncode for Be<$t> {
+                #[inline]
+                fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+                    self.0.encode(e);
+                }
+
+                #[inline]
+                fn size_hint() -> usize {
+                    <$t>::size_hint()
+                }
+            }
+
+            impl<'src> BitDecode<'src> for Be<$t> {
+                #[inline]
+                fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+                    <$t>::decode(d).map(Be)
+                }
+            }
+
+            impl BitVerify for Be<$t> {
+                #[inline]
+                fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+                    <$t>::verify(d)
+                }
+            }
+        )*
+    }
+}
+
+impl_endian!(u16, u32, u64, i16, i32, i64, f32, f64);
@@ -0,0 +1,105 @@
+//! Encoding of fieldless enums by the values of their discriminants,
+//! so an enum can share its numbering with a peer, such as a C enum,
+//! instead of depending on the order its variants are declared in.
+//! `bit_enum!` wraps the enum definition, every variant has to have an
+//! explicit discriminant, which is written as the `size` tag. Values
+//! below `128` take a single byte, same as the `u8` tags of `Either`
+//! and `Bound`. Decoding an unknown tag fails with `InvalidEnumVariant`.
+//!
+//! ```
+//! # #[macro_use] extern crate bitsparrow;
+//! # fn main() {
+//! use bitsparrow::{Encoder, Decoder, Error};
+//!
+//! bit_enum! {
+//!     /// Mirrors `enum log_level` of the C agent.
+//!     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+//!     pub enum LogLevel {
+//!         Error = 1,
+//!         Warning = 2,
+//!         Info = 4,
+//!         Debug = 200,
+//!     }
+//! }
+//!
+//! assert_eq!(Encoder::encode(LogLevel::Info), &[4]);
+//! assert_eq!(Encoder::encode(LogLevel::Debug), &[0x80, 200]);
+//! assert_eq!(Decoder::decode::<LogLevel>(&[2]).unwrap(), LogLevel::Warning);
+//!
+//! match Decoder::decode::<LogLevel>(&[3]) {
+//!     Err(Error::InvalidEnumVariant { tag: 3 }) => {},
+//!     other => panic!("unexpected result: {:?}", other),
+//! }
+//! # }
+//! ```
+//!
+//! Discriminants have to be non-negative, negative ones don't fit in
+//! a `size` and fail to compile.
+//!
+//! ```compile_fail
+//! # #[macro_use] extern crate bitsparrow;
+//! # fn main() {
+//! bit_enum! {
+//!     #[repr(i8)]
+//!     enum Direction {
+//!         Down = -1,
+//!         Up = 1,
+//!     }
+//! }
+//! # }
+//! ```
+
+/// Define a fieldless enum encoded by the values of its explicit
+/// discriminants, see the `enums` module.
+#[macro_export]
+macro_rules! bit_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $( $(#[$vmeta:meta])* $variant:ident = $value:expr ),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $( $(#[$vmeta])* $variant = $value ),+
+        }
+
+        $(
+            const _: () = assert!(
+                $name::$variant as i128 >= 0,
+                concat!("discriminant of `", stringify!($name), "::", stringify!($variant), "` is negative"),
+            );
+        )+
+
+        impl $crate::BitEncode for $name {
+            #[inline]
+            fn encode<S: $crate::Sink>(&self, e: &mut $crate::Encoder<S>) {
+                e.size(match *self {
+                    $( $name::$variant => $name::$variant as usize ),+
+                });
+            }
+        }
+
+        impl<'src> $crate::BitDecode<'src> for $name {
+            #[inline]
+            fn decode<S: $crate::Source<'src>>(d: &mut $crate::Decoder<'src, S>) -> $crate::Result<Self> {
+                let tag = d.size()?;
+
+                $(
+                    if tag == $name::$variant as usize {
+                        return Ok($name::$variant);
+                    }
+                )+
+
+                Err($crate::Error::InvalidEnumVariant { tag })
+            }
+        }
+
+        impl $crate::BitVerify for $name {
+            #[inline]
+            fn verify<'src, S: $crate::Source<'src>>(d: &mut $crate::Decoder<'src, S>) -> $crate::Result<()> {
+                <$name as $crate::BitDecode<'src>>::decode(d).map(|_| ())
+            }
+        }
+    };
+}
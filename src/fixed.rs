@@ -0,0 +1,109 @@
+//! Fixed size fields without a `size` prefix, for interop with fixed
+//! record formats such as file headers of other tools.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::fixed::FixedStr;
+//!
+//! let buffer = Encoder::encode(FixedStr::<6>::new("WAVE"));
+//!
+//! assert_eq!(buffer, b"WAVE\0\0");
+//! assert_eq!(Decoder::decode::<FixedStr<6>>(&buffer).unwrap().as_str(), "WAVE");
+//! ```
+
+use std::fmt;
+use std::str::from_utf8;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// String stored in exactly `N` bytes, padded with zero bytes. Strings
+/// longer than `N` bytes are truncated at the last character boundary
+/// that fits, trailing zero bytes are removed when decoding.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FixedStr<const N: usize>(String);
+
+impl<const N: usize> FixedStr<N> {
+    /// Create a `FixedStr`, truncating the string if it doesn't
+    /// fit in `N` bytes.
+    pub fn new(string: &str) -> FixedStr<N> {
+        let mut len = string.len().min(N);
+
+        while !string.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        FixedStr(string[..len].to_string())
+    }
+
+    /// The string without padding.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Obtain the string without padding.
+    #[inline]
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedStr<N> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<const N: usize> BitEncode for FixedStr<N> {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        let len = self.0.len().min(N);
+
+        e.append_raw(&self.0.as_bytes()[..len]).reserved(N - len);
+    }
+
+    #[inline]
+    fn size_hint() -> usize {
+        N
+    }
+}
+
+/// Decoding fails with `Utf8Encoding` if the bytes before the padding
+/// aren't a valid UTF-8 string.
+impl<'src, const N: usize> BitDecode<'src> for FixedStr<N> {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let bytes = d.read_raw(N)?;
+        let string = unpadded(&bytes)?;
+
+        d.allocate(string.len())?;
+
+        Ok(FixedStr(string.to_string()))
+    }
+}
+
+impl<const N: usize> BitVerify for FixedStr<N> {
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        match d.borrow_raw(N) {
+            Ok(bytes) => unpadded(bytes).map(|_| ()),
+            Err(Error::NotBorrowable) => {
+                let mut bytes = [0; N];
+
+                d.read_exact_into(&mut bytes)?;
+                unpadded(&bytes).map(|_| ())
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The string stored in `bytes`, without the padding.
+fn unpadded(bytes: &[u8]) -> Result<&str> {
+    let len = bytes.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+
+    from_utf8(&bytes[..len]).map_err(Into::into)
+}
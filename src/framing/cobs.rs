@@ -0,0 +1,130 @@
+//! Consistent Overhead Byte Stuffing. Encoded frames contain no zero
+//! bytes, which makes a single `0x00` a reliable packet delimiter on
+//! serial links.
+
+use utils::{Error, Result};
+
+/// Encode a buffer, appending the `0x00` delimiter at the end.
+///
+/// ```
+/// use bitsparrow::framing::cobs;
+///
+/// assert_eq!(cobs::encode(&[0x11, 0x00, 0x22]), &[0x02, 0x11, 0x02, 0x22, 0x00]);
+/// ```
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(data.len() + data.len() / 254 + 2);
+    let mut code_index = 0;
+    let mut code = 1u8;
+
+    frame.push(0);
+
+    for &byte in data {
+        if byte != 0 {
+            frame.push(byte);
+            code += 1;
+        }
+
+        if byte == 0 || code == 0xFF {
+            frame[code_index] = code;
+            code_index = frame.len();
+            code = 1;
+            frame.push(0);
+        }
+    }
+
+    frame[code_index] = code;
+    frame.push(0);
+    frame
+}
+
+/// Decode a single frame. The trailing delimiter is optional.
+pub fn decode(frame: &[u8]) -> Result<Vec<u8>> {
+    let frame = match frame.split_last() {
+        Some((&0, rest)) => rest,
+        _                => frame,
+    };
+
+    let mut data = Vec::with_capacity(frame.len());
+    let mut index = 0;
+
+    while index < frame.len() {
+        let code = frame[index] as usize;
+        let end = index + code;
+
+        if code == 0 {
+            return Err(Error::InvalidData);
+        }
+
+        if end > frame.len() {
+            return Err(Error::ReadingOutOfBounds);
+        }
+
+        let block = &frame[index + 1 .. end];
+
+        if block.contains(&0) {
+            return Err(Error::InvalidData);
+        }
+
+        data.extend_from_slice(block);
+        index = end;
+
+        if code < 0xFF && index < frame.len() {
+            data.push(0);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Iterate over all delimiter-separated frames in a buffer, decoding
+/// each of them. Empty frames between consecutive delimiters are skipped.
+///
+/// ```
+/// use bitsparrow::{Encoder, Decoder};
+/// use bitsparrow::framing::cobs;
+///
+/// let mut stream = cobs::encode(&Encoder::encode(0u32));
+/// stream.extend(cobs::encode(&Encoder::encode(1u32)));
+///
+/// for (i, frame) in cobs::frames(&stream).enumerate() {
+///     let value: u32 = Decoder::decode(&frame.unwrap()).unwrap();
+///
+///     assert_eq!(value, i as u32);
+/// }
+/// ```
+pub fn frames(data: &[u8]) -> Frames<'_> {
+    Frames {
+        data,
+    }
+}
+
+/// Iterator over COBS frames, created by `cobs::frames`.
+pub struct Frames<'src> {
+    data: &'src [u8],
+}
+
+impl<'src> Frames<'src> {
+    /// Bytes following the last complete frame. This will be
+    /// non-empty if the buffer is missing the final delimiter.
+    #[inline]
+    pub fn remainder(&self) -> &'src [u8] {
+        self.data
+    }
+}
+
+impl<'src> Iterator for Frames<'src> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let end = self.data.iter().position(|&byte| byte == 0)?;
+            let frame = &self.data[..end];
+
+            self.data = &self.data[end + 1..];
+
+            if !frame.is_empty() {
+                return Some(decode(frame));
+            }
+        }
+    }
+}
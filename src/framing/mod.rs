@@ -0,0 +1,5 @@
+//! Framing formats for sending finished buffers over transports
+//! that don't preserve message boundaries on their own.
+
+pub mod cobs;
+pub mod sync;
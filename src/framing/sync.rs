@@ -0,0 +1,152 @@
+//! Sync-word framing. Every frame starts with a marker followed by
+//! a `size` prefixed payload. After a corrupted frame the `Scanner`
+//! can skip garbage until the next marker, so a single bad frame
+//! doesn't take the rest of the stream with it.
+
+use encode::Encoder;
+use decode::Decoder;
+
+/// Marker used when the protocol doesn't define its own.
+pub const DEFAULT_MARKER: [u8; 4] = [0xB1, 0x75, 0xFA, 0xCE];
+
+/// Encode a single frame. The marker must not be empty.
+pub fn encode(marker: &[u8], payload: &[u8]) -> Vec<u8> {
+    assert!(!marker.is_empty(), "sync marker must not be empty");
+
+    let mut frame = Vec::with_capacity(marker.len() + 9 + payload.len());
+
+    frame.extend_from_slice(marker);
+    frame.extend_from_slice(&Encoder::encode(payload));
+    frame
+}
+
+/// Create a `Scanner` reading frames from a buffer.
+pub fn scan<'m, 'src>(marker: &'m [u8], data: &'src [u8]) -> Scanner<'m, 'src> {
+    assert!(!marker.is_empty(), "sync marker must not be empty");
+
+    Scanner {
+        marker,
+        data,
+        index: 0,
+        frame_start: None,
+        skipped: 0,
+    }
+}
+
+/// Iterator over payloads of sync-word frames, created by `sync::scan`.
+///
+/// ```
+/// use bitsparrow::{Encoder, Decoder};
+/// use bitsparrow::framing::sync::{self, DEFAULT_MARKER};
+///
+/// let mut stream = sync::encode(&DEFAULT_MARKER, &Encoder::encode("foo"));
+/// stream.extend_from_slice(&[0xB1, 0x00, 0x13, 0x37]); // line noise
+/// stream.extend(sync::encode(&DEFAULT_MARKER, &Encoder::encode("bar")));
+///
+/// let mut scanner = sync::scan(&DEFAULT_MARKER, &stream);
+///
+/// assert_eq!(Decoder::decode::<&str>(scanner.next().unwrap()).unwrap(), "foo");
+/// assert_eq!(Decoder::decode::<&str>(scanner.next().unwrap()).unwrap(), "bar");
+/// assert_eq!(scanner.next(), None);
+/// assert_eq!(scanner.skipped(), 4);
+/// ```
+pub struct Scanner<'m, 'src> {
+    marker: &'m [u8],
+    data: &'src [u8],
+    index: usize,
+    frame_start: Option<usize>,
+    skipped: usize,
+}
+
+impl<'m, 'src> Scanner<'m, 'src> {
+    /// Call after the last returned frame failed to decode. Instead of
+    /// trusting its length, scanning resumes right after its marker.
+    pub fn resync(&mut self) {
+        if let Some(start) = self.frame_start.take() {
+            self.skipped += 1;
+            self.index = start + 1;
+        }
+    }
+
+    /// Total number of bytes skipped while searching for markers.
+    #[inline]
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Unread bytes. At the end of iteration this is either empty or
+    /// holds what might be the beginning of a frame that is not
+    /// complete yet.
+    #[inline]
+    pub fn remainder(&self) -> &'src [u8] {
+        &self.data[self.index..]
+    }
+
+    fn find_marker(&self, from: usize) -> Option<usize> {
+        self.data[from..]
+            .windows(self.marker.len())
+            .position(|window| window == self.marker)
+            .map(|offset| from + offset)
+    }
+
+    // Returns the range of the payload of a frame starting at `start`,
+    // provided the whole frame fits within the buffer.
+    fn frame(&self, start: usize) -> Option<(usize, usize)> {
+        let header = start + self.marker.len();
+        let len = Decoder::new(&self.data[header..]).size().ok()?;
+        let payload = header + size_len(self.data[header]);
+        let end = payload.checked_add(len)?;
+
+        if end > self.data.len() {
+            return None;
+        }
+
+        Some((payload, end))
+    }
+}
+
+impl<'m, 'src> Iterator for Scanner<'m, 'src> {
+    type Item = &'src [u8];
+
+    fn next(&mut self) -> Option<&'src [u8]> {
+        let mut search = self.index;
+        let mut pending = None;
+
+        self.frame_start = None;
+
+        while let Some(start) = self.find_marker(search) {
+            if let Some((payload, end)) = self.frame(start) {
+                self.skipped += start - self.index;
+                self.index = end;
+                self.frame_start = Some(start);
+
+                return Some(&self.data[payload..end]);
+            }
+
+            // Either a false marker, or a frame that is not complete yet
+            pending = pending.or(Some(start));
+            search = start + 1;
+        }
+
+        // Keep anything that could still turn into a frame
+        let stop = match pending {
+            Some(start) => start,
+            None        => (self.data.len() + 1).saturating_sub(self.marker.len()).max(self.index),
+        };
+
+        self.skipped += stop - self.index;
+        self.index = stop;
+
+        None
+    }
+}
+
+// Number of bytes used by a `size` prefix, determined by its first byte
+#[inline]
+fn size_len(high: u8) -> usize {
+    if high & 0x80 == 0 {
+        1
+    } else {
+        (!high).leading_zeros() as usize + 1
+    }
+}
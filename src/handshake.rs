@@ -0,0 +1,131 @@
+use encode::{Encoder, BitEncode};
+use sink::Sink;
+use source::Source;
+use decode::{Decoder, BitDecode};
+use utils::Result;
+
+/// First message of a handshake, listing all protocol versions
+/// supported by the connecting peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hello {
+    pub versions: Vec<u16>,
+}
+
+/// Response to `Hello`, carrying the protocol version both peers
+/// are going to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Accept {
+    pub version: u16,
+}
+
+/// Set of protocol versions supported by a peer, each paired with
+/// options (of any type) the codec should use once that version has
+/// been negotiated.
+///
+/// ```
+/// use bitsparrow::handshake::{Protocol, Hello};
+///
+/// let server = Protocol::new()
+///                 .version(1, "legacy")
+///                 .version(2, "compact");
+///
+/// let hello = Hello { versions: vec![1, 2, 3] };
+/// let accept = server.accept(&hello).unwrap();
+///
+/// assert_eq!(accept.version, 2);
+/// assert_eq!(server.options(accept.version), Some(&"compact"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Protocol<O> {
+    versions: Vec<(u16, O)>,
+}
+
+impl<O> Default for Protocol<O> {
+    fn default() -> Self {
+        Protocol::new()
+    }
+}
+
+impl<O> Protocol<O> {
+    /// Create a new `Protocol` with no supported versions.
+    #[inline]
+    pub fn new() -> Protocol<O> {
+        Protocol {
+            versions: Vec::new(),
+        }
+    }
+
+    /// Add a supported version with its codec options, replacing
+    /// options previously set for the same version.
+    pub fn version(mut self, version: u16, options: O) -> Protocol<O> {
+        match self.versions.iter().position(|&(v, _)| v == version) {
+            Some(index) => self.versions[index].1 = options,
+            None        => self.versions.push((version, options)),
+        }
+
+        self
+    }
+
+    /// Create a `Hello` message listing all supported versions.
+    pub fn hello(&self) -> Hello {
+        Hello {
+            versions: self.versions.iter().map(|&(v, _)| v).collect(),
+        }
+    }
+
+    /// Pick the highest version supported by both peers, returning
+    /// `None` if there is no common version.
+    pub fn accept(&self, hello: &Hello) -> Option<Accept> {
+        self.versions
+            .iter()
+            .map(|&(v, _)| v)
+            .filter(|v| hello.versions.contains(v))
+            .max()
+            .map(|version| Accept { version })
+    }
+
+    /// Get codec options for a version, if supported.
+    pub fn options(&self, version: u16) -> Option<&O> {
+        self.versions
+            .iter()
+            .find(|&&(v, _)| v == version)
+            .map(|(_, options)| options)
+    }
+}
+
+impl BitEncode for Hello {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.write(&self.versions);
+    }
+}
+
+impl<'src> BitDecode<'src> for Hello {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(Hello {
+            versions: d.read()?,
+        })
+    }
+}
+
+impl BitEncode for Accept {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.uint16(self.version);
+    }
+
+    #[inline]
+    fn size_hint() -> usize {
+        2
+    }
+}
+
+impl<'src> BitDecode<'src> for Accept {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(Accept {
+            version: d.uint16()?,
+        })
+    }
+}
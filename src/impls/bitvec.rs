@@ -0,0 +1,84 @@
+use std::borrow::Cow;
+
+use bitvec::order::BitOrder;
+use bitvec::slice::BitSlice;
+use bitvec::store::BitStore;
+use bitvec::vec::BitVec;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// Read the number of bits and the bytes they are packed into, checking
+/// the unused bits of the last byte are zero.
+fn read_packed<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<(usize, Cow<'src, [u8]>)> {
+    let len = d.collection_size()?;
+    let packed = d.read_raw(len.div_ceil(8))?;
+
+    if len % 8 != 0 && packed[packed.len() - 1] >> (len % 8) != 0 {
+        return Err(Error::InvalidData);
+    }
+
+    Ok((len, packed))
+}
+
+/// `BitSlice` is stored as a `size` holding the number of bits, followed
+/// by the bits packed into bytes, starting from the lowest bit of each
+/// byte (the same order stacked `bool`s use). Unused bits of the last
+/// byte are zero. The layout doesn't depend on the store or the order
+/// of the slice.
+impl<T: BitStore, O: BitOrder> BitEncode for BitSlice<T, O> {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        let packed: Vec<u8> = self
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .by_vals()
+                    .enumerate()
+                    .fold(0, |byte, (shift, bit)| byte | (bit as u8) << shift)
+            })
+            .collect();
+
+        e.size(self.len()).append_raw(&packed);
+    }
+}
+
+impl<T: BitStore, O: BitOrder> BitEncode for BitVec<T, O> {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        self.as_bitslice().encode(e);
+    }
+}
+
+/// Decoding fails with `InvalidData` if any of the unused bits
+/// of the last byte are set.
+impl<'src, T: BitStore + 'src, O: BitOrder + 'src> BitDecode<'src> for BitVec<T, O> {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let (len, packed) = read_packed(d)?;
+        let mut vec = BitVec::with_capacity(len);
+
+        for index in 0..len {
+            vec.push(packed[index / 8] >> (index % 8) & 1 != 0);
+        }
+
+        Ok(vec)
+    }
+}
+
+impl<T: BitStore, O: BitOrder> BitVerify for BitSlice<T, O> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        read_packed(d).map(|_| ())
+    }
+}
+
+impl<T: BitStore, O: BitOrder> BitVerify for BitVec<T, O> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        BitSlice::<T, O>::verify(d)
+    }
+}
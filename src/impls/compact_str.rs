@@ -0,0 +1,36 @@
+use std::borrow::Cow;
+
+use compact_str::CompactString;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::Result;
+
+/// `CompactString` is stored as a `string`. Short strings decoded from
+/// a contiguous source are kept inline without allocating.
+impl BitEncode for CompactString {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.string(self);
+    }
+}
+
+impl<'src> BitDecode<'src> for CompactString {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(match d.read_cow_str()? {
+            Cow::Borrowed(string) => CompactString::new(string),
+            Cow::Owned(string) => CompactString::from(string),
+        })
+    }
+}
+
+impl BitVerify for CompactString {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        d.skip_str()
+    }
+}
@@ -0,0 +1,45 @@
+use either::Either;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+const LEFT: u8 = 0;
+const RIGHT: u8 = 1;
+
+/// `Either` is stored as a `u8` tag, `0` for `Left` and `1` for `Right`,
+/// followed by the value.
+impl<L: BitEncode, R: BitEncode> BitEncode for Either<L, R> {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        match *self {
+            Either::Left(ref left)   => e.uint8(LEFT).write(left),
+            Either::Right(ref right) => e.uint8(RIGHT).write(right),
+        };
+    }
+}
+
+impl<'src, L: BitDecode<'src>, R: BitDecode<'src>> BitDecode<'src> for Either<L, R> {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        match d.uint8()? {
+            LEFT  => d.read().map(Either::Left),
+            RIGHT => d.read().map(Either::Right),
+            tag   => Err(Error::InvalidEnumVariant { tag: tag as usize }),
+        }
+    }
+}
+
+impl<L: BitVerify, R: BitVerify> BitVerify for Either<L, R> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        match d.uint8()? {
+            LEFT  => L::verify(d),
+            RIGHT => R::verify(d),
+            tag   => Err(Error::InvalidEnumVariant { tag: tag as usize }),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use glam::{Vec2, Vec3, Vec4, Quat, Mat4};
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::Result;
+
+/// Vectors, quaternions and matrices are stored as consecutive `f32`
+/// components, matrices in column-major order.
+macro_rules! impl_glam {
+    ($( $t:ident: $n:expr, $to:ident, $from:expr; )*) => {
+        $(
+            impl BitEncode for $t {
+                #[inline]
+                fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+                    for component in &self.$to() {
+                        e.float32(*component);
+                    }
+                }
+
+                #[inline(always)]
+                fn size_hint() -> usize {
+                    $n * 4
+                }
+            }
+
+            impl<'src> BitDecode<'src> for $t {
+                #[inline]
+                fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+                    let mut components = [0.0; $n];
+
+                    for component in &mut components {
+                        *component = d.float32()?;
+                    }
+
+                    Ok($from(components))
+                }
+            }
+
+            impl BitVerify for $t {
+                #[inline]
+                fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+                    $t::decode(d).map(|_| ())
+                }
+            }
+        )*
+    }
+}
+
+impl_glam! {
+    Vec2: 2, to_array, Vec2::from_array;
+    Vec3: 3, to_array, Vec3::from_array;
+    Vec4: 4, to_array, Vec4::from_array;
+    Quat: 4, to_array, Quat::from_array;
+    Mat4: 16, to_cols_array, |cols| Mat4::from_cols_array(&cols);
+}
@@ -0,0 +1,29 @@
+//! Implementations of the encoding traits for types of optional
+//! dependencies, each enabled by a cargo feature of the same name.
+
+#[cfg(feature = "bitvec")]
+mod bitvec;
+
+#[cfg(feature = "compact_str")]
+mod compact_str;
+
+#[cfg(feature = "either")]
+mod either;
+
+#[cfg(feature = "glam")]
+mod glam;
+
+#[cfg(feature = "nalgebra")]
+mod nalgebra;
+
+#[cfg(feature = "num-complex")]
+mod num_complex;
+
+#[cfg(feature = "semver")]
+mod semver;
+
+#[cfg(feature = "smol_str")]
+mod smol_str;
+
+#[cfg(feature = "url")]
+mod url;
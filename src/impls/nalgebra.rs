@@ -0,0 +1,49 @@
+use nalgebra::{Scalar, SMatrix};
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::Result;
+
+/// Statically sized matrices, including vectors such as `Vector3`, are
+/// stored as consecutive components in column-major order, without a
+/// `size` prefix.
+impl<T, const R: usize, const C: usize> BitEncode for SMatrix<T, R, C> where
+    T: Scalar + BitEncode,
+{
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        for component in self.iter() {
+            component.encode(e);
+        }
+    }
+
+    #[inline(always)]
+    fn size_hint() -> usize {
+        R * C * T::size_hint()
+    }
+}
+
+impl<'src, T, const R: usize, const C: usize> BitDecode<'src> for SMatrix<T, R, C> where
+    T: Scalar + BitDecode<'src>,
+{
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        d.read_n::<T>(R * C).map(SMatrix::from_iterator)
+    }
+}
+
+impl<T, const R: usize, const C: usize> BitVerify for SMatrix<T, R, C> where
+    T: Scalar + BitVerify,
+{
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        for _ in 0..R * C {
+            T::verify(d)?;
+        }
+
+        Ok(())
+    }
+}
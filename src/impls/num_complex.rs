@@ -0,0 +1,40 @@
+use num_complex::Complex;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::Result;
+
+/// `Complex` is stored as the real part followed by the imaginary part.
+impl<T: BitEncode> BitEncode for Complex<T> {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        self.re.encode(e);
+        self.im.encode(e);
+    }
+
+    #[inline(always)]
+    fn size_hint() -> usize {
+        2 * T::size_hint()
+    }
+}
+
+impl<'src, T: BitDecode<'src>> BitDecode<'src> for Complex<T> {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Ok(Complex {
+            re: T::decode(d)?,
+            im: T::decode(d)?,
+        })
+    }
+}
+
+impl<V: BitVerify> BitVerify for Complex<V> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        V::verify(d)?;
+        V::verify(d)
+    }
+}
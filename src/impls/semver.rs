@@ -0,0 +1,32 @@
+use semver::Version;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// `Version` is stored as a `string`, decoding fails with `InvalidData`
+/// if the string is not a valid semantic version.
+impl BitEncode for Version {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.string(&self.to_string());
+    }
+}
+
+impl<'src> BitDecode<'src> for Version {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Version::parse(&d.read_cow_str()?).map_err(|_| Error::InvalidData)
+    }
+}
+
+/// Verifying parses the version, which allocates.
+impl BitVerify for Version {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        Version::decode(d).map(|_| ())
+    }
+}
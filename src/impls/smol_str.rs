@@ -0,0 +1,31 @@
+use smol_str::SmolStr;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::Result;
+
+/// `SmolStr` is stored as a `string`. Short strings are kept inline
+/// without allocating.
+impl BitEncode for SmolStr {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.string(self);
+    }
+}
+
+impl<'src> BitDecode<'src> for SmolStr {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        d.read_cow_str().map(SmolStr::new)
+    }
+}
+
+impl BitVerify for SmolStr {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        d.skip_str()
+    }
+}
@@ -0,0 +1,32 @@
+use url::Url;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// `Url` is stored as a `string`, decoding fails with `InvalidData`
+/// if the string is not a valid URL.
+impl BitEncode for Url {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.string(self.as_str());
+    }
+}
+
+impl<'src> BitDecode<'src> for Url {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        Url::parse(&d.read_cow_str()?).map_err(|_| Error::InvalidData)
+    }
+}
+
+/// Verifying parses the URL, which allocates.
+impl BitVerify for Url {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        Url::decode(d).map(|_| ())
+    }
+}
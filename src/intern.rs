@@ -0,0 +1,261 @@
+//! Strings interned for the lifetime of a connection, so identifiers
+//! repeated across messages are only sent once. Each peer keeps an
+//! `Interner` per direction, and both build the same table as long as
+//! messages are decoded in the order they were encoded, which makes it
+//! suitable for ordered transports such as TCP.
+//!
+//! Every interned string is stored as a `size` tag:
+//!
+//! - `0` is followed by a string that isn't added to the table, used
+//!   once the table is full,
+//! - `1` is followed by a string that is added to the table,
+//! - any other value refers to entry `tag - 2` of the table.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::intern::{Interner, Interned};
+//!
+//! let mut sender = Interner::new();
+//! let mut receiver = Interner::new();
+//!
+//! let event = Interned::from("user.login");
+//!
+//! let first = Encoder::new().write_with(&event, &mut sender).end();
+//! let second = Encoder::new().write_with(&event, &mut sender).end();
+//!
+//! assert_eq!(first.len(), 12);
+//! assert_eq!(second.len(), 1);
+//!
+//! let first: Interned = Decoder::new(&first).read_with(&mut receiver).unwrap();
+//! let second: Interned = Decoder::new(&second).read_with(&mut receiver).unwrap();
+//!
+//! assert_eq!(&*first, "user.login");
+//! assert_eq!(first, second);
+//! ```
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use encode::Encoder;
+use decode::Decoder;
+use context::{BitEncodeWith, BitDecodeWith};
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// Default number of strings an `Interner` holds, see `Interner::with_limit`.
+pub const DEFAULT_LIMIT: usize = 4096;
+
+const TAG_LITERAL: usize = 0;
+const TAG_INSERT: usize = 1;
+const TAG_OFFSET: usize = 2;
+
+/// Strings frozen with `Interner::freeze`, shared by all clones.
+#[derive(Debug, Clone, Default)]
+struct Frozen {
+    ids: HashMap<Arc<str>, usize>,
+    strings: Vec<Arc<str>>,
+}
+
+/// Table of strings shared by an `Encoder` and a `Decoder` on the
+/// other end of a connection, see the module documentation.
+///
+/// `Interner` is `Send` and `Sync`. A server can preload and `freeze`
+/// one, then clone it for every connection without copying the
+/// preloaded strings.
+#[derive(Debug, Clone)]
+pub struct Interner {
+    frozen: Arc<Frozen>,
+    ids: HashMap<Arc<str>, usize>,
+    strings: Vec<Arc<str>>,
+    limit: usize,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Interner::new()
+    }
+}
+
+impl Interner {
+    /// Create an empty `Interner` holding up to `DEFAULT_LIMIT` strings.
+    #[inline]
+    pub fn new() -> Interner {
+        Interner::with_limit(DEFAULT_LIMIT)
+    }
+
+    /// Create an empty `Interner` holding up to `limit` strings. Both
+    /// peers have to use the same limit, the `Decoder` fails with
+    /// `LimitExceeded` if the table would grow past it.
+    #[inline]
+    pub fn with_limit(limit: usize) -> Interner {
+        Interner {
+            frozen: Arc::default(),
+            ids: HashMap::new(),
+            strings: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Add strings both peers know about beforehand, such as the names
+    /// of all message types, so they are never sent in full. Strings
+    /// already in the table or past the limit are ignored.
+    pub fn preload<I, T>(mut self, strings: I) -> Interner where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        for string in strings {
+            let string = string.as_ref();
+
+            if self.id(string).is_none() && !self.is_full() {
+                self.insert(string.into());
+            }
+        }
+
+        self
+    }
+
+    /// Move all strings of the table into a part shared by clones of the
+    /// `Interner`, so cloning it for every connection is cheap no matter
+    /// how many strings were preloaded. Strings added afterwards are
+    /// kept by each clone separately.
+    ///
+    /// ```
+    /// use bitsparrow::intern::Interner;
+    ///
+    /// let template = Interner::new().preload(&["user.login", "user.logout"]).freeze();
+    /// let connection = template.clone();
+    ///
+    /// assert_eq!(connection.id("user.logout"), Some(1));
+    /// ```
+    pub fn freeze(mut self) -> Interner {
+        if self.strings.is_empty() {
+            return self;
+        }
+
+        let frozen = Arc::make_mut(&mut self.frozen);
+
+        for string in self.strings.drain(..) {
+            frozen.ids.insert(string.clone(), frozen.strings.len());
+            frozen.strings.push(string);
+        }
+
+        self.ids.clear();
+
+        self
+    }
+
+    /// Number of strings in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.frozen.strings.len() + self.strings.len()
+    }
+
+    /// Returns `true` if the table is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if no more strings can be added to the table.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.limit
+    }
+
+    /// Get the string with an id.
+    #[inline]
+    pub fn get(&self, id: usize) -> Option<&str> {
+        self.entry(id).map(|string| &**string)
+    }
+
+    /// Get the id of a string, if it's in the table.
+    #[inline]
+    pub fn id(&self, string: &str) -> Option<usize> {
+        self.frozen.ids.get(string).or_else(|| self.ids.get(string)).cloned()
+    }
+
+    /// Store a string, sending it in full only the first time.
+    pub fn write<S: Sink>(&mut self, e: &mut Encoder<S>, string: &str) {
+        if let Some(id) = self.id(string) {
+            e.size(id + TAG_OFFSET);
+        } else if self.is_full() {
+            e.size(TAG_LITERAL).string(string);
+        } else {
+            e.size(TAG_INSERT).string(string);
+            self.insert(string.into());
+        }
+    }
+
+    /// Read a string stored with `Interner::write`. Fails with
+    /// `InvalidData` if it refers to a string not in the table.
+    pub fn read<'src, S: Source<'src>>(&mut self, d: &mut Decoder<'src, S>) -> Result<Arc<str>> {
+        match d.size()? {
+            TAG_LITERAL => Ok(d.read::<String>()?.into()),
+            TAG_INSERT => {
+                if self.is_full() {
+                    return Err(Error::LimitExceeded);
+                }
+
+                let string: Arc<str> = d.read::<String>()?.into();
+
+                if self.id(&string).is_some() {
+                    return Err(Error::InvalidData);
+                }
+
+                self.insert(string.clone());
+
+                Ok(string)
+            },
+            tag => self.entry(tag - TAG_OFFSET).cloned().ok_or(Error::InvalidData),
+        }
+    }
+
+    fn entry(&self, id: usize) -> Option<&Arc<str>> {
+        match id.checked_sub(self.frozen.strings.len()) {
+            Some(id) => self.strings.get(id),
+            None     => self.frozen.strings.get(id),
+        }
+    }
+
+    fn insert(&mut self, string: Arc<str>) {
+        self.ids.insert(string.clone(), self.len());
+        self.strings.push(string);
+    }
+}
+
+/// String stored through an `Interner` passed as the context of
+/// `Encoder::write_with` and `Decoder::read_with`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interned(pub Arc<str>);
+
+impl<'a> From<&'a str> for Interned {
+    #[inline]
+    fn from(string: &'a str) -> Self {
+        Interned(string.into())
+    }
+}
+
+impl Deref for Interned {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl BitEncodeWith<Interner> for Interned {
+    #[inline]
+    fn encode_with<S: Sink>(&self, e: &mut Encoder<S>, interner: &mut Interner) {
+        interner.write(e, &self.0);
+    }
+}
+
+impl<'src> BitDecodeWith<'src, Interner> for Interned {
+    #[inline]
+    fn decode_with<S: Source<'src>>(d: &mut Decoder<'src, S>, interner: &mut Interner) -> Result<Self> {
+        interner.read(d).map(Interned)
+    }
+}
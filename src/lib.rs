@@ -53,10 +53,118 @@
 //! `true` if you have read the entire buffer, ensuring the entire
 //! buffer has been read.
 
+#![cfg_attr(feature = "forbid-unsafe", forbid(unsafe_code))]
+
+#[cfg(feature = "bincode")]
+extern crate bincode;
+
+#[cfg(feature = "bitvec")]
+extern crate bitvec;
+
+#[cfg(feature = "bytemuck")]
+extern crate bytemuck;
+
+#[cfg(feature = "bytes")]
+extern crate bytes;
+
+#[cfg(feature = "chrono")]
+extern crate chrono;
+
+#[cfg(feature = "compact_str")]
+extern crate compact_str;
+
+#[cfg(feature = "either")]
+extern crate either;
+
+#[cfg(feature = "glam")]
+extern crate glam;
+
+#[cfg(feature = "nalgebra")]
+extern crate nalgebra;
+
+#[cfg(feature = "num-complex")]
+extern crate num_complex;
+
+#[cfg(feature = "postcard")]
+extern crate postcard;
+
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+#[cfg(feature = "semver")]
+extern crate semver;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[cfg(feature = "smol_str")]
+extern crate smol_str;
+
+#[cfg(feature = "tokio-util")]
+extern crate tokio_util;
+
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+#[cfg(feature = "url")]
+extern crate url;
+
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
+#[macro_use]
+mod utils;
 mod encode;
 mod decode;
-mod utils;
+mod verify;
+mod context;
+mod impls;
+pub mod sink;
+pub mod source;
+pub mod batch;
+pub mod channel;
+pub mod chunked;
+#[cfg(feature = "zstd")]
+pub mod compress;
+pub mod container;
+pub mod dump;
+pub mod endian;
+pub mod enums;
+pub mod fixed;
+pub mod framing;
+pub mod handshake;
+pub mod intern;
+pub mod metadata;
+#[cfg(all(feature = "serde", any(feature = "bincode", feature = "postcard")))]
+pub mod migrate;
+pub mod net;
+#[cfg(feature = "bytemuck")]
+pub mod pod;
+pub mod profile;
+pub mod project;
+pub mod replicate;
+pub mod scratch;
+pub mod seq;
+pub mod testing;
+pub mod time;
+pub mod trace;
+
+pub use utils::{Error, FieldError, Result};
+pub use encode::{Encoder, SizeCounter, SortedMap, Mark, BitEncode, DynEncode};
+pub use sink::Sink;
+pub use decode::{Decoder, BitDecode, Messages, MapEntries, Recovery, DEFAULT_MAX_DEPTH};
+pub use source::Source;
+pub use verify::BitVerify;
+pub use context::{BitEncodeWith, BitDecodeWith};
+pub use container::Container;
+
+/// Version of the wire format produced by this crate, embedded by
+/// envelopes that opt into it, such as `Container::wire_version`.
+/// Buffers with a newer version are rejected with
+/// `Error::UnsupportedWireVersion`, older ones can be adapted to by
+/// checking `Decoder::wire_version`.
+pub const WIRE_VERSION: u16 = 2;
 
-pub use utils::Error;
-pub use encode::{Encoder, BitEncode};
-pub use decode::{Decoder, BitDecode};
+/// Largest integer JavaScript numbers represent exactly, `2^53 - 1`,
+/// see `Encoder::js_safe_uint`.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
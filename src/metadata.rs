@@ -0,0 +1,220 @@
+//! Standard metadata section preceding the body of a message, so
+//! routers and proxies can inspect the priority, tenant or type of a
+//! message and forward its body untouched, without knowing how to
+//! decode it.
+//!
+//! The section consists of a flags byte, followed by a `size` count of
+//! extensions, each stored as a `u16` key and `bytes` value. Keys below
+//! `256` are reserved for standard extensions such as `PRIORITY`, the
+//! rest are free to use by applications.
+//!
+//! ```
+//! use bitsparrow::Decoder;
+//! use bitsparrow::metadata::{self, Metadata};
+//!
+//! let meta = Metadata::new()
+//!     .extension(metadata::PRIORITY, 7u16)
+//!     .extension(metadata::TENANT, "acme");
+//!
+//! let buffer = meta.encode(("order", 42u32));
+//!
+//! // Router inspecting the metadata only
+//! let (meta, body) = Metadata::decode(&buffer).unwrap();
+//!
+//! assert_eq!(meta.get::<u16>(metadata::PRIORITY).unwrap().unwrap(), 7);
+//! assert_eq!(meta.get::<&str>(metadata::TENANT).unwrap().unwrap(), "acme");
+//!
+//! let forwarded = meta.encode_raw(body);
+//!
+//! assert_eq!(forwarded, buffer);
+//! assert_eq!(Decoder::decode::<(&str, u32)>(body).unwrap(), ("order", 42));
+//! ```
+
+use std::mem::size_of;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// Priority of the message.
+pub const PRIORITY: u16 = 1;
+
+/// Tenant or account the message belongs to.
+pub const TENANT: u16 = 2;
+
+/// Type of the message body, e.g. its name or numeric tag.
+pub const MESSAGE_TYPE: u16 = 3;
+
+/// Id correlating a response with its request.
+pub const CORRELATION_ID: u16 = 4;
+
+/// Metadata of a message, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metadata {
+    flags: u8,
+    extensions: Vec<(u16, Vec<u8>)>,
+}
+
+impl Metadata {
+    /// Create empty `Metadata` with all flags unset.
+    #[inline]
+    pub fn new() -> Metadata {
+        Metadata::default()
+    }
+
+    /// Set the application defined flags byte.
+    #[inline]
+    pub fn with_flags(mut self, flags: u8) -> Metadata {
+        self.flags = flags;
+
+        self
+    }
+
+    /// Add an extension with a value of any type, replacing an extension
+    /// previously set with the same key.
+    pub fn extension<E: BitEncode>(mut self, key: u16, value: E) -> Metadata {
+        self.insert(key, Encoder::encode(value));
+
+        self
+    }
+
+    /// Add an extension with a value encoded beforehand, replacing an
+    /// extension previously set with the same key.
+    pub fn insert(&mut self, key: u16, value: Vec<u8>) -> &mut Self {
+        match self.extensions.iter().position(|&(k, _)| k == key) {
+            Some(index) => self.extensions[index].1 = value,
+            None        => self.extensions.push((key, value)),
+        }
+
+        self
+    }
+
+    /// Remove an extension, returning its encoded value.
+    pub fn remove(&mut self, key: u16) -> Option<Vec<u8>> {
+        let index = self.extensions.iter().position(|&(k, _)| k == key)?;
+
+        Some(self.extensions.remove(index).1)
+    }
+
+    /// Flags byte of the metadata.
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Encoded value of an extension.
+    #[inline]
+    pub fn raw(&self, key: u16) -> Option<&[u8]> {
+        self.extensions.iter().find(|&&(k, _)| k == key).map(|(_, value)| &value[..])
+    }
+
+    /// Decode the value of an extension, returning `None` if it's not set.
+    #[inline]
+    pub fn get<'a, D: BitDecode<'a>>(&'a self, key: u16) -> Option<Result<D>> {
+        self.raw(key).map(Decoder::decode)
+    }
+
+    /// Keys and encoded values of all extensions, in the order they
+    /// were added.
+    #[inline]
+    pub fn extensions(&self) -> impl Iterator<Item = (u16, &[u8])> {
+        self.extensions.iter().map(|(key, value)| (*key, &value[..]))
+    }
+
+    /// Encode the metadata followed by a body into a new buffer.
+    pub fn encode<E: BitEncode>(&self, body: E) -> Vec<u8> {
+        Encoder::new().write(self).write(body).end()
+    }
+
+    /// Encode the metadata followed by a body encoded beforehand, e.g.
+    /// one obtained from `Metadata::decode`.
+    pub fn encode_raw(&self, body: &[u8]) -> Vec<u8> {
+        Encoder::new().write(self).append_raw(body).end()
+    }
+
+    /// Read the metadata from a buffer, returning it along with the body
+    /// following it, which is left as it is.
+    pub fn decode(data: &[u8]) -> Result<(Metadata, &[u8])> {
+        let mut d = Decoder::new(data);
+        let meta = d.read()?;
+
+        Ok((meta, d.take_rest()))
+    }
+}
+
+impl BitEncode for Metadata {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.uint8(self.flags).size(self.extensions.len());
+
+        for (key, value) in &self.extensions {
+            e.uint16(*key).bytes(value);
+        }
+    }
+}
+
+impl<'src> BitDecode<'src> for Metadata {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let flags = d.uint8()?;
+        let count = d.collection_size()?;
+
+        d.allocate(count.saturating_mul(size_of::<(u16, Vec<u8>)>()))?;
+
+        let mut meta = Metadata {
+            flags,
+            extensions: Vec::with_capacity(count.min(16)),
+        };
+        let mut seen = Keys::new();
+
+        for _ in 0..count {
+            let key = d.uint16()?;
+
+            if !seen.insert(key) {
+                return Err(Error::InvalidData);
+            }
+
+            meta.extensions.push((key, d.read()?));
+        }
+
+        Ok(meta)
+    }
+}
+
+impl BitVerify for Metadata {
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        d.uint8()?;
+
+        let mut seen = Keys::new();
+
+        for _ in 0..d.collection_size()? {
+            if !seen.insert(d.uint16()?) {
+                return Err(Error::InvalidData);
+            }
+
+            d.skip_bytes()?;
+        }
+
+        Ok(())
+    }
+}
+
+// Set of extension keys already read, one bit per possible key
+struct Keys([u64; 1024]);
+
+impl Keys {
+    fn new() -> Keys {
+        Keys([0; 1024])
+    }
+
+    // Returns `false` if the key was already in the set
+    fn insert(&mut self, key: u16) -> bool {
+        let (word, bit) = (key as usize / 64, 1 << (key % 64));
+        let seen = self.0[word] & bit != 0;
+
+        self.0[word] |= bit;
+
+        !seen
+    }
+}
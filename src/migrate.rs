@@ -0,0 +1,82 @@
+//! Conversion of buffers between BitSparrow and serde based formats,
+//! for fleets moving to BitSparrow one service at a time. Services that
+//! have migrated can keep talking to those that haven't by converting
+//! messages at the boundary, as long as the type implements both
+//! `BitEncode`/`BitDecode` and serde's `Serialize`/`Deserialize`.
+//!
+//! Requires the `serde` feature, along with `bincode` or `postcard` for
+//! the respective functions. Errors of those formats are reported as
+//! `Error::Io` of kind `InvalidData`.
+
+use std::error::Error as StdError;
+use std::io;
+
+use serde::{Serialize, Deserialize};
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use utils::{Error, Result};
+
+fn foreign_error<E>(err: E) -> Error where
+    E: Into<Box<dyn StdError + Send + Sync>>,
+{
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Decode a bincode buffer and encode the value with BitSparrow.
+/// Requires the `bincode` feature.
+///
+/// ```
+/// # extern crate bitsparrow;
+/// # extern crate bincode;
+/// # fn main() {
+/// use bitsparrow::{Encoder, migrate};
+///
+/// let legacy = bincode::serialize(&(42u32, "sparrow")).unwrap();
+/// let buffer = migrate::bincode_to_bitsparrow::<(u32, &str)>(&legacy).unwrap();
+///
+/// assert_eq!(buffer, Encoder::encode((42u32, "sparrow")));
+/// assert_eq!(migrate::bitsparrow_to_bincode::<(u32, &str)>(&buffer).unwrap(), legacy);
+/// # }
+/// ```
+#[cfg(feature = "bincode")]
+pub fn bincode_to_bitsparrow<'a, T>(data: &'a [u8]) -> Result<Vec<u8>> where
+    T: Deserialize<'a> + BitEncode,
+{
+    let value: T = ::bincode::deserialize(data).map_err(foreign_error)?;
+
+    Ok(Encoder::encode(value))
+}
+
+/// Decode a BitSparrow buffer and encode the value with bincode.
+/// Requires the `bincode` feature.
+#[cfg(feature = "bincode")]
+pub fn bitsparrow_to_bincode<'a, T>(data: &'a [u8]) -> Result<Vec<u8>> where
+    T: BitDecode<'a> + Serialize,
+{
+    let value: T = Decoder::decode(data)?;
+
+    ::bincode::serialize(&value).map_err(foreign_error)
+}
+
+/// Decode a postcard buffer and encode the value with BitSparrow.
+/// Requires the `postcard` feature.
+#[cfg(feature = "postcard")]
+pub fn postcard_to_bitsparrow<'a, T>(data: &'a [u8]) -> Result<Vec<u8>> where
+    T: Deserialize<'a> + BitEncode,
+{
+    let value: T = ::postcard::from_bytes(data).map_err(foreign_error)?;
+
+    Ok(Encoder::encode(value))
+}
+
+/// Decode a BitSparrow buffer and encode the value with postcard.
+/// Requires the `postcard` feature.
+#[cfg(feature = "postcard")]
+pub fn bitsparrow_to_postcard<'a, T>(data: &'a [u8]) -> Result<Vec<u8>> where
+    T: BitDecode<'a> + Serialize,
+{
+    let value: T = Decoder::decode(data)?;
+
+    ::postcard::to_allocvec(&value).map_err(foreign_error)
+}
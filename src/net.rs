@@ -0,0 +1,42 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+
+/// Largest payload that fits in a single UDP datagram over IPv4.
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// Encode a value and send it as a single datagram. Fails with
+/// `InvalidInput` without sending anything if the encoded value is
+/// larger than `MAX_DATAGRAM_SIZE`.
+pub fn send_to<A, E>(socket: &UdpSocket, addr: A, val: E) -> io::Result<usize> where
+    A: ToSocketAddrs,
+    E: BitEncode,
+{
+    let buffer = Encoder::encode(val);
+
+    if buffer.len() > MAX_DATAGRAM_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "encoded value exceeds the maximum datagram size"
+        ));
+    }
+
+    socket.send_to(&buffer, addr)
+}
+
+/// Receive a single datagram into `buf` and decode it. The datagram has
+/// to contain exactly one value.
+///
+/// **Note:** Datagrams larger than `buf` are truncated by the OS, use
+/// a buffer of `MAX_DATAGRAM_SIZE` bytes if the peer isn't known to
+/// send smaller ones.
+pub fn recv_from<'buf, D>(socket: &UdpSocket, buf: &'buf mut [u8]) -> io::Result<(D, SocketAddr)> where
+    D: BitDecode<'buf>,
+{
+    let (len, addr) = socket.recv_from(buf)?;
+    let buf: &'buf [u8] = buf;
+
+    Ok((Decoder::decode(&buf[..len])?, addr))
+}
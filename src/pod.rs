@@ -0,0 +1,157 @@
+//! Typed views of plain numeric arrays, available with the `bytemuck`
+//! feature. The elements are stored big-endian one after another
+//! without a `size` prefix, same as if each was written with
+//! `Encoder::write`, which lets large arrays be decoded in bulk.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//!
+//! let mut encoder = Encoder::new();
+//! for sample in &[1u16, 2, 3] {
+//!     encoder.write(sample);
+//! }
+//! let buffer = encoder.end();
+//!
+//! let mut decoder = Decoder::new(&buffer);
+//! let samples = decoder.pod_slice::<u16>(3).unwrap();
+//!
+//! assert_eq!(&*samples, &[1, 2, 3]);
+//! assert!(decoder.end());
+//! ```
+
+use std::borrow::Cow;
+#[cfg(not(feature = "forbid-unsafe"))]
+use std::mem::MaybeUninit;
+use std::mem::size_of;
+
+use bytemuck::{self, Pod};
+
+use decode::Decoder;
+use source::Source;
+use utils::{Error, Result};
+
+/// Numeric type that can be viewed directly in the decoded buffer.
+pub trait Element: Pod {
+    /// Convert a value read in big-endian byte order to the native one.
+    fn to_native(self) -> Self;
+}
+
+macro_rules! impl_element {
+    ($( $t:ty ),*) => {
+        $(
+            impl Element for $t {
+                #[inline]
+                fn to_native(self) -> Self {
+                    <$t>::from_be(self)
+                }
+            }
+        )*
+    }
+}
+
+impl_element!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Element for f32 {
+    #[inline]
+    fn to_native(self) -> Self {
+        f32::from_bits(u32::from_be(self.to_bits()))
+    }
+}
+
+impl Element for f64 {
+    #[inline]
+    fn to_native(self) -> Self {
+        f64::from_bits(u64::from_be(self.to_bits()))
+    }
+}
+
+impl<'src, S: Source<'src>> Decoder<'src, S> {
+    /// Read `len` elements without a `size` prefix. The elements are
+    /// borrowed from the source when it is borrowable, the data is
+    /// properly aligned for `T`, and the target is big-endian (or `T`
+    /// is a single byte). Otherwise they are copied and converted to
+    /// the native byte order. Copied elements count towards the limit
+    /// set with `set_max_allocation`.
+    pub fn pod_slice<T: Element>(&mut self, len: usize) -> Result<Cow<'src, [T]>> {
+        let size = len.checked_mul(size_of::<T>()).ok_or(Error::ReadingOutOfBounds)?;
+        let raw = match self.borrow_raw(size) {
+            Ok(bytes) => {
+                if cfg!(target_endian = "big") || size_of::<T>() == 1 {
+                    if let Ok(slice) = bytemuck::try_cast_slice(bytes) {
+                        return Ok(Cow::Borrowed(slice));
+                    }
+                }
+
+                self.allocate(size)?;
+
+                Cow::Borrowed(bytes)
+            },
+            Err(Error::NotBorrowable) => {
+                self.allocate(size)?;
+
+                self.read_raw(size)?
+            },
+            Err(err) => return Err(err),
+        };
+
+        Ok(Cow::Owned(
+            raw.chunks_exact(size_of::<T>())
+               .map(|chunk| bytemuck::pod_read_unaligned::<T>(chunk).to_native())
+               .collect()
+        ))
+    }
+
+    /// Read `out.len()` elements without a `size` prefix directly into
+    /// uninitialized memory provided by the caller, such as a mapped
+    /// GPU staging buffer, converting them to the native byte order.
+    /// Returns the initialized elements. On error, part of `out` may
+    /// have been written to.
+    ///
+    /// Data that can't be borrowed from the source is read through
+    /// a small buffer on the stack, nothing is allocated either way.
+    /// Not available with the `forbid-unsafe` feature.
+    ///
+    /// ```
+    /// use std::mem::MaybeUninit;
+    /// use bitsparrow::{Encoder, Decoder};
+    ///
+    /// let buffer = Encoder::new().write(1.5f32).write(-2.0f32).end();
+    /// let mut staging = [MaybeUninit::<f32>::uninit(); 2];
+    ///
+    /// let mut decoder = Decoder::new(&buffer);
+    /// let vertices = decoder.pod_into_uninit(&mut staging).unwrap();
+    ///
+    /// assert_eq!(vertices, &[1.5, -2.0]);
+    /// ```
+    #[cfg(not(feature = "forbid-unsafe"))]
+    pub fn pod_into_uninit<'a, T: Element>(&mut self, out: &'a mut [MaybeUninit<T>]) -> Result<&'a mut [T]> {
+        let size = out.len().checked_mul(size_of::<T>()).ok_or(Error::ReadingOutOfBounds)?;
+
+        match self.borrow_raw(size) {
+            Ok(bytes) => convert_into(bytes, out),
+            Err(Error::NotBorrowable) => {
+                let mut scratch = [0; 4096];
+                let per_chunk = scratch.len() / size_of::<T>();
+
+                for chunk in out.chunks_mut(per_chunk) {
+                    let bytes = &mut scratch[..chunk.len() * size_of::<T>()];
+
+                    self.read_exact_into(bytes)?;
+                    convert_into(bytes, chunk);
+                }
+            },
+            Err(err) => return Err(err),
+        }
+
+        // Every element of `out` has been written by `convert_into`
+        Ok(unsafe { &mut *(out as *mut [MaybeUninit<T>] as *mut [T]) })
+    }
+}
+
+#[cfg(not(feature = "forbid-unsafe"))]
+#[inline]
+fn convert_into<T: Element>(bytes: &[u8], out: &mut [MaybeUninit<T>]) {
+    for (slot, chunk) in out.iter_mut().zip(bytes.chunks_exact(size_of::<T>())) {
+        slot.write(bytemuck::pod_read_unaligned::<T>(chunk).to_native());
+    }
+}
@@ -0,0 +1,187 @@
+use std::cmp::Reverse;
+use std::fmt;
+
+/// Breakdown of encoded sizes per message type and field, collected
+/// by an `Encoder` created with `Encoder::profiled`.
+///
+/// Every value stored with `Encoder::write` outside of another value
+/// counts as a message of its type. Fields are recorded whenever a
+/// `BitEncode` implementation stores them with `Encoder::field`, nested
+/// fields are recorded with their full path (`header.id`).
+///
+/// ```
+/// use bitsparrow::{Encoder, BitEncode, Sink};
+///
+/// struct Login<'a> {
+///     user: &'a str,
+///     token: [u8; 16],
+/// }
+///
+/// impl<'a> BitEncode for Login<'a> {
+///     fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+///         e.field("user", self.user)
+///          .field("token", &self.token);
+///     }
+/// }
+///
+/// let mut encoder = Encoder::profiled();
+/// encoder.write(Login { user: "sparrow", token: [0; 16] });
+///
+/// let profile = encoder.take_profile().unwrap();
+/// let login = &profile.messages()[0];
+///
+/// assert_eq!(login.count, 1);
+/// assert_eq!(login.bytes, 24);
+/// assert_eq!(login.field("user").unwrap().bytes, 8);
+/// assert_eq!(login.field("token").unwrap().bytes, 16);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    messages: Vec<MessageProfile>,
+}
+
+/// Encoded sizes of all messages of a single type.
+#[derive(Debug, Clone)]
+pub struct MessageProfile {
+    /// Name of the message type.
+    pub name: &'static str,
+    /// Number of messages encoded.
+    pub count: usize,
+    /// Total number of bytes of all messages.
+    pub bytes: usize,
+    /// Fields of the message in order they were first encoded.
+    pub fields: Vec<FieldProfile>,
+}
+
+/// Encoded size of a single field across all messages.
+#[derive(Debug, Clone)]
+pub struct FieldProfile {
+    /// Path to the field, nested fields are separated with dots.
+    pub path: String,
+    /// Number of times the field was encoded.
+    pub count: usize,
+    /// Total number of bytes used by the field.
+    pub bytes: usize,
+}
+
+impl Profile {
+    /// All profiled message types in order they were first encoded.
+    /// Fields encoded outside of a message are attributed to an
+    /// unnamed message (`""`) with a count of zero.
+    #[inline]
+    pub fn messages(&self) -> &[MessageProfile] {
+        &self.messages
+    }
+
+    /// Get the profile of a message type by its name.
+    pub fn message(&self, name: &str) -> Option<&MessageProfile> {
+        self.messages.iter().find(|message| message.name == name)
+    }
+
+    fn entry(&mut self, name: &'static str) -> &mut MessageProfile {
+        let index = match self.messages.iter().position(|message| message.name == name) {
+            Some(index) => index,
+            None => {
+                self.messages.push(MessageProfile {
+                    name,
+                    count: 0,
+                    bytes: 0,
+                    fields: Vec::new(),
+                });
+
+                self.messages.len() - 1
+            }
+        };
+
+        &mut self.messages[index]
+    }
+}
+
+impl MessageProfile {
+    /// Get the profile of a field by its path.
+    pub fn field(&self, path: &str) -> Option<&FieldProfile> {
+        self.fields.iter().find(|field| field.path == path)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for message in &self.messages {
+            writeln!(f, "{}: {} messages, {} bytes", message.name, message.count, message.bytes)?;
+
+            let mut fields: Vec<&FieldProfile> = message.fields.iter().collect();
+            fields.sort_by_key(|field| Reverse(field.bytes));
+
+            for field in fields {
+                let share = match message.bytes {
+                    0     => 0.0,
+                    total => field.bytes as f64 * 100.0 / total as f64,
+                };
+
+                writeln!(f, "    {:<24} {:>10} bytes {:>6.1}%", field.path, field.bytes, share)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Profiling state attached to an `Encoder`.
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    profile: Profile,
+    message: Option<&'static str>,
+    path: Vec<&'static str>,
+}
+
+impl Profiler {
+    /// Returns `true` if the value about to be written starts a new message.
+    #[inline]
+    pub fn begin_message(&mut self, name: &'static str) -> bool {
+        if self.message.is_some() || !self.path.is_empty() {
+            return false;
+        }
+
+        self.message = Some(name);
+
+        true
+    }
+
+    pub fn end_message(&mut self, bytes: usize) {
+        if let Some(name) = self.message.take() {
+            let message = self.profile.entry(name);
+
+            message.count += 1;
+            message.bytes += bytes;
+        }
+    }
+
+    #[inline]
+    pub fn begin_field(&mut self, name: &'static str) {
+        self.path.push(name);
+    }
+
+    pub fn end_field(&mut self, bytes: usize) {
+        let path = self.path.join(".");
+        let message = self.profile.entry(self.message.unwrap_or(""));
+
+        self.path.pop();
+
+        match message.fields.iter_mut().find(|field| field.path == path) {
+            Some(field) => {
+                field.count += 1;
+                field.bytes += bytes;
+            },
+            None => message.fields.push(FieldProfile {
+                path,
+                count: 1,
+                bytes,
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn into_profile(self) -> Profile {
+        self.profile
+    }
+}
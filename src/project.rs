@@ -0,0 +1,261 @@
+//! Decoding of selected fields of a composite type, skipping over the
+//! rest without decoding them. A `Layout` lists the names and types of
+//! the fields in the order they are encoded, from which a `Projection`
+//! of the fields of interest is selected. Reading a value with it walks
+//! every field, but only checks the ones that weren't selected the
+//! same way `BitVerify` does, and just borrows the encoded bytes of the
+//! selected ones, which are decoded on demand.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::project::Layout;
+//!
+//! let layout = Layout::new()
+//!     .field::<u64>("id")
+//!     .field::<String>("name")
+//!     .field::<Vec<String>>("tags")
+//!     .field::<f64>("score");
+//!
+//! let projection = layout.select(&["score", "id"]).unwrap();
+//!
+//! let rows = Encoder::new()
+//!     .write((1u64, "first", vec!["a", "b"], 0.5f64))
+//!     .write((2u64, "second", Vec::<&str>::new(), 1.5f64))
+//!     .end();
+//!
+//! let mut decoder = Decoder::new(&rows);
+//! let mut total = 0.0;
+//!
+//! while !decoder.end() {
+//!     let fields = projection.read(&mut decoder).unwrap();
+//!     let id: u64 = fields.get(1).unwrap().unwrap();
+//!
+//!     total += id as f64 * fields.get::<f64>(0).unwrap().unwrap();
+//! }
+//!
+//! assert_eq!(total, 3.5);
+//! ```
+//!
+//! For random access to the fields of a single value, a `View` locates
+//! them lazily on first access instead.
+//!
+//! Consecutive `bool` fields share a byte, so they can only be selected
+//! together, declared as a single tuple field.
+
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use utils::{Error, Result};
+
+type Skip = fn(&mut Decoder) -> Result<()>;
+
+fn skip<V: BitVerify>(d: &mut Decoder) -> Result<()> {
+    d.verify::<V>()
+}
+
+/// Names and types of the fields of a composite type, in the order
+/// they are encoded.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    fields: Vec<(&'static str, Skip)>,
+}
+
+impl Layout {
+    /// Create an empty `Layout`.
+    #[inline]
+    pub fn new() -> Layout {
+        Layout::default()
+    }
+
+    /// Add the next field of a given type.
+    #[inline]
+    pub fn field<V: BitVerify>(mut self, name: &'static str) -> Layout {
+        self.fields.push((name, skip::<V>));
+
+        self
+    }
+
+    /// Number of fields.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns `true` if there are no fields.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Create a lazy `View` of a value with this layout, encoded at the
+    /// start of `data`.
+    #[inline]
+    pub fn view<'src>(&self, data: &'src [u8]) -> View<'_, 'src> {
+        View {
+            layout: self,
+            data,
+            ends: Vec::new(),
+        }
+    }
+
+    /// Select the fields to decode, in the order they will be returned
+    /// in by `Projection::read`. Fails with `InvalidData` annotated with
+    /// the name of a field that isn't in the layout, or is selected more
+    /// than once.
+    pub fn select(&self, names: &[&str]) -> Result<Projection> {
+        let mut slots = vec![None; self.fields.len()];
+
+        for (slot, name) in names.iter().enumerate() {
+            let index = self.fields
+                .iter()
+                .position(|&(field, _)| field == *name)
+                .filter(|&index| slots[index].is_none())
+                .ok_or_else(|| Error::InvalidData.at_field(name))?;
+
+            slots[index] = Some(slot);
+        }
+
+        Ok(Projection {
+            fields: self.fields.iter().zip(slots).map(|(&(name, skip), slot)| (name, skip, slot)).collect(),
+            selected: names.len(),
+        })
+    }
+}
+
+/// Fields selected from a `Layout`, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Projection {
+    fields: Vec<(&'static str, Skip, Option<usize>)>,
+    selected: usize,
+}
+
+impl Projection {
+    /// Read a value, borrowing the encoded bytes of the selected fields.
+    /// The decoder is left past the last field of the value. Errors are
+    /// annotated with the name of the field, same as by
+    /// `Decoder::read_field`.
+    pub fn read<'src>(&self, d: &mut Decoder<'src>) -> Result<Fields<'src>> {
+        let mut values = vec![&[][..]; self.selected];
+
+        for &(name, skip, slot) in &self.fields {
+            let rest = d.remaining();
+            let start = d.position();
+
+            skip(d).map_err(|err| err.at_field(name))?;
+
+            if let Some(slot) = slot {
+                values[slot] = &rest[..d.position() - start];
+            }
+        }
+
+        Ok(Fields { values })
+    }
+}
+
+/// Encoded selected fields of a value, read with `Projection::read`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fields<'src> {
+    values: Vec<&'src [u8]>,
+}
+
+impl<'src> Fields<'src> {
+    /// Number of fields.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if no fields were selected.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Encoded bytes of the field at a given index of the selection.
+    #[inline]
+    pub fn raw(&self, index: usize) -> Option<&'src [u8]> {
+        self.values.get(index).cloned()
+    }
+
+    /// Decode the field at a given index of the selection.
+    #[inline]
+    pub fn get<D: BitDecode<'src>>(&self, index: usize) -> Option<Result<D>> {
+        self.raw(index).map(Decoder::decode)
+    }
+}
+
+/// Lazy view of a value with a given `Layout`, locating its fields in
+/// the buffer only when they are accessed. Offsets of the fields found
+/// so far are cached, so every field is walked over at most once, and
+/// fields past the last one accessed aren't walked at all.
+///
+/// ```
+/// use bitsparrow::Encoder;
+/// use bitsparrow::project::Layout;
+///
+/// let layout = Layout::new()
+///     .field::<String>("title")
+///     .field::<Vec<u32>>("samples")
+///     .field::<u16>("rate")
+///     .field::<String>("notes");
+///
+/// let buffer = Encoder::encode(("take 3", vec![1u32; 1000], 48000u16, "noisy"));
+/// let mut view = layout.view(&buffer);
+///
+/// assert_eq!(view.get::<u16>("rate").unwrap(), 48000);
+/// assert_eq!(view.get::<&str>("title").unwrap(), "take 3");
+/// assert_eq!(view.size().unwrap(), buffer.len());
+/// ```
+#[derive(Debug, Clone)]
+pub struct View<'l, 'src> {
+    layout: &'l Layout,
+    data: &'src [u8],
+    ends: Vec<usize>,
+}
+
+impl<'l, 'src> View<'l, 'src> {
+    fn locate(&mut self, index: usize) -> Result<()> {
+        while self.ends.len() <= index {
+            let (name, skip) = self.layout.fields[self.ends.len()];
+            let mut d = Decoder::new_at(self.data, self.ends.last().cloned().unwrap_or(0));
+
+            skip(&mut d).map_err(|err| err.at_field(name))?;
+
+            self.ends.push(d.position());
+        }
+
+        Ok(())
+    }
+
+    /// Encoded bytes of a field. Fails with `InvalidData` annotated with
+    /// the name of the field if it isn't in the layout.
+    pub fn raw(&mut self, name: &str) -> Result<&'src [u8]> {
+        let index = self.layout.fields
+            .iter()
+            .position(|&(field, _)| field == name)
+            .ok_or_else(|| Error::InvalidData.at_field(name))?;
+
+        self.locate(index)?;
+
+        let start = match index {
+            0 => 0,
+            _ => self.ends[index - 1],
+        };
+
+        Ok(&self.data[start..self.ends[index]])
+    }
+
+    /// Decode a field, errors are annotated with its name.
+    pub fn get<D: BitDecode<'src>>(&mut self, name: &str) -> Result<D> {
+        let raw = self.raw(name)?;
+
+        Decoder::decode(raw).map_err(|err| err.at_field(name))
+    }
+
+    /// Size of the entire encoded value, locating all of its fields.
+    pub fn size(&mut self) -> Result<usize> {
+        match self.layout.fields.len() {
+            0 => Ok(0),
+            len => self.locate(len - 1).map(|_| self.ends[len - 1]),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Replication of a value changing over time, such as the state of a
+//! game or a document, by sending a full snapshot first and patches
+//! with the changes afterwards. `Replicator` produces the updates on
+//! one end, `Applier` validates their sequence numbers and applies
+//! them on the other.
+//!
+//! How a value is diffed and patched is up to its `Diff` implementation.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder, BitEncode, BitDecode, Sink, Source, Result};
+//! use bitsparrow::replicate::{Diff, Replicator, Applier};
+//!
+//! #[derive(Clone, Debug, PartialEq)]
+//! struct Score {
+//!     home: u16,
+//!     away: u16,
+//! }
+//!
+//! impl BitEncode for Score {
+//!     fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+//!         e.uint16(self.home).uint16(self.away);
+//!     }
+//! }
+//!
+//! impl<'src> BitDecode<'src> for Score {
+//!     fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+//!         Ok(Score { home: d.uint16()?, away: d.uint16()? })
+//!     }
+//! }
+//!
+//! impl Diff for Score {
+//!     // Points scored by each side
+//!     type Patch = (u16, u16);
+//!
+//!     fn diff(&self, old: &Self) -> Option<(u16, u16)> {
+//!         match self == old {
+//!             true => None,
+//!             false => Some((self.home - old.home, self.away - old.away)),
+//!         }
+//!     }
+//!
+//!     fn apply(&mut self, (home, away): (u16, u16)) -> Result<()> {
+//!         self.home += home;
+//!         self.away += away;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut replicator = Replicator::new();
+//! let mut applier = Applier::<Score>::new();
+//!
+//! let mut score = Score { home: 0, away: 0 };
+//! let snapshot = replicator.update(&score).unwrap();
+//!
+//! score.home += 3;
+//! let patch = replicator.update(&score).unwrap();
+//!
+//! assert_eq!(replicator.update(&score), None);
+//!
+//! applier.apply(&snapshot).unwrap();
+//! assert_eq!(applier.apply(&patch).unwrap(), &score);
+//! ```
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use seq::Seq;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// Values that can be replicated by sending the changes between two
+/// versions of them.
+pub trait Diff: Sized {
+    /// Changes between two versions of the value.
+    type Patch;
+
+    /// Get the changes from `old` to `self`, or `None` if there are none.
+    fn diff(&self, old: &Self) -> Option<Self::Patch>;
+
+    /// Apply changes produced by `diff`.
+    fn apply(&mut self, patch: Self::Patch) -> Result<()>;
+}
+
+/// Single update sent by a `Replicator`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Update<T, P> {
+    /// Full value, replacing any previous one.
+    Snapshot { seq: Seq, value: T },
+    /// Changes to the value of the preceding update.
+    Patch { seq: Seq, patch: P },
+}
+
+impl<T, P> Update<T, P> {
+    /// Sequence number of the update.
+    #[inline]
+    pub fn seq(&self) -> Seq {
+        match *self {
+            Update::Snapshot { seq, .. } => seq,
+            Update::Patch { seq, .. }    => seq,
+        }
+    }
+}
+
+/// Produces encoded updates of a value, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Replicator<T> {
+    last: Option<T>,
+    next: Seq,
+    interval: usize,
+    patches: usize,
+}
+
+impl<T> Default for Replicator<T> {
+    fn default() -> Self {
+        Replicator::new()
+    }
+}
+
+impl<T> Replicator<T> {
+    /// Create a `Replicator` sending a snapshot only with the first update.
+    #[inline]
+    pub fn new() -> Replicator<T> {
+        Replicator {
+            last: None,
+            next: Seq(0),
+            interval: usize::MAX,
+            patches: 0,
+        }
+    }
+
+    /// Send a snapshot instead of a patch after every `interval`
+    /// patches, so receivers joining late or losing updates catch up
+    /// eventually.
+    #[inline]
+    pub fn snapshot_interval(mut self, interval: usize) -> Replicator<T> {
+        self.interval = interval;
+
+        self
+    }
+
+    /// Sequence number the next update will have.
+    #[inline]
+    pub fn next_seq(&self) -> Seq {
+        self.next
+    }
+}
+
+impl<T: Diff + Clone + BitEncode> Replicator<T> where T::Patch: BitEncode {
+    /// Encode an update to the current version of the value, a snapshot
+    /// if it's the first one or it's time for one, otherwise a patch with
+    /// the changes since the last update. Returns `None` if the value
+    /// is the same as in the last update.
+    pub fn update(&mut self, value: &T) -> Option<Vec<u8>> {
+        let patch = match self.last {
+            Some(ref last) => value.diff(last)?,
+            None => return Some(self.snapshot(value)),
+        };
+
+        if self.patches >= self.interval {
+            return Some(self.snapshot(value));
+        }
+
+        let seq = self.advance();
+
+        self.patches += 1;
+        self.last = Some(value.clone());
+
+        Some(Encoder::encode(Update::<&T, _>::Patch { seq, patch }))
+    }
+
+    /// Encode a snapshot of the current version of the value, e.g. when
+    /// a receiver asks to be resynchronized.
+    pub fn snapshot(&mut self, value: &T) -> Vec<u8> {
+        let seq = self.advance();
+
+        self.patches = 0;
+        self.last = Some(value.clone());
+
+        Encoder::encode(Update::<_, T::Patch>::Snapshot { seq, value })
+    }
+
+    fn advance(&mut self) -> Seq {
+        let seq = self.next;
+        self.next = seq.next();
+        seq
+    }
+}
+
+/// Applies updates produced by a `Replicator`.
+#[derive(Debug, Clone)]
+pub struct Applier<T> {
+    value: Option<T>,
+    seq: Seq,
+}
+
+impl<T> Default for Applier<T> {
+    fn default() -> Self {
+        Applier::new()
+    }
+}
+
+impl<T> Applier<T> {
+    /// Create an `Applier` waiting for a snapshot.
+    #[inline]
+    pub fn new() -> Applier<T> {
+        Applier {
+            value: None,
+            seq: Seq(0),
+        }
+    }
+
+    /// Current version of the value, `None` until a snapshot arrives.
+    #[inline]
+    pub fn value(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    /// Sequence number of the last update applied, if any.
+    #[inline]
+    pub fn seq(&self) -> Option<Seq> {
+        self.value.as_ref().map(|_| self.seq)
+    }
+}
+
+impl<T> Applier<T> where
+    T: Diff + for<'a> BitDecode<'a>,
+    T::Patch: for<'a> BitDecode<'a>,
+{
+    /// Decode and apply an update, returning the current version of the
+    /// value. Snapshots are always accepted. Patches fail with
+    /// `InvalidData` before the first snapshot, and with `OutOfSequence`
+    /// unless they directly follow the last update, in which case the
+    /// value is kept and a snapshot is needed to continue. If applying a
+    /// patch fails, the value is dropped until the next snapshot.
+    pub fn apply(&mut self, buffer: &[u8]) -> Result<&T> {
+        match Decoder::decode::<Update<T, T::Patch>>(buffer)? {
+            Update::Snapshot { seq, value } => {
+                self.seq = seq;
+                self.value = Some(value);
+            },
+            Update::Patch { seq, patch } => {
+                let expected = self.seq.next();
+                let value = self.value.as_mut().ok_or(Error::InvalidData)?;
+
+                if seq != expected {
+                    return Err(Error::OutOfSequence { expected: expected.0, received: seq.0 });
+                }
+
+                if let Err(err) = value.apply(patch) {
+                    self.value = None;
+
+                    return Err(err);
+                }
+
+                self.seq = seq;
+            },
+        }
+
+        Ok(self.value.as_ref().expect("value was just set"))
+    }
+}
+
+/// `Update` is stored as a `size` tag, `0` for snapshots and `1` for
+/// patches, followed by the sequence number and the value or patch.
+impl<T: BitEncode, P: BitEncode> BitEncode for Update<T, P> {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        match *self {
+            Update::Snapshot { seq, ref value } => e.size(0).write(seq).write(value),
+            Update::Patch { seq, ref patch }    => e.size(1).write(seq).write(patch),
+        };
+    }
+}
+
+impl<'src, T: BitDecode<'src>, P: BitDecode<'src>> BitDecode<'src> for Update<T, P> {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        match d.size()? {
+            0 => Ok(Update::Snapshot { seq: d.read()?, value: d.read()? }),
+            1 => Ok(Update::Patch { seq: d.read()?, patch: d.read()? }),
+            tag => Err(Error::InvalidEnumVariant { tag }),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Per-thread pool of buffers for decoding owned strings and byte
+//! vectors, enabled with `Decoder::set_scratch_buffers`. Every decoded
+//! `String` or `Vec<u8>` still owns its buffer, but instead of a fresh
+//! allocation it gets one from the pool of the current thread, which
+//! is refilled with buffers given back with `recycle` once the values
+//! aren't needed anymore, or preallocated with `reserve`.
+//!
+//! Retained buffers are limited to `MAX_POOLED` per thread, holding at
+//! most `MAX_CAPACITY` bytes each, larger ones are freed when recycled.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder, scratch};
+//!
+//! let buffer = Encoder::encode(vec!["user.login", "user.logout"]);
+//!
+//! scratch::reserve(16, 32);
+//!
+//! for _ in 0..100 {
+//!     let mut decoder = Decoder::new(&buffer);
+//!     decoder.set_scratch_buffers(true);
+//!
+//!     let names: Vec<String> = decoder.read().unwrap();
+//!     assert_eq!(names, ["user.login", "user.logout"]);
+//!
+//!     names.into_iter().for_each(scratch::recycle);
+//! }
+//!
+//! assert_eq!(scratch::pooled(), 16);
+//! ```
+
+use std::cell::RefCell;
+
+/// Largest number of buffers retained by the pool of a thread.
+pub const MAX_POOLED: usize = 1024;
+
+/// Largest capacity of a buffer retained by the pool, in bytes.
+pub const MAX_CAPACITY: usize = 4096;
+
+thread_local! {
+    static POOL: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Give back the buffer of a string to the pool of the current thread.
+#[inline]
+pub fn recycle(string: String) {
+    recycle_bytes(string.into_bytes());
+}
+
+/// Give back a byte buffer to the pool of the current thread.
+pub fn recycle_bytes(mut vec: Vec<u8>) {
+    if vec.capacity() == 0 || vec.capacity() > MAX_CAPACITY {
+        return;
+    }
+
+    vec.clear();
+
+    POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+
+        if pool.len() < MAX_POOLED {
+            pool.push(vec);
+        }
+    });
+}
+
+/// Preallocate buffers of `capacity` bytes until the pool of the current
+/// thread holds `count` of them, or the limits are reached.
+pub fn reserve(count: usize, capacity: usize) {
+    let capacity = capacity.min(MAX_CAPACITY);
+
+    POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        let count = count.min(MAX_POOLED);
+
+        while pool.len() < count {
+            pool.push(Vec::with_capacity(capacity));
+        }
+    });
+}
+
+/// Number of buffers in the pool of the current thread.
+#[inline]
+pub fn pooled() -> usize {
+    POOL.with(|pool| pool.borrow().len())
+}
+
+fn take() -> Vec<u8> {
+    POOL.with(|pool| pool.borrow_mut().pop()).unwrap_or_default()
+}
+
+/// Copy `data` into a buffer taken from the pool, or a new one if the
+/// pool is empty.
+pub(crate) fn copy(data: &[u8]) -> Vec<u8> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+
+    let mut vec = take();
+
+    vec.extend_from_slice(data);
+    vec
+}
+
+/// Copy `string` into a buffer taken from the pool, or a new one if the
+/// pool is empty.
+pub(crate) fn copy_str(string: &str) -> String {
+    if string.is_empty() {
+        return String::new();
+    }
+
+    let mut buffer = String::from_utf8(take()).expect("pooled buffers are empty");
+
+    buffer.push_str(string);
+    buffer
+}
@@ -0,0 +1,205 @@
+//! Sequence numbers and acknowledgements for reliable messaging built
+//! on top of BitSparrow. Both are stored as varints in the same format
+//! as `size`, the ranges of `AckRange` as deltas from the end of the
+//! preceding range, keeping frequent small acknowledgements small.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::seq::{Seq, AckRange};
+//!
+//! let mut acks = AckRange::new();
+//! acks.insert(Seq(1_000_000));
+//! acks.insert(Seq(1_000_001));
+//! acks.insert(Seq(1_000_005));
+//!
+//! let buffer = Encoder::encode(&acks);
+//!
+//! // count, then (gap, length) pairs
+//! assert_eq!(buffer.len(), 1 + 3 + 1 + 1 + 1);
+//! assert_eq!(Decoder::decode::<AckRange>(&buffer).unwrap(), acks);
+//! assert!(acks.contains(Seq(1_000_001)));
+//! assert!(!acks.contains(Seq(1_000_002)));
+//! ```
+
+use std::mem::size_of;
+use std::ops::Range;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::{Error, Result};
+
+/// Sequence number of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Seq(pub u64);
+
+impl Seq {
+    /// The sequence number following this one.
+    #[inline]
+    pub fn next(self) -> Seq {
+        Seq(self.0 + 1)
+    }
+}
+
+/// Set of acknowledged sequence numbers, kept as sorted ranges that
+/// neither overlap nor touch each other.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct AckRange(Vec<Range<u64>>);
+
+impl AckRange {
+    /// Create an empty `AckRange`.
+    #[inline]
+    pub fn new() -> AckRange {
+        AckRange(Vec::new())
+    }
+
+    /// Create an `AckRange` from arbitrary ranges, sorting and merging
+    /// them as necessary. Empty ranges are ignored.
+    pub fn from_ranges<I: IntoIterator<Item = Range<u64>>>(ranges: I) -> AckRange {
+        let mut sorted: Vec<Range<u64>> = ranges.into_iter().filter(|range| range.start < range.end).collect();
+        sorted.sort_by_key(|range| range.start);
+
+        let mut acks = AckRange::new();
+
+        for range in sorted {
+            match acks.0.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => acks.0.push(range),
+            }
+        }
+
+        acks
+    }
+
+    /// Sorted ranges of acknowledged sequence numbers.
+    #[inline]
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.0
+    }
+
+    /// Returns `true` if no sequence number is acknowledged.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns `true` if the sequence number is acknowledged.
+    pub fn contains(&self, seq: Seq) -> bool {
+        match self.0.binary_search_by_key(&seq.0, |range| range.start) {
+            Ok(_) => true,
+            Err(0) => false,
+            Err(index) => seq.0 < self.0[index - 1].end,
+        }
+    }
+
+    /// Acknowledge a sequence number, merging it with adjacent ranges.
+    /// `u64::MAX` is past the end of any range and is ignored.
+    pub fn insert(&mut self, seq: Seq) {
+        let end = match seq.0.checked_add(1) {
+            Some(end) if !self.contains(seq) => end,
+            _ => return,
+        };
+
+        let index = self.0.partition_point(|range| range.end < seq.0);
+
+        if index < self.0.len() && self.0[index].end == seq.0 {
+            self.0[index].end = end;
+
+            if index + 1 < self.0.len() && self.0[index + 1].start == end {
+                let next = self.0.remove(index + 1);
+                self.0[index].end = next.end;
+            }
+        } else if index < self.0.len() && self.0[index].start == end {
+            self.0[index].start = seq.0;
+        } else {
+            self.0.insert(index, seq.0..end);
+        }
+    }
+}
+
+impl BitEncode for Seq {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.write_varint(self.0, 0);
+    }
+}
+
+impl<'src> BitDecode<'src> for Seq {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        d.read_varint().map(Seq)
+    }
+}
+
+impl BitVerify for Seq {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        d.read_varint().map(|_| ())
+    }
+}
+
+/// `AckRange` is stored as a `size` holding the number of ranges,
+/// followed by the gap from the end of the preceding range (or zero)
+/// to the start of each range, and its length. Decoding fails with
+/// `InvalidData` if the ranges are empty, overlap or touch.
+impl BitEncode for AckRange {
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        let mut end = 0;
+
+        e.size(self.0.len());
+
+        for range in &self.0 {
+            e.write_varint(range.start - end, 0);
+            e.write_varint(range.end - range.start, 0);
+            end = range.end;
+        }
+    }
+}
+
+impl<'src> BitDecode<'src> for AckRange {
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        let count = d.collection_size()?;
+        let mut ranges = Vec::new();
+
+        d.allocate(count.saturating_mul(size_of::<Range<u64>>()))?;
+        read_ranges(d, count, |range| ranges.push(range))?;
+
+        Ok(AckRange(ranges))
+    }
+}
+
+impl BitVerify for AckRange {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        let count = d.collection_size()?;
+
+        read_ranges(d, count, |_| {})
+    }
+}
+
+/// Read `count` ranges of an `AckRange`, checking they are valid and
+/// passing each of them to `f`.
+fn read_ranges<'src, S, F>(d: &mut Decoder<'src, S>, count: usize, mut f: F) -> Result<()> where
+    S: Source<'src>,
+    F: FnMut(Range<u64>),
+{
+    let mut end = 0u64;
+
+    for index in 0..count {
+        let gap = d.read_varint()?;
+        let len = d.read_varint()?;
+
+        if len == 0 || (index != 0 && gap == 0) {
+            return Err(Error::InvalidData);
+        }
+
+        let start = end.checked_add(gap).ok_or(Error::InvalidData)?;
+        end = start.checked_add(len).ok_or(Error::InvalidData)?;
+
+        f(start..end);
+    }
+
+    Ok(())
+}
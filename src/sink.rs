@@ -0,0 +1,767 @@
+//! Output backends of the `Encoder`.
+//!
+//! The `Encoder` is generic over the `Sink` it writes to, defaulting
+//! to `Vec<u8>`. This module provides sinks for writing into a fixed
+//! size buffer (`Slice`), an `io::Write` (`Writer`), and a sink only
+//! counting bytes (`Counter`), a sink writing to recycled buffers
+//! (`Pooled`), and an adapter hashing bytes as they are written to
+//! another sink (`Hashed`). With the `bytes` feature
+//! enabled `BytesMut` is a sink as well.
+//!
+//! ```
+//! use bitsparrow::Encoder;
+//! use bitsparrow::sink::Slice;
+//!
+//! let mut buffer = [0u8; 8];
+//! let len = Encoder::with_sink(Slice::new(&mut buffer))
+//!                 .uint8(100)
+//!                 .string("Foo")
+//!                 .end()
+//!                 .unwrap();
+//!
+//! assert_eq!(&buffer[..len], &[0x64,0x03,0x46,0x6f,0x6f]);
+//! ```
+
+use std::hash::Hasher;
+use std::io::{self, IoSlice, Write};
+
+#[cfg(feature = "bytes")]
+use bytes::BytesMut;
+
+use utils::{Error, Result};
+
+/// Destination for bytes produced by an `Encoder`.
+///
+/// Besides appending bytes the sink has to be able to modify the last
+/// byte it received, which is how consecutive `bool`s are stacked into
+/// a single byte.
+pub trait Sink {
+    /// Number of bytes written to the sink so far.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if nothing has been written to the sink yet.
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hint that at least `additional` more bytes are about to be written.
+    #[inline]
+    fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
+
+    /// Append a single byte.
+    fn push_byte(&mut self, byte: u8);
+
+    /// Append a slice of bytes.
+    fn push_slice(&mut self, bytes: &[u8]);
+
+    /// Append an owned buffer, sinks that can take ownership of it
+    /// may avoid copying.
+    #[inline]
+    fn push_vec(&mut self, bytes: Vec<u8>) {
+        self.push_slice(&bytes);
+    }
+
+    /// Set `bits` on the last byte written. Never called on an empty sink.
+    fn or_last(&mut self, bits: u8);
+
+    /// Up to `max` of the last bytes written, used for `Debug` output.
+    /// Sinks that don't keep the bytes return an empty slice.
+    #[inline]
+    fn tail(&self, max: usize) -> &[u8] {
+        let _ = max;
+
+        &[]
+    }
+}
+
+/// Last `max` bytes of a slice.
+#[inline]
+fn tail(bytes: &[u8], max: usize) -> &[u8] {
+    &bytes[bytes.len().saturating_sub(max)..]
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    #[inline(always)]
+    fn reserve(&mut self, additional: usize) {
+        (**self).reserve(additional);
+    }
+
+    #[inline(always)]
+    fn push_byte(&mut self, byte: u8) {
+        (**self).push_byte(byte);
+    }
+
+    #[inline(always)]
+    fn push_slice(&mut self, bytes: &[u8]) {
+        (**self).push_slice(bytes);
+    }
+
+    #[inline(always)]
+    fn push_vec(&mut self, bytes: Vec<u8>) {
+        (**self).push_vec(bytes);
+    }
+
+    #[inline(always)]
+    fn or_last(&mut self, bits: u8) {
+        (**self).or_last(bits);
+    }
+
+    #[inline]
+    fn tail(&self, max: usize) -> &[u8] {
+        (**self).tail(max)
+    }
+}
+
+/// Sink that can discard bytes written to it, required for
+/// `Encoder::rollback`.
+pub trait Truncate: Sink {
+    /// Discard everything written past the first `len` bytes.
+    fn truncate(&mut self, len: usize);
+
+    /// Clear all bits not set in `mask` on the last byte written.
+    /// Never called on an empty sink.
+    fn and_last(&mut self, mask: u8);
+}
+
+impl Sink for Vec<u8> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    #[inline(always)]
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional);
+    }
+
+    #[inline(always)]
+    fn push_byte(&mut self, byte: u8) {
+        Vec::push(self, byte);
+    }
+
+    #[inline(always)]
+    fn push_slice(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+
+    #[inline]
+    fn push_vec(&mut self, mut bytes: Vec<u8>) {
+        if Vec::is_empty(self) {
+            ::std::mem::swap(self, &mut bytes);
+        } else {
+            self.extend_from_slice(&bytes);
+        }
+    }
+
+    #[inline(always)]
+    fn or_last(&mut self, bits: u8) {
+        let last = Vec::len(self) - 1;
+
+        self[last] |= bits;
+    }
+    #[inline]
+    fn tail(&self, max: usize) -> &[u8] {
+        tail(self, max)
+    }
+}
+
+impl Truncate for Vec<u8> {
+    #[inline]
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+
+    #[inline]
+    fn and_last(&mut self, mask: u8) {
+        let last = Vec::len(self) - 1;
+
+        self[last] &= mask;
+    }
+}
+
+/// Sink that discards all bytes and only counts them.
+/// See `SizeCounter`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counter {
+    len: usize,
+}
+
+impl Sink for Counter {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn push_byte(&mut self, _: u8) {
+        self.len += 1;
+    }
+
+    #[inline(always)]
+    fn push_slice(&mut self, bytes: &[u8]) {
+        self.len += bytes.len();
+    }
+
+    #[inline(always)]
+    fn or_last(&mut self, _: u8) {}
+}
+
+impl Truncate for Counter {
+    #[inline]
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    #[inline]
+    fn and_last(&mut self, _: u8) {}
+}
+
+/// Sink writing into a fixed size buffer. Bytes that don't fit are
+/// counted but discarded, the overflow is reported once the `Encoder`
+/// is finished.
+#[derive(Debug)]
+pub struct Slice<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Slice<'a> {
+    /// Create a sink writing from the start of `buf`.
+    #[inline]
+    pub fn new(buf: &'a mut [u8]) -> Slice<'a> {
+        Slice {
+            buf,
+            len: 0,
+        }
+    }
+
+    /// Number of bytes written, or `BufferTooSmall` with the number
+    /// of bytes required if they did not fit in the buffer.
+    #[inline]
+    pub fn written(&self) -> Result<usize> {
+        if self.len > self.buf.len() {
+            return Err(Error::BufferTooSmall { needed: self.len });
+        }
+
+        Ok(self.len)
+    }
+
+    /// Start writing from the beginning of the buffer again.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<'a> Sink for Slice<'a> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn push_byte(&mut self, byte: u8) {
+        if let Some(slot) = self.buf.get_mut(self.len) {
+            *slot = byte;
+        }
+
+        self.len += 1;
+    }
+
+    #[inline(always)]
+    fn push_slice(&mut self, bytes: &[u8]) {
+        if let Some(free) = self.buf.get_mut(self.len..) {
+            let fit = free.len().min(bytes.len());
+
+            free[..fit].copy_from_slice(&bytes[..fit]);
+        }
+
+        self.len += bytes.len();
+    }
+
+    #[inline(always)]
+    fn or_last(&mut self, bits: u8) {
+        if let Some(last) = self.buf.get_mut(self.len - 1) {
+            *last |= bits;
+        }
+    }
+    #[inline]
+    fn tail(&self, max: usize) -> &[u8] {
+        tail(&self.buf[..self.len.min(self.buf.len())], max)
+    }
+}
+
+impl<'a> Truncate for Slice<'a> {
+    #[inline]
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    #[inline]
+    fn and_last(&mut self, mask: u8) {
+        if let Some(last) = self.buf.get_mut(self.len - 1) {
+            *last &= mask;
+        }
+    }
+}
+
+/// Size of the staging buffer of `Writer`.
+const STAGING_SIZE: usize = 8 * 1024;
+
+/// Slices at least this long are passed to `Writer` as is with
+/// vectored IO, instead of being copied to the staging buffer.
+const VECTORED_SIZE: usize = 1024;
+
+/// Sink writing to an `io::Write` through a staging buffer. Large
+/// `bytes` and `string` payloads bypass the staging buffer, they are
+/// written together with the staged bytes with `Write::write_vectored`.
+///
+/// Writing is infallible from the point of view of the `Encoder`, the
+/// first IO error is stored and returned when the `Encoder` is finished,
+/// nothing is written past it.
+#[derive(Debug)]
+pub struct Writer<W> {
+    writer: W,
+    staging: Vec<u8>,
+    flushed: usize,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Create a sink writing to `writer`.
+    #[inline]
+    pub fn new(writer: W) -> Writer<W> {
+        Writer {
+            writer,
+            staging: Vec::with_capacity(STAGING_SIZE),
+            flushed: 0,
+            error: None,
+        }
+    }
+
+    /// Write out all staged bytes, returning the total number of bytes
+    /// written since the last flush, or the first error encountered.
+    pub fn flush(&mut self) -> io::Result<usize> {
+        self.write_out(self.staging.len());
+
+        if let Some(err) = self.error.take() {
+            self.flushed = 0;
+
+            return Err(err);
+        }
+
+        self.writer.flush()?;
+
+        Ok(::std::mem::replace(&mut self.flushed, 0))
+    }
+
+    /// Obtain the underlying writer. Staged bytes are discarded
+    /// unless `flush` was called beforehand.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Keep the last byte staged, so bools can still be stacked on it.
+    #[inline]
+    fn write_staged(&mut self) {
+        if self.staging.len() >= STAGING_SIZE {
+            let len = self.staging.len() - 1;
+
+            self.write_out(len);
+        }
+    }
+
+    fn write_out(&mut self, len: usize) {
+        if self.error.is_none() {
+            if let Err(err) = self.writer.write_all(&self.staging[..len]) {
+                self.error = Some(err);
+            }
+        }
+
+        self.staging.drain(..len);
+        self.flushed += len;
+    }
+
+    /// Write out all staged bytes followed by `bytes`, except for its
+    /// last byte, which stays staged.
+    fn write_vectored(&mut self, bytes: &[u8]) {
+        let (body, last) = bytes.split_at(bytes.len() - 1);
+
+        if self.error.is_none() {
+            let mut slices = [IoSlice::new(&self.staging), IoSlice::new(body)];
+            let mut slices = &mut slices[..];
+
+            // Drops the staging buffer if it's empty
+            IoSlice::advance_slices(&mut slices, 0);
+
+            while !slices.is_empty() {
+                match self.writer.write_vectored(slices) {
+                    Ok(0) => {
+                        self.error = Some(io::ErrorKind::WriteZero.into());
+                        break;
+                    },
+                    Ok(written) => IoSlice::advance_slices(&mut slices, written),
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                    Err(err) => {
+                        self.error = Some(err);
+                        break;
+                    },
+                }
+            }
+        }
+
+        self.flushed += self.staging.len() + body.len();
+        self.staging.clear();
+        self.staging.extend_from_slice(last);
+    }
+}
+
+impl<W: Write> Sink for Writer<W> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.flushed + self.staging.len()
+    }
+
+    #[inline(always)]
+    fn push_byte(&mut self, byte: u8) {
+        self.staging.push(byte);
+        self.write_staged();
+    }
+
+    #[inline(always)]
+    fn push_slice(&mut self, bytes: &[u8]) {
+        if bytes.len() >= VECTORED_SIZE {
+            return self.write_vectored(bytes);
+        }
+
+        self.staging.extend_from_slice(bytes);
+        self.write_staged();
+    }
+
+    #[inline(always)]
+    fn or_last(&mut self, bits: u8) {
+        let last = self.staging.len() - 1;
+
+        self.staging[last] |= bits;
+    }
+    #[inline]
+    fn tail(&self, max: usize) -> &[u8] {
+        tail(&self.staging, max)
+    }
+}
+
+/// Source of buffers for the `Pooled` sink, letting pool or slab
+/// allocators recycle buffers the `Encoder` writes into.
+pub trait BufferProvider {
+    /// Obtain an empty buffer, possibly with capacity left over from
+    /// its previous use.
+    fn acquire(&mut self) -> Vec<u8>;
+
+    /// Give back a buffer that is no longer needed.
+    fn release(&mut self, buf: Vec<u8>);
+}
+
+impl<P: BufferProvider + ?Sized> BufferProvider for &mut P {
+    #[inline]
+    fn acquire(&mut self) -> Vec<u8> {
+        (**self).acquire()
+    }
+
+    #[inline]
+    fn release(&mut self, buf: Vec<u8>) {
+        (**self).release(buf);
+    }
+}
+
+/// Sink writing to buffers obtained from a `BufferProvider`. Finishing
+/// the `Encoder` hands out the current buffer and acquires a new one,
+/// see `Encoder::with_provider`.
+///
+/// ```
+/// use bitsparrow::Encoder;
+/// use bitsparrow::sink::BufferProvider;
+///
+/// #[derive(Default)]
+/// struct Pool(Vec<Vec<u8>>);
+///
+/// impl BufferProvider for Pool {
+///     fn acquire(&mut self) -> Vec<u8> {
+///         self.0.pop().unwrap_or_else(|| Vec::with_capacity(1024))
+///     }
+///
+///     fn release(&mut self, mut buf: Vec<u8>) {
+///         buf.clear();
+///         self.0.push(buf);
+///     }
+/// }
+///
+/// let mut encoder = Encoder::with_provider(Pool::default());
+///
+/// let buffer = encoder.string("foo").end();
+/// assert_eq!(buffer, &[3, b'f', b'o', b'o']);
+///
+/// encoder.release(buffer);
+///
+/// let pool = encoder.into_sink().into_provider();
+/// assert_eq!(pool.0.len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct Pooled<P: BufferProvider> {
+    buf: Vec<u8>,
+    provider: P,
+}
+
+impl<P: BufferProvider> Pooled<P> {
+    /// Create a sink writing to a buffer acquired from `provider`.
+    #[inline]
+    pub fn new(mut provider: P) -> Pooled<P> {
+        Pooled {
+            buf: provider.acquire(),
+            provider,
+        }
+    }
+
+    /// Take out the buffer written so far, replacing it with a new
+    /// one acquired from the provider.
+    #[inline]
+    pub fn take(&mut self) -> Vec<u8> {
+        let next = self.provider.acquire();
+
+        ::std::mem::replace(&mut self.buf, next)
+    }
+
+    /// Give back a buffer that is no longer needed to the provider.
+    #[inline]
+    pub fn release(&mut self, buf: Vec<u8>) {
+        self.provider.release(buf);
+    }
+
+    /// Get a reference to the provider.
+    #[inline]
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Obtain the provider, releasing the current buffer to it first.
+    #[inline]
+    pub fn into_provider(mut self) -> P {
+        self.provider.release(self.buf);
+        self.provider
+    }
+}
+
+impl<P: BufferProvider> Sink for Pooled<P> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        Sink::len(&self.buf)
+    }
+
+    #[inline(always)]
+    fn reserve(&mut self, additional: usize) {
+        Sink::reserve(&mut self.buf, additional);
+    }
+
+    #[inline(always)]
+    fn push_byte(&mut self, byte: u8) {
+        self.buf.push_byte(byte);
+    }
+
+    #[inline(always)]
+    fn push_slice(&mut self, bytes: &[u8]) {
+        self.buf.push_slice(bytes);
+    }
+
+    #[inline(always)]
+    fn or_last(&mut self, bits: u8) {
+        self.buf.or_last(bits);
+    }
+
+    #[inline]
+    fn tail(&self, max: usize) -> &[u8] {
+        tail(&self.buf, max)
+    }
+}
+
+impl<P: BufferProvider> Truncate for Pooled<P> {
+    #[inline]
+    fn truncate(&mut self, len: usize) {
+        Truncate::truncate(&mut self.buf, len);
+    }
+
+    #[inline]
+    fn and_last(&mut self, mask: u8) {
+        self.buf.and_last(mask);
+    }
+}
+
+/// Sink feeding every byte written to another sink into a `Hasher`,
+/// so the hash of the encoded buffer is known without reading it again.
+///
+/// The last byte is only hashed once something else is written after
+/// it, or the hasher is taken out with `into_parts`, since stacked
+/// `bool`s can still change it. Bytes are fed in arbitrary chunks, so
+/// the hash matches hashing the buffer in one go only for hashers that
+/// don't depend on how input is split, such as `DefaultHasher`.
+///
+/// ```
+/// use std::collections::hash_map::DefaultHasher;
+/// use std::hash::Hasher;
+///
+/// use bitsparrow::Encoder;
+/// use bitsparrow::sink::Hashed;
+///
+/// let mut encoder = Encoder::with_sink(Hashed::new(Vec::new(), DefaultHasher::new()));
+/// encoder.string("foo").bool(true).bool(false);
+///
+/// let (buffer, hasher) = encoder.into_sink().into_parts();
+///
+/// let mut expected = DefaultHasher::new();
+/// expected.write(&buffer);
+///
+/// assert_eq!(hasher.finish(), expected.finish());
+/// ```
+#[derive(Debug)]
+pub struct Hashed<S, H> {
+    sink: S,
+    hasher: H,
+    last: Option<u8>,
+}
+
+impl<S: Sink, H: Hasher> Hashed<S, H> {
+    /// Create a sink writing to `sink` and hashing with `hasher`.
+    #[inline]
+    pub fn new(sink: S, hasher: H) -> Hashed<S, H> {
+        Hashed {
+            sink,
+            hasher,
+            last: None,
+        }
+    }
+
+    /// Obtain the underlying sink, along with the hasher that has
+    /// been fed all bytes written.
+    #[inline]
+    pub fn into_parts(mut self) -> (S, H) {
+        self.hash_last();
+
+        (self.sink, self.hasher)
+    }
+
+    #[inline]
+    fn hash_last(&mut self) {
+        if let Some(byte) = self.last.take() {
+            self.hasher.write(&[byte]);
+        }
+    }
+}
+
+impl<S: Sink, H: Hasher> Sink for Hashed<S, H> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.sink.len()
+    }
+
+    #[inline(always)]
+    fn reserve(&mut self, additional: usize) {
+        self.sink.reserve(additional);
+    }
+
+    #[inline]
+    fn push_byte(&mut self, byte: u8) {
+        self.hash_last();
+        self.last = Some(byte);
+        self.sink.push_byte(byte);
+    }
+
+    #[inline]
+    fn push_slice(&mut self, bytes: &[u8]) {
+        if let Some((&last, init)) = bytes.split_last() {
+            self.hash_last();
+            self.hasher.write(init);
+            self.last = Some(last);
+            self.sink.push_slice(bytes);
+        }
+    }
+
+    #[inline]
+    fn push_vec(&mut self, bytes: Vec<u8>) {
+        if let Some((&last, init)) = bytes.split_last() {
+            self.hash_last();
+            self.hasher.write(init);
+            self.last = Some(last);
+        }
+
+        self.sink.push_vec(bytes);
+    }
+
+    #[inline]
+    fn or_last(&mut self, bits: u8) {
+        if let Some(ref mut last) = self.last {
+            *last |= bits;
+        }
+
+        self.sink.or_last(bits);
+    }
+    #[inline]
+    fn tail(&self, max: usize) -> &[u8] {
+        self.sink.tail(max)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Sink for BytesMut {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        BytesMut::len(self)
+    }
+
+    #[inline(always)]
+    fn reserve(&mut self, additional: usize) {
+        BytesMut::reserve(self, additional);
+    }
+
+    #[inline(always)]
+    fn push_byte(&mut self, byte: u8) {
+        self.extend_from_slice(&[byte]);
+    }
+
+    #[inline(always)]
+    fn push_slice(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+
+    #[inline(always)]
+    fn or_last(&mut self, bits: u8) {
+        let last = BytesMut::len(self) - 1;
+
+        self[last] |= bits;
+    }
+    #[inline]
+    fn tail(&self, max: usize) -> &[u8] {
+        tail(self, max)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Truncate for BytesMut {
+    #[inline]
+    fn truncate(&mut self, len: usize) {
+        BytesMut::truncate(self, len);
+    }
+
+    #[inline]
+    fn and_last(&mut self, mask: u8) {
+        let last = BytesMut::len(self) - 1;
+
+        self[last] &= mask;
+    }
+}
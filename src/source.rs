@@ -0,0 +1,608 @@
+//! Input sources of the `Decoder`.
+//!
+//! The `Decoder` is generic over the `Source` it reads from, defaulting
+//! to a contiguous `Slice` of bytes, which is what `Decoder::new` uses.
+//! Memory maps deref to `&[u8]` and can be read with `Decoder::new`
+//! directly. This module also provides sources reading from a chain of
+//! buffers (`Chain`), from an `io::Read` (`Reader`), and an adapter
+//! hashing bytes as they are read from another source (`Hashed`).
+//!
+//! Only contiguous sources can lend out data for the lifetime of the
+//! source, reading `&str` or `&[u8]` from other sources fails with
+//! `Error::NotBorrowable`. Use `String` and `Vec<u8>` instead.
+//!
+//! With the `forbid-unsafe` feature enabled `Slice` bounds checks its
+//! reads a second time instead of skipping the check after it made
+//! sure there are enough bytes left. The `wasm-opt` feature replaces
+//! both with a single safe check that can't panic.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::source::Chain;
+//!
+//! let buffer = Encoder::encode((9001u16, "Foo".to_string()));
+//! let (head, tail) = buffer.split_at(3);
+//!
+//! let mut decoder = Decoder::with_source(Chain::new(vec![head, tail]));
+//!
+//! assert_eq!(9001u16, decoder.uint16().unwrap());
+//! assert_eq!("Foo", decoder.read::<String>().unwrap());
+//! assert_eq!(true, decoder.end());
+//! ```
+
+use std::{fmt, mem};
+use std::hash::Hasher;
+use std::io::{self, Read};
+
+use utils::{DEBUG_WINDOW, HexWindow, Error, Result};
+
+// Initial number of bytes the default `Source::read_vec` reads at once
+const READ_STEP: usize = 4096;
+
+/// Source of bytes for a `Decoder`.
+pub trait Source<'src> {
+    /// Number of bytes consumed so far.
+    fn position(&self) -> usize;
+
+    /// Fill `buf` with the next bytes. Fails with `ReadingOutOfBounds`
+    /// if there aren't enough bytes.
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<()>;
+
+    /// Read the next byte.
+    #[inline]
+    fn read_byte(&mut self) -> Result<u8> {
+        let mut byte = [0];
+        self.read_into(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Read the next `len` bytes into a new `Vec<u8>`. The default
+    /// implementation doesn't trust `len` for preallocation, reading in
+    /// steps growing with the bytes actually read instead.
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut vec = Vec::new();
+
+        while vec.len() < len {
+            let start = vec.len();
+            let step = (len - start).min(start.max(READ_STEP));
+
+            vec.resize(start + step, 0);
+            self.read_into(&mut vec[start..])?;
+        }
+
+        Ok(vec)
+    }
+
+    /// Borrow the next `len` bytes for the lifetime of the source.
+    /// Fails with `NotBorrowable`, without consuming anything, if
+    /// the bytes aren't contiguous in memory.
+    #[inline]
+    fn borrow(&mut self, len: usize) -> Result<&'src [u8]> {
+        let _ = len;
+
+        Err(Error::NotBorrowable)
+    }
+
+    /// Skip over the next `len` bytes.
+    #[inline]
+    fn skip(&mut self, mut len: usize) -> Result<()> {
+        let mut scratch = [0; 64];
+
+        while len != 0 {
+            let chunk = len.min(scratch.len());
+            self.read_into(&mut scratch[..chunk])?;
+            len -= chunk;
+        }
+
+        Ok(())
+    }
+}
+
+/// Source reading from a contiguous slice of bytes.
+#[derive(Clone)]
+pub struct Slice<'src> {
+    index: usize,
+    data: &'src [u8],
+}
+
+impl<'src> Slice<'src> {
+    /// Create a source reading from the start of `data`.
+    #[inline]
+    pub fn new(data: &'src [u8]) -> Slice<'src> {
+        Slice {
+            index: 0,
+            data,
+        }
+    }
+
+    /// Create a source reading from `offset` bytes into `data`,
+    /// positions are still counted from the start of `data`.
+    #[inline]
+    pub fn new_at(data: &'src [u8], offset: usize) -> Slice<'src> {
+        let mut slice = Slice::new(data);
+        slice.index = offset;
+        slice
+    }
+
+    /// Start reading from the beginning of the slice again.
+    #[inline]
+    pub fn rewind(&mut self) {
+        self.index = 0;
+    }
+
+    /// Continue reading from a position counted from the start of the
+    /// slice. Fails with `ReadingOutOfBounds` if it's past the end.
+    #[inline]
+    pub fn seek(&mut self, position: usize) -> Result<()> {
+        if position > self.data.len() {
+            return Err(Error::ReadingOutOfBounds);
+        }
+
+        self.index = position;
+
+        Ok(())
+    }
+
+    /// Returns `true` if the entire slice has been read.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.index >= self.data.len()
+    }
+
+    /// Bytes that have not been read yet.
+    #[inline]
+    pub fn remaining(&self) -> &'src [u8] {
+        &self.data[self.index.min(self.data.len())..]
+    }
+
+    /// Consume the next `len` bytes, returning them. The index can be
+    /// past the end of the data (see `new_at`), and `len` comes from
+    /// untrusted input, so neither of them is added up unchecked.
+    #[cfg(not(feature = "wasm-opt"))]
+    #[inline(always)]
+    fn advance(&mut self, len: usize) -> Result<&'src [u8]> {
+        let start = self.index;
+
+        if start > self.data.len() || len > self.data.len() - start {
+            return Err(Error::ReadingOutOfBounds);
+        }
+
+        self.index = start + len;
+
+        Ok(self.range(start, len))
+    }
+
+    /// Same as above with a single check and no panicking slicing, which
+    /// keeps the formatting code of panics out of size sensitive builds.
+    #[cfg(feature = "wasm-opt")]
+    #[inline(always)]
+    fn advance(&mut self, len: usize) -> Result<&'src [u8]> {
+        let data = self.data;
+        let slice = self.index.checked_add(len)
+            .and_then(|end| data.get(self.index..end))
+            .ok_or(Error::ReadingOutOfBounds)?;
+
+        self.index += len;
+
+        Ok(slice)
+    }
+
+    #[cfg(not(any(feature = "forbid-unsafe", feature = "wasm-opt")))]
+    #[inline(always)]
+    fn range(&self, start: usize, len: usize) -> &'src [u8] {
+        let data = self.data;
+
+        // `advance` made sure that `start + len` is within `data`
+        unsafe { data.get_unchecked(start..start + len) }
+    }
+
+    #[cfg(all(feature = "forbid-unsafe", not(feature = "wasm-opt")))]
+    #[inline(always)]
+    fn range(&self, start: usize, len: usize) -> &'src [u8] {
+        let data = self.data;
+
+        &data[start..start + len]
+    }
+}
+
+impl<'src> fmt::Debug for Slice<'src> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let index = self.index.min(self.data.len());
+        let before = &self.data[index.saturating_sub(DEBUG_WINDOW)..index];
+        let after = &self.data[index..(index + DEBUG_WINDOW).min(self.data.len())];
+
+        f.debug_struct("Slice")
+            .field("position", &self.index)
+            .field("len", &self.data.len())
+            .field("window", &HexWindow { before, after })
+            .finish()
+    }
+}
+
+impl<'src> Source<'src> for Slice<'src> {
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.index
+    }
+
+    #[inline(always)]
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<()> {
+        buf.copy_from_slice(self.advance(buf.len())?);
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn read_byte(&mut self) -> Result<u8> {
+        self.advance(1).map(|byte| byte[0])
+    }
+
+    #[inline(always)]
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+        self.advance(len).map(<[u8]>::to_vec)
+    }
+
+    #[inline(always)]
+    fn borrow(&mut self, len: usize) -> Result<&'src [u8]> {
+        self.advance(len)
+    }
+
+    #[inline(always)]
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.advance(len).map(|_| ())
+    }
+}
+
+/// Source reading from a sequence of buffers as if they were one,
+/// e.g. segments of a ring buffer or chunks received from a socket.
+#[derive(Clone)]
+pub struct Chain<'src> {
+    chunks: Vec<&'src [u8]>,
+    chunk: usize,
+    offset: usize,
+    position: usize,
+}
+
+impl<'src> Chain<'src> {
+    /// Create a source reading from the chunks in order.
+    pub fn new<I: IntoIterator<Item = &'src [u8]>>(chunks: I) -> Chain<'src> {
+        Chain {
+            chunks: chunks.into_iter().filter(|chunk| !chunk.is_empty()).collect(),
+            chunk: 0,
+            offset: 0,
+            position: 0,
+        }
+    }
+
+    /// Returns `true` if all chunks have been read.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunk >= self.chunks.len()
+    }
+
+    /// Total number of bytes left in all chunks.
+    pub(crate) fn remaining(&self) -> usize {
+        self.chunks[self.chunk.min(self.chunks.len())..]
+            .iter()
+            .map(|chunk| chunk.len())
+            .sum::<usize>() - self.offset
+    }
+
+    #[inline]
+    fn consume(&mut self, len: usize) {
+        self.offset += len;
+        self.position += len;
+
+        if self.offset == self.chunks[self.chunk].len() {
+            self.chunk += 1;
+            self.offset = 0;
+        }
+    }
+}
+
+impl<'src> fmt::Debug for Chain<'src> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let after = match self.chunks.get(self.chunk) {
+            Some(chunk) => &chunk[self.offset..(self.offset + DEBUG_WINDOW).min(chunk.len())],
+            None        => &[],
+        };
+
+        f.debug_struct("Chain")
+            .field("position", &self.position)
+            .field("chunks", &self.chunks.len())
+            .field("remaining", &self.remaining())
+            .field("window", &HexWindow { before: &[], after })
+            .finish()
+    }
+}
+
+impl<'src> Source<'src> for Chain<'src> {
+    #[inline]
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<()> {
+        if buf.len() > self.remaining() {
+            return Err(Error::ReadingOutOfBounds);
+        }
+
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            let chunk = &self.chunks[self.chunk][self.offset..];
+            let len = chunk.len().min(buf.len() - filled);
+
+            buf[filled..filled + len].copy_from_slice(&chunk[..len]);
+            filled += len;
+
+            self.consume(len);
+        }
+
+        Ok(())
+    }
+
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+        if len > self.remaining() {
+            return Err(Error::ReadingOutOfBounds);
+        }
+
+        let mut vec = vec![0; len];
+        self.read_into(&mut vec)?;
+        Ok(vec)
+    }
+
+    fn borrow(&mut self, len: usize) -> Result<&'src [u8]> {
+        if len == 0 {
+            return Ok(&[]);
+        }
+
+        let chunk = match self.chunks.get(self.chunk) {
+            Some(&chunk) => &chunk[self.offset..],
+            None         => return Err(Error::ReadingOutOfBounds),
+        };
+
+        if chunk.len() < len {
+            if len > self.remaining() {
+                return Err(Error::ReadingOutOfBounds);
+            }
+
+            return Err(Error::NotBorrowable);
+        }
+
+        self.consume(len);
+
+        Ok(&chunk[..len])
+    }
+}
+
+/// Source reading from an `io::Read`. IO errors are reported as
+/// `Error::Io`, reaching the end of the reader early as
+/// `ReadingOutOfBounds`. Bytes taken from the reader by a failed read
+/// are kept, so the read can be retried from the same position once
+/// more data is available.
+///
+/// Reads are not buffered, wrap unbuffered readers such as `File`
+/// in a `BufReader`.
+#[derive(Clone)]
+pub struct Reader<R> {
+    reader: R,
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Reader<R> {
+    /// Create a source reading from `reader`.
+    #[inline]
+    pub fn new(reader: R) -> Reader<R> {
+        Reader {
+            reader,
+            pending: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Returns `true` if the reader has no more data, reading ahead
+    /// a single byte to find out.
+    pub fn is_empty(&mut self) -> bool {
+        if !self.pending.is_empty() {
+            return false;
+        }
+
+        let mut byte = [0];
+
+        match read_exact(&mut self.reader, &mut byte) {
+            Ok(()) => {
+                self.pending.push(byte[0]);
+                false
+            },
+            Err(_) => true,
+        }
+    }
+
+    /// Read everything up to the end of the reader.
+    pub(crate) fn read_to_end(&mut self) -> Result<Vec<u8>> {
+        let mut vec = mem::take(&mut self.pending);
+
+        self.reader.read_to_end(&mut vec)?;
+        self.position += vec.len();
+
+        Ok(vec)
+    }
+
+    /// Obtain the underlying reader, along with bytes that have been
+    /// taken from it but not consumed yet, read ahead by `is_empty` or
+    /// by a failed read.
+    #[inline]
+    pub fn into_inner(self) -> (R, Vec<u8>) {
+        (self.reader, self.pending)
+    }
+}
+
+impl<R> fmt::Debug for Reader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reader")
+            .field("position", &self.position)
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+impl<'src, R: Read> Source<'src> for Reader<R> {
+    #[inline]
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<()> {
+        let len = buf.len();
+        let mut filled = self.pending.len().min(len);
+
+        buf[..filled].copy_from_slice(&self.pending[..filled]);
+
+        while filled < len {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => {
+                    self.pending = buf[..filled].to_vec();
+                    return Err(Error::Io(err));
+                },
+            }
+        }
+
+        // Keep what was read, starting with the bytes already pending
+        if filled < len {
+            self.pending = buf[..filled].to_vec();
+            return Err(Error::ReadingOutOfBounds);
+        }
+
+        self.pending.drain(..len.min(self.pending.len()));
+        self.position += len;
+
+        Ok(())
+    }
+
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut vec = mem::take(&mut self.pending);
+
+        if vec.len() > len {
+            self.pending = vec.split_off(len);
+        }
+
+        // Don't trust `len` for preallocation, read what's there instead
+        let rest = len - vec.len();
+        let read = Read::by_ref(&mut self.reader).take(rest as u64).read_to_end(&mut vec);
+
+        if read.is_err() || vec.len() != len {
+            self.pending = vec;
+            read?;
+
+            return Err(Error::ReadingOutOfBounds);
+        }
+
+        self.position += len;
+
+        Ok(vec)
+    }
+}
+
+/// Source feeding every byte read from another source into a `Hasher`,
+/// so integrity can be checked over exactly what was decoded without
+/// holding the whole buffer. Bytes that are skipped are read and hashed
+/// as well.
+///
+/// ```
+/// use std::collections::hash_map::DefaultHasher;
+/// use std::hash::Hasher;
+///
+/// use bitsparrow::{Encoder, Decoder};
+/// use bitsparrow::source::{Hashed, Reader};
+///
+/// let buffer = Encoder::encode(("foo".to_string(), 9001u16));
+///
+/// let source = Hashed::new(Reader::new(&buffer[..]), DefaultHasher::new());
+/// let mut decoder = Decoder::with_source(source);
+///
+/// assert_eq!(decoder.read::<(String, u16)>().unwrap(), ("foo".to_string(), 9001));
+///
+/// let mut expected = DefaultHasher::new();
+/// expected.write(&buffer);
+///
+/// assert_eq!(decoder.into_source().hasher().finish(), expected.finish());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Hashed<S, H> {
+    source: S,
+    hasher: H,
+}
+
+impl<S, H: Hasher> Hashed<S, H> {
+    /// Create a source reading from `source` and hashing with `hasher`.
+    #[inline]
+    pub fn new(source: S, hasher: H) -> Hashed<S, H> {
+        Hashed {
+            source,
+            hasher,
+        }
+    }
+
+    /// Hasher that has been fed all bytes read so far.
+    #[inline]
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Obtain the underlying source and the hasher.
+    #[inline]
+    pub fn into_parts(self) -> (S, H) {
+        (self.source, self.hasher)
+    }
+}
+
+impl<'src, S: Source<'src>, H: Hasher> Source<'src> for Hashed<S, H> {
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.source.position()
+    }
+
+    #[inline]
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.source.read_into(buf)?;
+        self.hasher.write(buf);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn read_byte(&mut self) -> Result<u8> {
+        let byte = self.source.read_byte()?;
+        self.hasher.write(&[byte]);
+
+        Ok(byte)
+    }
+
+    #[inline]
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+        let vec = self.source.read_vec(len)?;
+        self.hasher.write(&vec);
+
+        Ok(vec)
+    }
+
+    #[inline]
+    fn borrow(&mut self, len: usize) -> Result<&'src [u8]> {
+        let slice = self.source.borrow(len)?;
+        self.hasher.write(slice);
+
+        Ok(slice)
+    }
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::ReadingOutOfBounds,
+        _                            => Error::Io(err),
+    })
+}
@@ -0,0 +1,79 @@
+//! Helpers for compatibility tests, turning accidental changes of the
+//! wire format into test failures. A golden buffer is the encoding of
+//! a value checked in next to the tests. `compat_test!` generates a
+//! test asserting that the value still encodes to it byte for byte and
+//! that the buffer still decodes to the value.
+//!
+//! ```
+//! use bitsparrow::testing::assert_compat;
+//!
+//! assert_compat(&(9001u16, "foo".to_string()), &[0x23, 0x29, 0x03, b'f', b'o', b'o']);
+//! ```
+//!
+//! Which `compat_test!` wraps in a test function, given its name,
+//! the type, the value and the golden buffer:
+//!
+//! ```text
+//! compat_test!(pair_is_stable, (u16, String), (9001, "foo".into()), &[0x23, 0x29, 0x03, b'f', b'o', b'o']);
+//! ```
+//!
+//! Golden buffers are conveniently stored as files and included with
+//! `include_bytes!`. Buffers written by older releases that are no
+//! longer produced can still be checked with `assert_decodes`.
+
+use std::fmt::Debug;
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use dump::Dump;
+
+/// Generate a `#[test]` named `$name` calling `assert_compat` on a
+/// value of type `$ty` and a golden buffer.
+#[macro_export]
+macro_rules! compat_test {
+    ($name:ident, $ty:ty, $value:expr, $golden:expr) => {
+        #[test]
+        fn $name() {
+            let value: $ty = $value;
+
+            $crate::testing::assert_compat(&value, $golden);
+        }
+    };
+}
+
+/// Panic unless `value` encodes exactly to `golden`, and `golden`
+/// decodes back to `value`.
+pub fn assert_compat<T>(value: &T, golden: &[u8]) where
+    T: BitEncode + for<'a> BitDecode<'a> + PartialEq + Debug,
+{
+    assert_encodes(value, golden);
+    assert_decodes(golden, value);
+}
+
+/// Panic unless `value` encodes exactly to `golden`, showing hex dumps
+/// of both buffers otherwise.
+pub fn assert_encodes<E: BitEncode + ?Sized>(value: &E, golden: &[u8]) {
+    let buffer = Encoder::encode(value);
+
+    if buffer != golden {
+        let at = buffer.iter().zip(golden).take_while(|&(a, b)| a == b).count();
+
+        panic!(
+            "encoding changed at byte {}\n\nencoded:\n{}\ngolden:\n{}",
+            at,
+            Dump::new(&buffer),
+            Dump::new(golden),
+        );
+    }
+}
+
+/// Panic unless the entire `buffer` decodes to `expected`.
+pub fn assert_decodes<'src, D>(buffer: &'src [u8], expected: &D) where
+    D: BitDecode<'src> + PartialEq + Debug,
+{
+    match Decoder::decode::<D>(buffer) {
+        Ok(ref value) if value == expected => {},
+        Ok(value) => panic!("decoded {:?}, expected {:?}\n\nbuffer:\n{}", value, expected, Dump::new(buffer)),
+        Err(err) => panic!("failed to decode {:?}: {}\n\nbuffer:\n{}", expected, err, Dump::new(buffer)),
+    }
+}
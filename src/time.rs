@@ -0,0 +1,126 @@
+//! Compact timestamps. `Timestamp` holds the number of milliseconds
+//! since the UNIX epoch, stored in the same variable length format as
+//! `size`, taking 6 bytes for current dates.
+//!
+//! ```
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::time::Timestamp;
+//!
+//! let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+//! let buffer = Encoder::encode(Timestamp::from(time));
+//!
+//! assert_eq!(buffer.len(), 6);
+//!
+//! let decoded: Timestamp = Decoder::decode(&buffer).unwrap();
+//!
+//! assert_eq!(decoded.millis(), 1_700_000_000_123);
+//! assert_eq!(decoded.to_system_time(), time);
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+
+use encode::{Encoder, BitEncode};
+use decode::{Decoder, BitDecode};
+use verify::BitVerify;
+use sink::Sink;
+use source::Source;
+use utils::Result;
+
+/// Point in time with millisecond precision, counted from the UNIX
+/// epoch. Earlier times are not representable and are clamped to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    /// Current time of the system clock.
+    #[inline]
+    pub fn now() -> Timestamp {
+        Timestamp::from(SystemTime::now())
+    }
+
+    /// Create a `Timestamp` from milliseconds since the UNIX epoch.
+    #[inline]
+    pub fn from_millis(millis: u64) -> Timestamp {
+        Timestamp(millis)
+    }
+
+    /// Milliseconds since the UNIX epoch.
+    #[inline]
+    pub fn millis(&self) -> u64 {
+        self.0
+    }
+
+    /// Convert to a `SystemTime`.
+    #[inline]
+    pub fn to_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.0)
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    #[inline]
+    fn from(time: SystemTime) -> Timestamp {
+        let millis = time.duration_since(UNIX_EPOCH).map(|since| since.as_millis()).unwrap_or(0);
+
+        Timestamp(millis.min(u64::MAX as u128) as u64)
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    #[inline]
+    fn from(timestamp: Timestamp) -> SystemTime {
+        timestamp.to_system_time()
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<DateTime<Utc>> for Timestamp {
+    #[inline]
+    fn from(time: DateTime<Utc>) -> Timestamp {
+        Timestamp(time.timestamp_millis().max(0) as u64)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Timestamp {
+    /// Convert to a chrono `DateTime`, returning `None` if the time
+    /// is out of its range.
+    #[inline]
+    pub fn to_date_time(&self) -> Option<DateTime<Utc>> {
+        if self.0 > i64::MAX as u64 {
+            return None;
+        }
+
+        DateTime::from_timestamp_millis(self.0 as i64)
+    }
+}
+
+impl BitEncode for Timestamp {
+    #[inline]
+    fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+        e.write_varint(self.0, 0);
+    }
+
+    #[inline]
+    fn size_hint() -> usize {
+        6
+    }
+}
+
+impl<'src> BitDecode<'src> for Timestamp {
+    #[inline]
+    fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+        d.read_varint().map(Timestamp)
+    }
+}
+
+impl BitVerify for Timestamp {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        d.read_varint().map(|_| ())
+    }
+}
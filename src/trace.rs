@@ -0,0 +1,194 @@
+//! Recording of every primitive read made by a `Decoder`, for
+//! post-mortem analysis of buffers that fail to decode.
+//!
+//! ```
+//! use bitsparrow::{Encoder, Decoder};
+//! use bitsparrow::trace::Value;
+//!
+//! let buffer = Encoder::new().uint16(9001).string("foo").end();
+//!
+//! let mut decoder = Decoder::new(&buffer[..5]);
+//! decoder.trace();
+//!
+//! assert!(decoder.read::<(u16, &str)>().is_err());
+//!
+//! let trace = decoder.take_trace().unwrap();
+//! let events = trace.events();
+//!
+//! assert_eq!(events.len(), 2);
+//! assert_eq!(events[0].value, Some(Value::Uint(9001)));
+//! assert_eq!(events[0].bytes(&buffer), &[0x23, 0x29]);
+//! assert_eq!((events[1].ty, events[1].offset, events[1].value.clone()), ("string", 2, None));
+//! ```
+
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use utils::Result;
+
+/// Value of a primitive read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Uint(u64),
+    Int(i64),
+    Float(f64),
+    Size(usize),
+    Bytes(Vec<u8>),
+    String(String),
+}
+
+/// A single primitive read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    /// Name of the BitSparrow type read, e.g. `u16` or `string`.
+    pub ty: &'static str,
+    /// Position in the source the read started at.
+    pub offset: usize,
+    /// Number of bytes consumed, zero for `bool`s stacked on a byte
+    /// that has already been read.
+    pub len: usize,
+    /// Value read, `None` if reading failed.
+    pub value: Option<Value>,
+}
+
+impl TraceEvent {
+    /// Bytes consumed by the read, given the buffer that was decoded.
+    #[inline]
+    pub fn bytes<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let start = self.offset.min(data.len());
+        let end = (self.offset + self.len).min(data.len());
+
+        &data[start..end]
+    }
+}
+
+/// All reads made by a `Decoder` since `Decoder::trace` was called,
+/// in order. Iteration replays the events.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+    events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    /// All recorded reads in order.
+    #[inline]
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// The read that failed, if any. Decoding stops at the first error,
+    /// so this is the last event.
+    pub fn failure(&self) -> Option<&TraceEvent> {
+        self.events.last().filter(|event| event.value.is_none())
+    }
+
+    #[inline]
+    pub(crate) fn record<T: Recordable>(&mut self, ty: &'static str, offset: usize, len: usize, result: &Result<T>) {
+        self.events.push(TraceEvent {
+            ty,
+            offset,
+            len,
+            value: result.as_ref().ok().map(Recordable::value),
+        });
+    }
+}
+
+impl<'a> IntoIterator for &'a Trace {
+    type Item = &'a TraceEvent;
+    type IntoIter = ::std::slice::Iter<'a, TraceEvent>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.events.iter()
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Bool(val)       => write!(f, "{}", val),
+            Value::Uint(val)       => write!(f, "{}", val),
+            Value::Int(val)        => write!(f, "{}", val),
+            Value::Float(val)      => write!(f, "{}", val),
+            Value::Size(val)       => write!(f, "{}", val),
+            Value::Bytes(ref val)  => write!(f, "{:?}", val),
+            Value::String(ref val) => write!(f, "{:?}", val),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            write!(f, "{:>8} {:>6} {:<8} ", event.offset, event.len, event.ty)?;
+
+            match event.value {
+                Some(ref value) => writeln!(f, "{}", value)?,
+                None            => writeln!(f, "<failed>")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Types returned by primitive reads of the `Decoder`.
+pub(crate) trait Recordable {
+    fn value(&self) -> Value;
+}
+
+macro_rules! impl_recordable {
+    ($variant:ident as $as:ty: $( $t:ty ),*) => {
+        $(
+            impl Recordable for $t {
+                #[inline]
+                fn value(&self) -> Value {
+                    Value::$variant(*self as $as)
+                }
+            }
+        )*
+    }
+}
+
+impl_recordable!(Uint as u64: u8, u16, u32, u64);
+impl_recordable!(Int as i64: i8, i16, i32, i64);
+impl_recordable!(Float as f64: f32, f64);
+impl_recordable!(Size as usize: usize);
+
+impl Recordable for bool {
+    #[inline]
+    fn value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+macro_rules! impl_recordable_str {
+    ($( $t:ty ),*) => {
+        $(
+            impl<'a> Recordable for $t {
+                #[inline]
+                fn value(&self) -> Value {
+                    Value::String(self.to_string())
+                }
+            }
+        )*
+    }
+}
+
+macro_rules! impl_recordable_bytes {
+    ($( $t:ty ),*) => {
+        $(
+            impl<'a> Recordable for $t {
+                #[inline]
+                fn value(&self) -> Value {
+                    Value::Bytes(self.to_vec())
+                }
+            }
+        )*
+    }
+}
+
+impl_recordable_str!(&'a str, String, Box<str>, Rc<str>, Arc<str>);
+impl_recordable_bytes!(&'a [u8], Vec<u8>, Box<[u8]>, Rc<[u8]>, Arc<[u8]>);
@@ -1,7 +1,48 @@
-use std::{error, fmt};
+use std::{error, fmt, io};
 use std::str::Utf8Error;
 use std::string::FromUtf8Error;
 
+// Evaluates `$body`, and with the `tracing` feature enabled emits a trace
+// level event with the type, offset and length of the field written or
+// read by it. `$pos` must evaluate to the current position in the buffer.
+macro_rules! traced {
+    ($pos:expr, $ty:expr, $body:expr) => ({
+        #[cfg(feature = "tracing")]
+        let start = $pos;
+        let result = $body;
+
+        #[cfg(feature = "tracing")]
+        {
+            if ::utils::Traced::succeeded(&result) {
+                ::tracing::trace!(ty = $ty, offset = start, len = $pos - start);
+            }
+        }
+
+        result
+    })
+}
+
+#[cfg(feature = "tracing")]
+pub trait Traced {
+    fn succeeded(&self) -> bool;
+}
+
+#[cfg(feature = "tracing")]
+impl Traced for () {
+    #[inline(always)]
+    fn succeeded(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T> Traced for Result<T> {
+    #[inline(always)]
+    fn succeeded(&self) -> bool {
+        self.is_ok()
+    }
+}
+
 pub static SIZE_MASKS: [u8; 9] = [
     0b00000000,
     0b10000000,
@@ -14,31 +55,251 @@ pub static SIZE_MASKS: [u8; 9] = [
     0b11111111
 ];
 
+/// Number of bytes shown on either side of the cursor in `Debug`
+/// output of the `Encoder`, `Decoder` and their backends.
+pub const DEBUG_WINDOW: usize = 8;
+
+/// Formats bytes around a cursor as hex, e.g. `[de ad | be ef]`.
+pub struct HexWindow<'a> {
+    pub before: &'a [u8],
+    pub after: &'a [u8],
+}
+
+impl<'a> fmt::Debug for HexWindow<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[")?;
+
+        for byte in self.before {
+            write!(f, "{:02x} ", byte)?;
+        }
+
+        f.write_str("|")?;
+
+        for byte in self.after {
+            write!(f, " {:02x}", byte)?;
+        }
+
+        f.write_str("]")
+    }
+}
+
+/// Byte the `Encoder` or `Decoder` is stacking `bool`s and bits on.
+/// Bits can only be added to it while the buffer position is right
+/// after the byte, every other read or write ends the group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoolSlot {
+    /// Position in the buffer right after the byte.
+    pub end: usize,
+    /// Index of the highest bit used so far.
+    pub shift: u8,
+    /// Contents of the byte.
+    pub bits: u8,
+}
+
+impl BoolSlot {
+    /// Start a new group on a byte ending at `end`, with its lowest
+    /// `count` bits used.
+    #[inline(always)]
+    pub fn new(end: usize, bits: u8, count: u8) -> Self {
+        BoolSlot {
+            end,
+            shift: count - 1,
+            bits,
+        }
+    }
+
+    /// Returns `true` if `count` more bits can be stacked on the byte
+    /// at `position`.
+    #[inline(always)]
+    pub fn fits(&self, position: usize, count: u8) -> bool {
+        self.end == position && self.shift + count <= 7
+    }
+
+    /// Use up the next `count` bits, returning their offset in the byte.
+    #[inline(always)]
+    pub fn advance(&mut self, count: u8) -> u8 {
+        let offset = self.shift + 1;
+        self.shift += count;
+        offset
+    }
+}
+
 /// Simple error type returned either by the `Decoder` or `Encoder`
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     Utf8Encoding,
     ReadingOutOfBounds,
     BufferNotEmpty,
     InvalidData,
+    InvalidEnumVariant { tag: usize },
+    LimitExceeded,
+    BufferTooSmall { needed: usize },
+    NotBorrowable,
+    UnsupportedWireVersion { version: u16 },
+    OutOfSequence { expected: u64, received: u64 },
+    BudgetExceeded,
+    DictionaryRequired,
+    Io(io::Error),
+    Field(Box<FieldError>),
 }
 
-impl error::Error for Error {
-    fn description(&self) -> &str {
-        use Error::*;
+/// Error that occurred while decoding a field of a composite type,
+/// along with the path to that field, e.g. `Foo.bar[3].baz`.
+#[derive(Debug)]
+pub struct FieldError {
+    ty: Option<&'static str>,
+    path: String,
+    error: Error,
+}
+
+impl FieldError {
+    /// Path to the field that failed to decode.
+    pub fn path(&self) -> String {
+        match self.ty {
+            Some(ty) => format!("{}{}{}", ty, separator(&self.path), self.path),
+            None     => self.path.clone(),
+        }
+    }
+
+    /// The error that caused decoding of the field to fail.
+    #[inline]
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+}
 
+impl Error {
+    /// Annotate the error with the name of the field being decoded.
+    /// Calling it from the outermost field inwards builds up the path.
+    ///
+    /// ```
+    /// use bitsparrow::Error;
+    ///
+    /// let err = Error::InvalidData.at_field("baz").at_index(3).at_field("bar").in_type("Foo");
+    ///
+    /// assert_eq!(err.path().unwrap(), "Foo.bar[3].baz");
+    /// ```
+    pub fn at_field(self, name: &str) -> Error {
+        self.prepend(name)
+    }
+
+    /// Annotate the error with the index of an element in a collection.
+    pub fn at_index(self, index: usize) -> Error {
+        self.prepend(&format!("[{}]", index))
+    }
+
+    /// Annotate the error with the name of the type containing the
+    /// path. Only the outermost type is kept in the path.
+    pub fn in_type(self, ty: &'static str) -> Error {
+        match self {
+            Error::Field(mut field) => {
+                field.ty = Some(ty);
+
+                Error::Field(field)
+            },
+            error => Error::Field(Box::new(FieldError {
+                ty: Some(ty),
+                path: String::new(),
+                error,
+            })),
+        }
+    }
+
+    /// Path to the field that caused the error, if known.
+    pub fn path(&self) -> Option<String> {
+        match *self {
+            Error::Field(ref field) => Some(field.path()),
+            _                       => None,
+        }
+    }
+
+    /// The underlying error, without the path annotation.
+    pub fn inner(&self) -> &Error {
         match *self {
-            Utf8Encoding       => "Couldn't decode UTF-8 string",
-            ReadingOutOfBounds => "Attempted to read out of bounds",
-            BufferNotEmpty     => "There is still data to read",
-            InvalidData        => "Data does not match requested type",
+            Error::Field(ref field) => &field.error,
+            ref error               => error,
+        }
+    }
+
+    fn prepend(self, segment: &str) -> Error {
+        match self {
+            Error::Field(mut field) => {
+                field.path = format!("{}{}{}", segment, separator(&field.path), field.path);
+                field.ty = None;
+
+                Error::Field(field)
+            },
+            error => Error::Field(Box::new(FieldError {
+                ty: None,
+                path: segment.to_string(),
+                error,
+            })),
+        }
+    }
+}
+
+fn separator(path: &str) -> &'static str {
+    if path.is_empty() || path.starts_with('[') {
+        ""
+    } else {
+        "."
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err)       => Some(err),
+            Error::Field(ref field)  => Some(&field.error),
+            _                        => None,
         }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(error::Error::description(self))
+        use Error::*;
+
+        match *self {
+            Utf8Encoding               => f.write_str("Couldn't decode UTF-8 string"),
+            ReadingOutOfBounds         => f.write_str("Attempted to read out of bounds"),
+            BufferNotEmpty             => f.write_str("There is still data to read"),
+            InvalidData                => f.write_str("Data does not match requested type"),
+            InvalidEnumVariant { tag } => write!(f, "Invalid enum variant tag {}", tag),
+            LimitExceeded              => f.write_str("Decoding limit exceeded"),
+            BufferTooSmall { needed }  => write!(f, "Buffer too small, {} bytes needed", needed),
+            NotBorrowable              => f.write_str("Data can't be borrowed from the source"),
+            UnsupportedWireVersion { version } => write!(f, "Unsupported wire format version {}", version),
+            OutOfSequence { expected, received } => write!(f, "Expected sequence number {}, received {}", expected, received),
+            BudgetExceeded             => f.write_str("Decoding time budget exceeded"),
+            DictionaryRequired         => f.write_str("Compressed data requires a decompression dictionary"),
+            Io(ref err)                => write!(f, "IO error: {}", err),
+            Field(ref field)           => write!(f, "{} at `{}`", field.error, field.path()),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        if let Error::Io(err) = err {
+            return err;
+        }
+
+        let kind = match *err.inner() {
+            Error::Io(ref inner)      => inner.kind(),
+            Error::ReadingOutOfBounds => io::ErrorKind::UnexpectedEof,
+            Error::BufferTooSmall {..} => io::ErrorKind::WriteZero,
+            _                         => io::ErrorKind::InvalidData,
+        };
+
+        io::Error::new(kind, err)
     }
 }
 
@@ -55,3 +316,18 @@ impl From<FromUtf8Error> for Error {
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// CRC-32 (IEEE 802.3) checksum of a byte slice.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+
+    !crc
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::num::{Saturating, Wrapping};
+use std::ops::{Bound, Range, RangeInclusive};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use decode::{Decoder, BitDecode};
+use source::Source;
+use utils::{Error, Result};
+
+/// Types that can be validated on a buffer without being decoded.
+/// Verifying walks the buffer the same way decoding would, checking
+/// lengths, UTF-8 strings and any other invariants of the type, without
+/// building the value. Nothing is allocated when verifying a slice,
+/// except by types that can only be checked by parsing them, such as
+/// `Url` and `Version`, which are decoded and dropped.
+///
+/// ```
+/// use bitsparrow::{Encoder, Decoder};
+///
+/// let buffer = Encoder::encode((vec!["foo", "bar"], 42u32));
+///
+/// assert!(Decoder::validate::<(Vec<String>, u32)>(&buffer).is_ok());
+/// assert!(Decoder::validate::<(Vec<String>, u64)>(&buffer).is_err());
+/// ```
+pub trait BitVerify {
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()>;
+}
+
+macro_rules! impl_verify {
+    ($( $t:ty ),*) => {
+        $(
+            impl BitVerify for $t {
+                #[inline]
+                fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+                    <$t as BitDecode<'src>>::decode(d).map(|_| ())
+                }
+            }
+        )*
+    }
+}
+
+impl_verify!(u16, u32, u64, i8, i16, i32, i64, f32, f64, bool, usize);
+
+macro_rules! impl_verify_as {
+    ($func:ident: $( $t:ty ),*) => {
+        $(
+            impl BitVerify for $t {
+                #[inline]
+                fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+                    d.$func()
+                }
+            }
+        )*
+    }
+}
+
+impl_verify_as!(skip_bytes: [u8], Vec<u8>);
+impl_verify_as!(skip_str: str, String);
+
+impl<const N: usize> BitVerify for [u8; N] {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        d.legacy_array_size(N)?;
+        d.skip_reserved(N).map(|_| ())
+    }
+}
+
+impl<V: BitVerify + ?Sized> BitVerify for &V {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        V::verify(d)
+    }
+}
+
+impl<V: BitVerify> BitVerify for [V] {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        let size = d.collection_size()?;
+
+        d.nested(|d| {
+            for _ in 0..size {
+                d.tick()?;
+                V::verify(d)?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl<V: BitVerify> BitVerify for Vec<V> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        <[V]>::verify(d)
+    }
+}
+
+impl<K: BitVerify, V: BitVerify, H> BitVerify for HashMap<K, V, H> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        let size = d.collection_size()?;
+
+        d.nested(|d| {
+            for _ in 0..size {
+                d.tick()?;
+                K::verify(d)?;
+                V::verify(d)?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+macro_rules! impl_wrapper {
+    ($( $w:ident ),*) => {
+        $(
+            impl<V: BitVerify> BitVerify for $w<V> {
+                #[inline]
+                fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+                    V::verify(d)
+                }
+            }
+        )*
+    }
+}
+
+impl_wrapper!(Wrapping, Saturating);
+
+impl<V: BitVerify> BitVerify for Range<V> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        V::verify(d)?;
+        V::verify(d)
+    }
+}
+
+impl<V: BitVerify> BitVerify for RangeInclusive<V> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        V::verify(d)?;
+        V::verify(d)
+    }
+}
+
+impl<V: BitVerify> BitVerify for Bound<V> {
+    #[inline]
+    fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+        match d.uint8()? {
+            0 | 1 => V::verify(d),
+            2     => Ok(()),
+            tag   => Err(Error::InvalidEnumVariant { tag: tag as usize }),
+        }
+    }
+}
+
+macro_rules! impl_pointer {
+    ($( $p:ident ),*) => {
+        $(
+            impl<V: BitVerify + ?Sized> BitVerify for $p<V> {
+                #[inline]
+                fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+                    d.nested(V::verify)
+                }
+            }
+        )*
+    }
+}
+
+impl_pointer!(Box, Rc, Arc);
+
+impl BitVerify for () {
+    #[inline(always)]
+    fn verify<'src, S: Source<'src>>(_: &mut Decoder<'src, S>) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: ?Sized> BitVerify for PhantomData<T> {
+    #[inline(always)]
+    fn verify<'src, S: Source<'src>>(_: &mut Decoder<'src, S>) -> Result<()> {
+        Ok(())
+    }
+}
+
+macro_rules! impl_tuple {
+    ($( $l:ident ),*) => {
+        impl<$($l),*> BitVerify for ($($l),*) where
+            $(
+                $l: BitVerify,
+            )*
+        {
+            #[inline(always)]
+            fn verify<'src, S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<()> {
+                $(
+                    $l::verify(d)?;
+                )*
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
+impl_tuple!(A, B, C, D, E, F, G, H, I);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J);
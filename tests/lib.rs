@@ -1,6 +1,47 @@
+#[macro_use]
 extern crate bitsparrow;
+#[cfg(feature = "bincode")]
+extern crate bincode;
+#[cfg(feature = "bitvec")]
+extern crate bitvec;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "compact_str")]
+extern crate compact_str;
+#[cfg(feature = "either")]
+extern crate either;
+#[cfg(feature = "glam")]
+extern crate glam;
+#[cfg(feature = "nalgebra")]
+extern crate nalgebra;
+#[cfg(feature = "num-complex")]
+extern crate num_complex;
+#[cfg(feature = "postcard")]
+extern crate postcard;
+#[cfg(feature = "semver")]
+extern crate semver;
+#[cfg(feature = "smol_str")]
+extern crate smol_str;
+#[cfg(feature = "url")]
+extern crate url;
+#[cfg(feature = "tokio-util")]
+extern crate tokio_util;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
-use bitsparrow::{Encoder, Decoder};
+use bitsparrow::{Encoder, SizeCounter, SortedMap, Decoder, BitEncode, BitEncodeWith, BitDecodeWith, DynEncode, Sink, Source, Container, Error};
+use bitsparrow::batch::Batch;
+use bitsparrow::dump::Dump;
+use bitsparrow::endian::{Le, Be};
+use bitsparrow::fixed::FixedStr;
+use bitsparrow::handshake::{Protocol, Hello, Accept};
+use bitsparrow::intern::{Interner, Interned};
+use bitsparrow::framing::{cobs, sync};
+use bitsparrow::net;
+use bitsparrow::sink::{Slice, Writer, Hashed};
+use bitsparrow::source::{self, Chain, Reader};
 
 #[test]
 fn eat_own_dog_food() {
@@ -158,8 +199,11 @@ test_type!(size_4, size, 0x0FFFFFFF_usize);
 test_type!(size_5, size, 0x07FFFFFFFF_usize);
 test_type!(size_6, size, 0x03FFFFFFFFFF_usize);
 test_type!(size_7, size, 0x01FFFFFFFFFFFF_usize);
+#[cfg(not(feature = "wasm-opt"))]
 test_type!(size_8, size, 0x00FFFFFFFFFFFFFF_usize);
+#[cfg(not(feature = "wasm-opt"))]
 test_type!(size_9, size, 0xFFFFFFFFFFFFFFFF_usize);
+#[cfg(not(feature = "wasm-opt"))]
 test_type!(size_max, size, ::std::usize::MAX);
 test_type!(uint8_zero, uint8, 0_u8);
 test_type!(uint8_max, uint8, ::std::u8::MAX);
@@ -329,3 +373,3579 @@ fn encode_decode_slices() {
 
     assert_eq!(data, decoded);
 }
+
+#[test]
+fn container_roundtrip() {
+    let container = Container::new(7).checksum(true);
+    let buffer = container.encode(("foo", 3.14f32));
+
+    assert_eq!(&buffer[..4], b"BSPR");
+
+    let (header, value): (Container, (String, f32)) = Container::decode(&buffer).unwrap();
+
+    assert_eq!(header, container);
+    assert_eq!(value, ("foo".into(), 3.14));
+}
+
+#[test]
+fn container_detects_corruption() {
+    let mut buffer = Container::new(1).checksum(true).encode("foo");
+    let last = buffer.len() - 1;
+    buffer[last] ^= 0xFF;
+
+    match Container::decode::<&str>(&buffer) {
+        Err(Error::InvalidData) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    match Container::decode::<&str>(b"JSON\x00\x01\x00\x03foo") {
+        Err(Error::InvalidData) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn container_read_write() {
+    let mut file = Vec::new();
+    Container::new(2).write(&mut file, [1u16, 2, 3]).unwrap();
+
+    let (header, value): (Container, Vec<u16>) = Container::read(&file[..]).unwrap();
+
+    assert_eq!(header.version(), 2);
+    assert!(!header.has_checksum());
+    assert_eq!(value, &[1, 2, 3]);
+}
+
+#[test]
+fn container_wire_version() {
+    use bitsparrow::WIRE_VERSION;
+
+    let container = Container::new(1).wire_version(true).checksum(true);
+    let buffer = container.encode(5u16);
+
+    assert_eq!(&buffer[7..9], &Encoder::encode(WIRE_VERSION)[..]);
+
+    let (header, value): (Container, u16) = Container::decode(&buffer).unwrap();
+
+    assert_eq!(header.embedded_wire_version(), Some(WIRE_VERSION));
+    assert_eq!(Container::new(1).embedded_wire_version(), None);
+    assert_eq!(value, 5);
+
+    let mut newer = Container::new(1).wire_version(true).encode(5u16);
+    newer[7..9].copy_from_slice(&Encoder::encode(WIRE_VERSION + 1));
+
+    match Container::decode::<u16>(&newer) {
+        Err(Error::UnsupportedWireVersion { version }) => assert_eq!(version, WIRE_VERSION + 1),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(decoder.wire_version(), WIRE_VERSION);
+    assert!(decoder.set_wire_version(WIRE_VERSION + 1).is_err());
+    assert_eq!(decoder.set_wire_version(0).unwrap().wire_version(), 0);
+}
+
+#[test]
+fn handshake_negotiation() {
+    let client = Protocol::new().version(1, 'a').version(2, 'b');
+    let server = Protocol::new().version(2, 'B').version(3, 'C');
+
+    let buffer = Encoder::encode(client.hello());
+    let hello: Hello = Decoder::decode(&buffer).unwrap();
+
+    assert_eq!(hello.versions, &[1, 2]);
+
+    let buffer = Encoder::encode(server.accept(&hello).unwrap());
+    let accept: Accept = Decoder::decode(&buffer).unwrap();
+
+    assert_eq!(accept, Accept { version: 2 });
+    assert_eq!(client.options(accept.version), Some(&'b'));
+    assert_eq!(server.options(accept.version), Some(&'B'));
+}
+
+#[test]
+fn handshake_no_common_version() {
+    let server = Protocol::new().version(3, ());
+
+    assert_eq!(server.accept(&Hello { versions: vec![1, 2] }), None);
+}
+
+#[test]
+fn decode_fields_since_version() {
+    let old = Encoder::new().string("foo").end();
+    let new = Encoder::new().string("foo").uint32(42).end();
+
+    let mut decoder = Decoder::new(&old);
+    decoder.set_version(1);
+    assert_eq!(decoder.string().unwrap(), "foo");
+    assert_eq!(decoder.read_since::<u32>(2).unwrap(), 0);
+    assert!(decoder.end());
+
+    let mut decoder = Decoder::new(&new);
+    decoder.set_version(2);
+    assert_eq!(decoder.string().unwrap(), "foo");
+    assert_eq!(decoder.read_since::<u32>(2).unwrap(), 42);
+    assert!(decoder.end());
+
+    let mut decoder = Decoder::new(&new);
+    assert_eq!(decoder.version(), u16::MAX);
+    assert_eq!(decoder.string().unwrap(), "foo");
+    assert_eq!(decoder.read_since::<u32>(2).unwrap(), 42);
+}
+
+#[test]
+fn cobs_roundtrip() {
+    let long: Vec<u8> = (0..600).map(|i| (i % 255) as u8 + 1).collect();
+    let samples: &[&[u8]] = &[
+        &[],
+        &[0],
+        &[0, 0],
+        &[1, 0, 2, 0],
+        &long[..254],
+        &long[..255],
+        &long,
+    ];
+
+    for &sample in samples {
+        let frame = cobs::encode(sample);
+
+        assert_eq!(frame.last(), Some(&0));
+        assert!(!frame[..frame.len() - 1].contains(&0));
+        assert_eq!(cobs::decode(&frame).unwrap(), sample);
+    }
+}
+
+#[test]
+fn cobs_frames() {
+    let mut stream = vec![0, 0];
+    stream.extend(cobs::encode(&Encoder::new().string("foo").uint8(0).end()));
+    stream.extend(cobs::encode(&Encoder::new().string("bar").uint8(0).end()));
+    stream.extend(&[0x05, 0x01]);
+
+    let mut frames = cobs::frames(&stream);
+    let mut decoded = Vec::new();
+
+    for frame in &mut frames {
+        let frame = frame.unwrap();
+        let mut decoder = Decoder::new(&frame);
+        decoded.push((decoder.string().unwrap().to_string(), decoder.uint8().unwrap()));
+        assert!(decoder.end());
+    }
+
+    assert_eq!(decoded, vec![("foo".to_string(), 0), ("bar".to_string(), 0)]);
+    assert_eq!(frames.remainder(), &[0x05, 0x01]);
+    assert!(cobs::decode(&[0x05, 0x01]).is_err());
+}
+
+#[test]
+fn sync_resync_after_corruption() {
+    let marker = sync::DEFAULT_MARKER;
+
+    let mut stream = vec![0xDE, 0xAD];
+    stream.extend(sync::encode(&marker, &Encoder::encode(("foo", 1u16))));
+    let corrupted = stream.len();
+    stream.extend(sync::encode(&marker, &Encoder::encode(("bar", 2u16))));
+    stream.extend(sync::encode(&marker, &Encoder::encode(("baz", 3u16))));
+    let incomplete = stream.len();
+    stream.extend(&sync::encode(&marker, &Encoder::encode(("qux", 4u16)))[..6]);
+
+    // Length prefix of the second frame claims to include the third one
+    stream[corrupted + marker.len()] = 0x0C;
+
+    let mut scanner = sync::scan(&marker, &stream);
+    let mut decoded = Vec::new();
+
+    while let Some(frame) = scanner.next() {
+        match Decoder::decode::<(&str, u16)>(frame) {
+            Ok(value) => decoded.push(value),
+            Err(_)    => scanner.resync(),
+        }
+    }
+
+    assert_eq!(decoded, vec![("foo", 1), ("baz", 3)]);
+    assert_eq!(scanner.remainder(), &stream[incomplete..]);
+    assert_eq!(scanner.skipped(), 2 + marker.len() + 1 + 6);
+}
+
+#[test]
+fn udp_send_recv() {
+    use std::net::UdpSocket;
+
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    net::send_to(&client, server.local_addr().unwrap(), ("ping", 42u32)).unwrap();
+
+    let mut buf = [0u8; 64];
+    let (value, addr): ((&str, u32), _) = net::recv_from(&server, &mut buf).unwrap();
+
+    assert_eq!(value, ("ping", 42));
+    assert_eq!(addr, client.local_addr().unwrap());
+
+    let huge = vec![0u8; net::MAX_DATAGRAM_SIZE];
+    let err = net::send_to(&client, server.local_addr().unwrap(), &huge).unwrap_err();
+
+    assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_field_events() {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::{Event, Metadata, Subscriber};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+
+    #[derive(Default)]
+    struct Fields(Vec<String>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    struct Collector(Arc<Mutex<Vec<String>>>);
+
+    impl Subscriber for Collector {
+        fn enabled(&self, _: &Metadata) -> bool { true }
+        fn new_span(&self, _: &Attributes) -> Id { Id::from_u64(1) }
+        fn record(&self, _: &Id, _: &Record) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+
+        fn event(&self, event: &Event) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0.join(" "));
+        }
+    }
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let buffer = Encoder::encode(("foo", 9001u16));
+
+    tracing::subscriber::with_default(Collector(events.clone()), || {
+        let mut decoder = Decoder::new(&buffer);
+        decoder.read::<&str>().unwrap();
+        decoder.uint16().unwrap();
+        assert!(decoder.read::<u16>().is_err());
+    });
+
+    let events = events.lock().unwrap();
+
+    assert_eq!(events[0], "ty=\"string\" offset=0 len=4");
+    assert_eq!(events[1], "ty=\"u16\" offset=4 len=2");
+    assert!(events[2].contains("decoding failed"));
+}
+
+#[test]
+fn profile_nested_fields() {
+    struct Point(f32, f32);
+    struct Shape<'a> {
+        name: &'a str,
+        points: Vec<Point>,
+        origin: Point,
+    }
+
+    impl BitEncode for Point {
+        fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+            e.field("x", self.0).field("y", self.1);
+        }
+    }
+
+    impl<'a> BitEncode for Shape<'a> {
+        fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+            e.field("name", self.name)
+             .field("points", &self.points)
+             .field("origin", &self.origin);
+        }
+    }
+
+    let shape = Shape {
+        name: "triangle",
+        points: vec![Point(0.0, 0.0), Point(1.0, 0.0), Point(0.0, 1.0)],
+        origin: Point(0.5, 0.5),
+    };
+
+    let mut encoder = Encoder::profiled();
+    encoder.write(&shape).write(&shape).uint8(1);
+
+    let total = encoder.end().len();
+    let profile = encoder.take_profile().unwrap();
+    let message = &profile.messages()[0];
+
+    assert_eq!(total, 2 * (9 + 25 + 8) + 1);
+    assert_eq!(profile.messages().len(), 1);
+    assert!(message.name.contains("Shape"));
+    assert_eq!(message.count, 2);
+    assert_eq!(message.bytes, 2 * (9 + 25 + 8));
+    assert_eq!(message.field("name").unwrap().bytes, 18);
+    assert_eq!(message.field("points").unwrap().bytes, 50);
+    assert_eq!(message.field("origin").unwrap().bytes, 16);
+    assert_eq!(message.field("origin.x").unwrap().bytes, 8);
+    assert_eq!(message.field("origin.y").unwrap().count, 2);
+    assert_eq!(message.field("points.x").unwrap().count, 6);
+    assert!(format!("{}", profile).contains("points"));
+    assert!(encoder.take_profile().is_none());
+}
+
+#[test]
+fn error_field_path() {
+    let buffer = Encoder::new().size(2).string("foo").uint8(0xFF).end();
+
+    let err = Decoder::new(&buffer).read::<Vec<String>>().err().unwrap();
+    assert_eq!(err.path().unwrap(), "[1]");
+
+    let err = Decoder::new(&buffer).read_field::<Vec<String>>("names").err().unwrap();
+    assert_eq!(err.path().unwrap(), "names[1]");
+
+    let err = Error::Utf8Encoding.at_field("name").at_index(1).at_field("names").in_type("User");
+    assert_eq!(err.path().unwrap(), "User.names[1].name");
+    assert_eq!(err.to_string(), "Couldn't decode UTF-8 string at `User.names[1].name`");
+
+    match *err.inner() {
+        Error::Utf8Encoding => {},
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn error_io_interop() {
+    use std::io;
+
+    fn read_u32(buffer: &[u8]) -> io::Result<u32> {
+        Ok(Decoder::new(buffer).uint32()?)
+    }
+
+    assert_eq!(read_u32(&[0, 0, 0, 42]).unwrap(), 42);
+    assert_eq!(read_u32(&[0, 0]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+    let err: io::Error = Error::InvalidEnumVariant { tag: 7 }.at_field("kind").into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "Invalid enum variant tag 7 at `kind`");
+
+    let err: Error = io::Error::new(io::ErrorKind::BrokenPipe, "gone").into();
+    let err: io::Error = err.into();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn verify_without_decoding() {
+    let buffer = Encoder::new()
+        .write(&["foo", "bar"][..])
+        .bytes(b"baz")
+        .bool(true)
+        .uint16(9001)
+        .end();
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.verify::<Vec<&str>>().unwrap();
+    decoder.verify::<[u8]>().unwrap();
+    assert!(decoder.bool().unwrap());
+    decoder.verify::<u16>().unwrap();
+    assert!(decoder.end());
+
+    assert!(Decoder::validate::<(Vec<String>, Vec<u8>, bool, u16)>(&buffer).is_ok());
+
+    match Decoder::validate::<(Vec<String>, Vec<u8>, bool)>(&buffer) {
+        Err(Error::BufferNotEmpty) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let invalid = Encoder::new().bytes(&[0xFF, 0xFE]).end();
+
+    match Decoder::validate::<String>(&invalid) {
+        Err(Error::Utf8Encoding) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn size_counter_matches_encoder() {
+    let value = ("foo", vec![true, false, true], [1u64; 3], 300usize, -1i8);
+    let buffer = Encoder::new()
+        .write(&value)
+        .bool(true)
+        .bool(true)
+        .bytes(&[0; 200])
+        .end();
+
+    let size = SizeCounter::default()
+        .write(&value)
+        .bool(true)
+        .bool(true)
+        .bytes(&[0; 200])
+        .end();
+
+    assert_eq!(size, buffer.len());
+    assert_eq!(SizeCounter::count(&value), Encoder::encode(&value).len());
+    assert_eq!(SizeCounter::count(0usize), 1);
+}
+
+#[test]
+fn encoder_sinks() {
+    let payload = vec![7u8; 10000];
+    let expected = Encoder::new().bool(true).bytes(&payload).bool(true).bool(true).end();
+
+    let mut output = Vec::new();
+    let written = {
+        let mut e = Encoder::with_sink(Writer::new(&mut output));
+        e.bool(true).bytes(&payload).bool(true).bool(true);
+        e.end().unwrap()
+    };
+
+    assert_eq!(written, expected.len());
+    assert_eq!(output, expected);
+
+    let mut buffer = [0u8; 4];
+    let mut e = Encoder::with_sink(Slice::new(&mut buffer));
+    assert_eq!(e.string("foo").end().unwrap(), 4);
+
+    match e.string("foobar").end() {
+        Err(Error::BufferTooSmall { needed: 7 }) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert_eq!(e.bool(false).bool(true).end().unwrap(), 1);
+    assert_eq!(e.into_sink().written().unwrap(), 0);
+    assert_eq!(buffer, [0b10, b'f', b'o', b'o']);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn encoder_bytes_mut_sink() {
+    let mut e = Encoder::with_sink(bytes::BytesMut::new());
+
+    let first = e.uint16(9001).bool(true).bool(true).end();
+    let second = e.string("foo").end();
+
+    assert_eq!(&first[..], &Encoder::new().uint16(9001).bool(true).bool(true).end()[..]);
+    assert_eq!(&second[..], &Encoder::encode("foo")[..]);
+}
+
+#[test]
+fn decoder_sources() {
+    let buffer = Encoder::new()
+        .bool(true)
+        .bool(false)
+        .bool(true)
+        .string("hello")
+        .uint32(9001)
+        .write(&["foo", "bar"][..])
+        .end();
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+    assert!(decoder.bool().unwrap());
+    assert!(!decoder.bool().unwrap());
+    assert!(decoder.bool().unwrap());
+    assert!(!decoder.end());
+    assert_eq!(decoder.read::<String>().unwrap(), "hello");
+    assert_eq!(decoder.uint32().unwrap(), 9001);
+    assert_eq!(decoder.position(), 11);
+    assert_eq!(decoder.read::<Vec<String>>().unwrap(), vec!["foo", "bar"]);
+    assert!(decoder.end());
+
+    match decoder.uint8() {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let chain = Chain::new(buffer.chunks(3));
+    let mut decoder = Decoder::with_source(chain);
+    assert_eq!(decoder.read::<(bool, bool, bool)>().unwrap(), (true, false, true));
+
+    match decoder.string() {
+        Err(Error::NotBorrowable) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let mut decoder = Decoder::with_source(Chain::new(vec![&buffer[..1], &buffer[1..]]));
+    decoder.verify::<(bool, bool, bool, String, u32)>().unwrap();
+    assert_eq!(decoder.read::<Vec<&str>>().unwrap(), vec!["foo", "bar"]);
+    assert!(decoder.end());
+}
+
+#[test]
+fn reader_retries_short_reads() {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::io::{self, Read};
+    use std::rc::Rc;
+
+    // Reader receiving more data after reaching its end, like a socket
+    struct Incoming(Rc<RefCell<VecDeque<u8>>>);
+
+    impl Read for Incoming {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.borrow_mut().read(buf)
+        }
+    }
+
+    let incoming = Rc::new(RefCell::new(VecDeque::from(vec![0x12])));
+    let mut decoder = Decoder::with_source(Reader::new(Incoming(incoming.clone())));
+
+    assert!(!decoder.end());
+
+    match decoder.uint16() {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    incoming.borrow_mut().push_back(0x34);
+
+    assert_eq!(decoder.position(), 0);
+    assert_eq!(decoder.uint16().unwrap(), 0x1234);
+
+    incoming.borrow_mut().push_back(b'f');
+
+    let mut reader = Reader::new(Incoming(incoming.clone()));
+
+    assert!(!reader.is_empty());
+
+    match reader.read_vec(3) {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    incoming.borrow_mut().extend(b"oo");
+
+    assert_eq!(reader.read_vec(3).unwrap(), b"foo");
+    assert_eq!(reader.position(), 3);
+
+    // Bytes read before reaching the end are kept as well
+    for owned in [false, true] {
+        incoming.borrow_mut().push_back(b'X');
+
+        let mut reader = Reader::new(Incoming(incoming.clone()));
+        let mut buf = [0; 4];
+
+        assert!(!reader.is_empty());
+
+        incoming.borrow_mut().extend(b"ab");
+
+        let read = match owned {
+            false => reader.read_into(&mut buf).map(|_| buf.to_vec()),
+            true  => reader.read_vec(4),
+        };
+
+        match read {
+            Err(Error::ReadingOutOfBounds) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        incoming.borrow_mut().extend(b"cD");
+
+        let read = match owned {
+            false => reader.read_into(&mut buf).map(|_| buf.to_vec()),
+            true  => reader.read_vec(4),
+        };
+
+        assert_eq!(read.unwrap(), b"Xabc");
+        assert_eq!(reader.read_byte().unwrap(), b'D');
+        assert_eq!(reader.position(), 5);
+        assert!(reader.is_empty());
+    }
+}
+
+#[test]
+fn read_into_existing_collections() {
+    use std::collections::HashMap;
+
+    let buffer = Encoder::new()
+        .write(&["foo", "bar"][..])
+        .write(&[("a", 1u32), ("b", 2)][..])
+        .write(&[("b", 3u32)][..])
+        .end();
+
+    let mut decoder = Decoder::new(&buffer);
+    let mut names = vec!["baz"];
+    let mut map: HashMap<&str, u32> = HashMap::new();
+
+    assert_eq!(decoder.read_into(&mut names).unwrap(), 2);
+    assert_eq!(decoder.read_map_into(&mut map).unwrap(), 2);
+    assert_eq!(decoder.read_map_into(&mut map).unwrap(), 1);
+    assert!(decoder.end());
+
+    assert_eq!(names, vec!["baz", "foo", "bar"]);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map["a"], 1);
+    assert_eq!(map["b"], 3);
+}
+
+#[test]
+fn huge_collection_size_on_short_buffer() {
+    use std::collections::HashMap;
+
+    let short = [0xFF, 0x10, 0, 0, 0, 0, 0, 0, 0];
+    let huge = Encoder::new().size(1 << 40).uint64(1).end();
+
+    // With `wasm-opt` the first size is over `MAX_SAFE_INTEGER`
+    for buffer in &[&short[..], &huge[..]] {
+        match Decoder::decode::<Vec<u64>>(buffer).as_ref().map_err(Error::inner) {
+            Err(&Error::ReadingOutOfBounds) | Err(&Error::LimitExceeded) => {},
+            other => panic!("unexpected {:?}", other),
+        }
+
+        match Decoder::decode::<HashMap<u64, u64>>(buffer).as_ref().map_err(Error::inner) {
+            Err(&Error::ReadingOutOfBounds) | Err(&Error::LimitExceeded) => {},
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn read_n_without_prefix() {
+    let buffer = Encoder::new().string("foo").string("bar").bool(true).bool(true).end();
+
+    let mut decoder = Decoder::new(&buffer);
+    assert_eq!(decoder.read_n::<&str>(2).unwrap(), vec!["foo", "bar"]);
+    assert_eq!(decoder.read_n::<bool>(2).unwrap(), vec![true, true]);
+    assert!(decoder.read_n::<u16>(0).unwrap().is_empty());
+    assert!(decoder.end());
+
+    let err = Decoder::new(&buffer).read_n::<&str>(3).err().unwrap();
+    assert_eq!(err.path().unwrap(), "[2]");
+
+    match *err.inner() {
+        Error::ReadingOutOfBounds => {},
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn encoder_append() {
+    let mut first = Encoder::new();
+    first.string("foo").bool(true);
+
+    let mut second = Encoder::new();
+    second.uint16(9001);
+
+    let raw = Encoder::encode(3.5f32);
+
+    let buffer = Encoder::new()
+        .bool(false)
+        .append(first)
+        .bool(true)
+        .append(second)
+        .bool(true)
+        .append_raw(&raw)
+        .end();
+
+    let expected = Encoder::new()
+        .bool(false)
+        .string("foo")
+        .bool(true)
+        .bool(true)
+        .uint16(9001)
+        .bool(true)
+        .float32(3.5)
+        .end();
+
+    assert_eq!(buffer, expected);
+    assert_eq!(SizeCounter::default().append(Encoder::new()).append_raw(&raw).end(), 4);
+}
+
+#[test]
+fn encoder_mark_rollback() {
+    const MTU: usize = 16;
+
+    let records = ["first", "second", "third"];
+    let mut encoder = Encoder::new();
+    let mut count = 0;
+
+    encoder.bool(true);
+
+    for record in &records {
+        let mark = encoder.mark();
+        encoder.bool(true).string(record);
+
+        if encoder.mark().len() > MTU {
+            encoder.rollback(mark);
+            break;
+        }
+
+        count += 1;
+    }
+
+    let buffer = encoder.bool(false).bool(true).end();
+
+    assert_eq!(count, 2);
+    assert_eq!(buffer, Encoder::new()
+        .bool(true)
+        .bool(true)
+        .string("first")
+        .bool(true)
+        .string("second")
+        .bool(false)
+        .bool(true)
+        .end());
+
+    let mut counter = SizeCounter::default();
+    let mark = counter.mark();
+    counter.string("foo");
+    assert_eq!(counter.rollback(mark).end(), 0);
+}
+
+#[test]
+fn encode_trait_objects() {
+    let messages: Vec<Box<dyn DynEncode>> = vec![
+        Box::new(true),
+        Box::new(true),
+        Box::new(vec!["foo", "bar"]),
+    ];
+
+    let mut encoder = Encoder::profiled();
+    let mut counter = SizeCounter::default();
+
+    for message in &messages {
+        encoder.write(&**message);
+        counter.write(&**message);
+    }
+
+    let buffer = encoder.end();
+
+    assert_eq!(buffer, Encoder::encode((true, true, vec!["foo", "bar"])));
+    assert_eq!(counter.end(), buffer.len());
+    assert_eq!(encoder.take_profile().unwrap().messages()[0].count, 3);
+    assert_eq!(messages[2].size_hint_dyn(), 0);
+}
+
+#[test]
+#[allow(clippy::needless_borrows_for_generic_args)]
+fn encode_through_references() {
+    let name = String::from("foo");
+    let bytes = vec![1u8, 2, 3];
+
+    let buffer = Encoder::new()
+        .write(&&name)
+        .write(&bytes)
+        .write(bytes.clone())
+        .write(&&&42u16)
+        .end();
+
+    let expected = Encoder::new()
+        .string("foo")
+        .bytes(&bytes)
+        .bytes(&bytes)
+        .uint16(42)
+        .end();
+
+    assert_eq!(buffer, expected);
+    assert_eq!(<&&[u8; 4]>::size_hint(), 4);
+}
+
+#[cfg(feature = "either")]
+#[test]
+fn either_roundtrip() {
+    use either::Either;
+
+    let values: Vec<Either<u16, String>> = vec![Either::Left(9001), Either::Right("foo".into())];
+    let buffer = Encoder::encode(&values);
+
+    assert_eq!(buffer, &[0x02, 0x00, 0x23, 0x29, 0x01, 0x03, b'f', b'o', b'o']);
+    assert_eq!(Decoder::decode::<Vec<Either<u16, String>>>(&buffer).unwrap(), values);
+    assert!(Decoder::validate::<Vec<Either<u16, String>>>(&buffer).is_ok());
+
+    match Decoder::decode::<Either<u16, u16>>(&[0x02, 0x00, 0x00]) {
+        Err(Error::InvalidEnumVariant { tag: 2 }) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn integer_wrappers() {
+    use std::num::{Saturating, Wrapping};
+
+    let value = (Wrapping(u32::MAX) + Wrapping(2), Saturating(250i16));
+    let buffer = Encoder::encode(value);
+
+    assert_eq!(buffer, Encoder::encode((1u32, 250i16)));
+    assert_eq!(Decoder::decode::<(Wrapping<u32>, Saturating<i16>)>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(Wrapping<u32>, Saturating<i16>)>(&buffer).is_ok());
+}
+
+#[test]
+fn ranges() {
+    use std::ops::{Range, RangeInclusive};
+
+    let value = (3u32..9, -1i16..=1);
+    let buffer = Encoder::encode(&value);
+
+    assert_eq!(buffer, Encoder::encode((3u32, 9u32, -1i16, 1i16)));
+    assert_eq!(Decoder::decode::<(Range<u32>, RangeInclusive<i16>)>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(Range<u32>, RangeInclusive<i16>)>(&buffer).is_ok());
+}
+
+#[test]
+fn range_bounds() {
+    use std::ops::Bound;
+
+    let value = (Bound::Included(3u32), Bound::Excluded("m"), Bound::<u16>::Unbounded);
+    let buffer = Encoder::encode(value);
+
+    assert_eq!(buffer, &[0, 0, 0, 0, 3, 1, 1, b'm', 2]);
+    assert_eq!(Decoder::decode::<(Bound<u32>, Bound<&str>, Bound<u16>)>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(Bound<u32>, Bound<&str>, Bound<u16>)>(&buffer).is_ok());
+
+    match Decoder::decode::<Bound<u32>>(&[3]) {
+        Err(Error::InvalidEnumVariant { tag: 3 }) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert!(Decoder::validate::<Bound<u32>>(&[3]).is_err());
+}
+
+#[test]
+fn zero_sized_types() {
+    use std::marker::PhantomData;
+
+    let value = (PhantomData::<str>, 42u16, ());
+    let buffer = Encoder::encode(value);
+
+    assert_eq!(buffer, Encoder::encode(42u16));
+    assert_eq!(Decoder::decode::<(PhantomData<str>, u16, ())>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(PhantomData<str>, u16, ())>(&buffer).is_ok());
+}
+
+#[test]
+fn shared_and_boxed_types() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    type Shared = (Arc<str>, Box<str>, Rc<[u8]>, Arc<[u16]>, Box<u32>);
+
+    let value: Shared = (
+        "foo".into(),
+        "bar".into(),
+        Rc::from(&[1u8, 2][..]),
+        Arc::from(&[3u16, 4][..]),
+        Box::new(5),
+    );
+
+    let buffer = Encoder::encode(&value);
+
+    assert_eq!(buffer, Encoder::encode(("foo", "bar", &[1u8, 2][..], &[3u16, 4], 5u32)));
+    assert_eq!(Decoder::decode::<Shared>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<Shared>(&buffer).is_ok());
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+    assert_eq!(&*decoder.read::<Rc<str>>().unwrap(), "foo");
+}
+
+#[cfg(feature = "glam")]
+#[test]
+fn glam_roundtrip() {
+    use glam::{Vec2, Vec3, Quat, Mat4};
+
+    let value = (
+        Vec2::new(1.0, 2.0),
+        Vec3::new(3.0, 4.0, 5.0),
+        Quat::from_rotation_y(0.5),
+        Mat4::from_translation(Vec3::new(6.0, 7.0, 8.0)),
+    );
+
+    let buffer = Encoder::encode(value);
+
+    assert_eq!(buffer.len(), 4 * (2 + 3 + 4 + 16));
+    assert_eq!(&buffer[..8], &Encoder::encode((1.0f32, 2.0f32))[..]);
+    assert_eq!(Decoder::decode::<(Vec2, Vec3, Quat, Mat4)>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(Vec2, Vec3, Quat, Mat4)>(&buffer).is_ok());
+}
+
+#[cfg(feature = "nalgebra")]
+#[test]
+fn nalgebra_roundtrip() {
+    use nalgebra::{Vector3, Matrix2};
+
+    let value = (Vector3::new(1.0f32, 2.0, 3.0), Matrix2::new(1u16, 2, 3, 4));
+    let buffer = Encoder::encode(value);
+
+    // Matrices are column-major
+    assert_eq!(buffer, Encoder::new()
+        .float32(1.0)
+        .float32(2.0)
+        .float32(3.0)
+        .uint16(1)
+        .uint16(3)
+        .uint16(2)
+        .uint16(4)
+        .end());
+    assert_eq!(Decoder::decode::<(Vector3<f32>, Matrix2<u16>)>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(Vector3<f32>, Matrix2<u16>)>(&buffer).is_ok());
+}
+
+#[cfg(feature = "num-complex")]
+#[test]
+fn complex_samples() {
+    use num_complex::Complex;
+
+    let samples = vec![Complex::new(0.5f32, -0.5), Complex::new(1.0, 0.0)];
+    let buffer = Encoder::encode(&samples);
+
+    assert_eq!(buffer, Encoder::new().size(2).write((0.5f32, -0.5f32, 1.0f32, 0.0f32)).end());
+    assert_eq!(Decoder::decode::<Vec<Complex<f32>>>(&buffer).unwrap(), samples);
+    assert!(Decoder::validate::<Vec<Complex<f32>>>(&buffer).is_ok());
+    assert!(Decoder::validate::<Vec<Complex<f32>>>(&buffer[..8]).is_err());
+}
+
+#[cfg(all(feature = "url", feature = "semver"))]
+#[test]
+fn url_and_semver() {
+    use semver::Version;
+    use url::Url;
+
+    let value = (Url::parse("https://bitsparrow.io/docs").unwrap(), Version::parse("2.0.0-rc4").unwrap());
+    let buffer = Encoder::encode(&value);
+
+    assert_eq!(buffer, Encoder::encode(("https://bitsparrow.io/docs", "2.0.0-rc4")));
+    assert_eq!(Decoder::decode::<(Url, Version)>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(Url, Version)>(&buffer).is_ok());
+
+    match Decoder::decode::<Version>(&Encoder::encode("two")) {
+        Err(Error::InvalidData) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert!(Decoder::validate::<Url>(&Encoder::encode("not a url")).is_err());
+}
+
+#[test]
+fn hash_maps_with_custom_hashers() {
+    use std::collections::HashMap;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    type CustomMap<K, V> = HashMap<K, V, BuildHasherDefault<DefaultHasher>>;
+
+    let mut map = CustomMap::default();
+    map.insert("foo".to_string(), 1u32);
+    map.insert("bar".to_string(), 2u32);
+
+    let buffer = Encoder::encode(&map);
+
+    assert_eq!(buffer.len(), 1 + 2 * (4 + 4));
+    assert_eq!(Decoder::decode::<CustomMap<String, u32>>(&buffer).unwrap(), map);
+    assert_eq!(Decoder::decode::<HashMap<&str, u32>>(&buffer).unwrap()["bar"], 2);
+    assert!(Decoder::validate::<CustomMap<String, u32>>(&buffer).is_ok());
+}
+
+#[cfg(all(feature = "compact_str", feature = "smol_str"))]
+#[test]
+fn inline_strings() {
+    use compact_str::CompactString;
+    use smol_str::SmolStr;
+
+    let long = "a string too long to be stored inline".to_string();
+    let value = (CompactString::new("foo"), SmolStr::new("bar"), CompactString::from(long.clone()), SmolStr::new(&long));
+    let buffer = Encoder::encode(&value);
+
+    assert_eq!(buffer, Encoder::encode(("foo", "bar", &long, &long)));
+
+    let decoded: (CompactString, SmolStr, CompactString, SmolStr) = Decoder::decode(&buffer).unwrap();
+
+    assert_eq!(decoded, value);
+    assert!(!decoded.0.is_heap_allocated());
+    assert!(!decoded.1.is_heap_allocated());
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+
+    assert_eq!(decoder.read::<SmolStr>().unwrap(), "foo");
+    assert!(Decoder::validate::<(CompactString, SmolStr)>(&buffer[..8]).is_ok());
+    assert!(Decoder::validate::<SmolStr>(&[0x01, 0xFF]).is_err());
+}
+
+#[cfg(feature = "bitvec")]
+#[test]
+fn bit_vectors() {
+    use bitvec::prelude::*;
+
+    let flags = bitvec![u8, Msb0; 1, 0, 1, 1, 0, 0, 0, 0, 1, 1];
+    let buffer = Encoder::encode(&flags);
+
+    assert_eq!(buffer, vec![0x0A, 0b00001101, 0b00000011]);
+    assert_eq!(Encoder::encode(flags.as_bitslice()), buffer);
+    assert_eq!(Decoder::decode::<BitVec<u8, Msb0>>(&buffer).unwrap(), flags);
+    assert_eq!(Decoder::decode::<BitVec<u64, Lsb0>>(&buffer).unwrap(), flags);
+    assert!(Decoder::validate::<BitSlice>(&buffer).is_ok());
+
+    let empty: BitVec = BitVec::new();
+
+    assert_eq!(Encoder::encode(&empty), vec![0x00]);
+    assert!(Decoder::decode::<BitVec>(&[0x00]).unwrap().is_empty());
+
+    match Decoder::decode::<BitVec>(&[0x0A, 0x0D, 0x07]) {
+        Err(Error::InvalidData) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert!(Decoder::validate::<BitVec>(&[0x0A, 0x0D]).is_err());
+}
+
+#[test]
+fn decode_with_context() {
+    use std::rc::Rc;
+
+    // Strings are stored once, then referenced by their index
+    #[derive(Default)]
+    struct Interner(Vec<Rc<str>>);
+
+    #[derive(Debug, PartialEq)]
+    struct Tag(Rc<str>);
+
+    impl<'src> BitDecodeWith<'src, Interner> for Tag {
+        fn decode_with<S: Source<'src>>(d: &mut Decoder<'src, S>, interner: &mut Interner) -> bitsparrow::Result<Self> {
+            let index = d.size()?;
+
+            if index == interner.0.len() {
+                interner.0.push(d.read()?);
+            }
+
+            interner.0.get(index).cloned().map(Tag).ok_or(Error::InvalidData)
+        }
+    }
+
+    let buffer = Encoder::new()
+                    .size(0).string("foo")
+                    .size(1).string("bar")
+                    .size(0)
+                    .uint16(7)
+                    .size(9)
+                    .end();
+
+    let mut interner = Interner::default();
+    let mut decoder = Decoder::new(&buffer);
+
+    let tags: Vec<Tag> = (0..3).map(|_| decoder.read_with(&mut interner).unwrap()).collect();
+
+    assert_eq!(tags, vec![Tag("foo".into()), Tag("bar".into()), Tag("foo".into())]);
+    assert!(Rc::ptr_eq(&tags[0].0, &tags[2].0));
+    assert_eq!(interner.0.len(), 2);
+    assert_eq!(decoder.read_with::<u16, _>(&mut interner).unwrap(), 7);
+
+    match decoder.read_field_with::<Tag, _>("tag", &mut interner) {
+        Err(err) => assert_eq!(err.path().unwrap(), "tag"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn encode_with_context() {
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Dictionary(HashMap<String, usize>);
+
+    struct Tag<'a>(&'a str);
+
+    impl<'a> BitEncodeWith<Dictionary> for Tag<'a> {
+        fn encode_with<S: Sink>(&self, e: &mut Encoder<S>, dictionary: &mut Dictionary) {
+            let next = dictionary.0.len();
+
+            match dictionary.0.get(self.0) {
+                Some(&index) => { e.size(index); },
+                None => {
+                    dictionary.0.insert(self.0.to_string(), next);
+                    e.size(next).string(self.0);
+                },
+            }
+        }
+    }
+
+    let mut dictionary = Dictionary::default();
+    let mut encoder = Encoder::profiled();
+
+    for tag in &["foo", "bar", "foo"] {
+        encoder.write_with(&Tag(tag), &mut dictionary);
+    }
+
+    encoder.field_with("count", &7u16, &mut dictionary);
+
+    let profile = encoder.take_profile().unwrap();
+    let buffer = encoder.end();
+
+    assert_eq!(buffer, Encoder::new()
+                        .size(0).string("foo")
+                        .size(1).string("bar")
+                        .size(0)
+                        .uint16(7)
+                        .end());
+
+    assert_eq!(dictionary.0.len(), 2);
+    assert_eq!(profile.messages()[0].count, 3);
+    assert_eq!(profile.messages()[0].bytes, 11);
+    assert_eq!(profile.message("").unwrap().field("count").unwrap().bytes, 2);
+}
+
+#[test]
+fn messages_iterator() {
+    let buffer = Encoder::new()
+                    .write(("foo", 1u16))
+                    .write(("bar", 2u16))
+                    .end();
+
+    let mut decoder = Decoder::new(&buffer);
+    let messages: Vec<(&str, u16)> = decoder.messages().collect::<Result<_, _>>().unwrap();
+
+    assert_eq!(messages, vec![("foo", 1), ("bar", 2)]);
+    assert!(decoder.end());
+    assert_eq!(Decoder::new(&[]).messages::<u16>().count(), 0);
+
+    // Truncated last record
+    let mut decoder = Decoder::new(&buffer[..buffer.len() - 1]);
+    let mut offsets = Vec::new();
+
+    {
+        let mut messages = decoder.messages::<(&str, u16)>();
+
+        while let Some(result) = messages.next() {
+            offsets.push((messages.offset(), result.is_ok()));
+        }
+    }
+
+    assert_eq!(offsets, vec![(0, true), (6, false)]);
+}
+
+#[test]
+fn batches() {
+    let mut batch = Batch::with_capacity(64);
+
+    assert!(batch.is_empty());
+
+    batch.push(true).push((false, true)).push("foo");
+
+    assert_eq!(batch.len(), 3);
+
+    let buffer = batch.end();
+
+    assert_eq!(buffer, Encoder::new()
+                        .size(3)
+                        .bytes(&Encoder::encode(true))
+                        .bytes(&Encoder::encode((false, true)))
+                        .bytes(&Encoder::encode("foo"))
+                        .end());
+    assert!(batch.is_empty());
+
+    let mut reader = Batch::read(&buffer).unwrap();
+
+    assert!(reader.decode_next::<bool>().unwrap().unwrap());
+    assert_eq!(reader.decode_next::<(bool, bool)>().unwrap().unwrap(), (false, true));
+    assert_eq!(reader.len(), 1);
+    assert!(!reader.end());
+
+    // Payload not consumed entirely
+    assert!(reader.decode_next::<u16>().unwrap().is_err());
+    assert!(reader.end());
+
+    // Batches nest in other messages
+    batch.push(1u16).push(2u16);
+
+    let nested = Encoder::encode((&batch, 3u32));
+    let mut decoder = Decoder::new(&nested);
+
+    assert_eq!(decoder.size().unwrap(), 2);
+    assert_eq!(decoder.bytes().unwrap(), &[0x00, 0x01]);
+    assert_eq!(decoder.bytes().unwrap(), &[0x00, 0x02]);
+    assert_eq!(decoder.uint32().unwrap(), 3);
+
+    // Truncated batch stops after the first error
+    let reader = Batch::read(&buffer[..buffer.len() - 1]).unwrap();
+    let results: Vec<bool> = reader.map(|payload| payload.is_ok()).collect();
+
+    assert_eq!(results, vec![true, true, false]);
+}
+
+#[test]
+fn hash_while_encoding() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    let mut other = Encoder::new();
+    other.write(("foo", vec![1u16, 2, 3])).bool(true);
+
+    let mut encoder = Encoder::with_sink(Hashed::new(Vec::new(), DefaultHasher::new()));
+
+    encoder
+        .bool(true)
+        .bool(true)
+        .uint32(9001)
+        .append(other)
+        .bool(false)
+        .bool(true)
+        .bytes(&[]);
+
+    let (buffer, hasher) = encoder.into_sink().into_parts();
+
+    let mut expected = DefaultHasher::new();
+    expected.write(&buffer);
+
+    assert_eq!(hasher.finish(), expected.finish());
+
+    // Hashing a sink that doesn't keep the bytes
+    let mut encoder = Encoder::with_sink(Hashed::new(Slice::new(&mut []), DefaultHasher::new()));
+    encoder.write(&buffer[..]);
+
+    let (slice, hasher) = encoder.into_sink().into_parts();
+
+    let mut expected = DefaultHasher::new();
+    expected.write(&Encoder::encode(&buffer[..]));
+
+    assert!(slice.written().is_err());
+    assert_eq!(hasher.finish(), expected.finish());
+}
+
+#[test]
+fn hash_while_decoding() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    let buffer = Encoder::new()
+                    .bool(true)
+                    .bool(false)
+                    .write(("foo", vec![1u16, 2, 3]))
+                    .bytes(&[0xFF; 100])
+                    .uint8(7)
+                    .end();
+
+    let mut expected = DefaultHasher::new();
+    expected.write(&buffer);
+
+    let source = source::Hashed::new(source::Slice::new(&buffer), DefaultHasher::new());
+    let mut decoder = Decoder::with_source(source);
+
+    assert!(decoder.bool().unwrap());
+    assert!(!decoder.bool().unwrap());
+    assert_eq!(decoder.read::<(&str, Vec<u16>)>().unwrap(), ("foo", vec![1, 2, 3]));
+    assert!(decoder.verify::<[u8]>().is_ok());
+    assert_eq!(decoder.uint8().unwrap(), 7);
+
+    let (slice, hasher) = decoder.into_source().into_parts();
+
+    assert!(slice.is_empty());
+    assert_eq!(hasher.finish(), expected.finish());
+
+    let (head, tail) = buffer.split_at(5);
+    let mut decoder = Decoder::with_source(source::Hashed::new(Chain::new(vec![head, tail]), DefaultHasher::new()));
+
+    assert_eq!(decoder.read::<(bool, bool, String, Vec<u16>, Vec<u8>)>().unwrap().4, vec![0xFF; 100]);
+    assert_eq!(decoder.uint8().unwrap(), 7);
+    assert_eq!(decoder.into_source().hasher().finish(), expected.finish());
+}
+
+#[test]
+fn spanned_values() {
+    let buffer = Encoder::new()
+                    .bool(true)
+                    .bool(false)
+                    .write(("foo", 1u32))
+                    .end();
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(decoder.read_spanned::<bool>().unwrap(), (true, 0..1));
+    assert_eq!(decoder.read_spanned::<bool>().unwrap(), (false, 1..1));
+    assert_eq!(decoder.read_spanned::<(String, u32)>().unwrap(), (("foo".into(), 1), 1..9));
+    assert!(decoder.read_spanned::<u16>().is_err());
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[1..]));
+
+    assert_eq!(decoder.read_spanned::<String>().unwrap().1, 0..4);
+}
+
+#[test]
+fn take_rest() {
+    let buffer = Encoder::new().bool(true).string("foo").append_raw(&[1, 2, 3]).end();
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(decoder.read::<(bool, &str)>().unwrap(), (true, "foo"));
+    assert_eq!(decoder.take_rest(), &[1, 2, 3]);
+    assert_eq!(decoder.take_rest(), &[]);
+    assert!(decoder.end());
+
+    let mut decoder = Decoder::new(&buffer[1..]);
+
+    assert_eq!(decoder.take_rest_vec(), &buffer[1..]);
+
+    let (head, tail) = buffer.split_at(3);
+    let mut decoder = Decoder::with_source(Chain::new(vec![head, tail]));
+
+    assert!(decoder.bool().unwrap());
+    assert_eq!(decoder.take_rest_vec(), &buffer[1..]);
+    assert!(decoder.end());
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+
+    assert!(!decoder.end());
+    assert_eq!(decoder.take_rest_vec().unwrap(), buffer);
+    assert_eq!(decoder.position(), buffer.len());
+    assert!(decoder.end());
+}
+
+#[test]
+fn decoder_offsets_and_seeking() {
+    let buffer = Encoder::new().uint32(0xDEADBEEF).bool(true).bool(false).string("foo").end();
+
+    let mut decoder = Decoder::new_at(&buffer, 4);
+
+    assert_eq!(decoder.position(), 4);
+    assert!(decoder.bool().unwrap());
+
+    // Seeking back to the same byte reads the first bool again
+    decoder.seek(4).unwrap();
+
+    assert!(decoder.bool().unwrap());
+    assert!(!decoder.bool().unwrap());
+    assert_eq!(decoder.string().unwrap(), "foo");
+    assert!(decoder.end());
+
+    decoder.rewind();
+
+    assert_eq!(decoder.uint32().unwrap(), 0xDEADBEEF);
+
+    assert!(decoder.seek(buffer.len()).unwrap().end());
+
+    match decoder.seek(buffer.len() + 1) {
+        Err(Error::ReadingOutOfBounds) => {},
+        _ => panic!("seeking past the end should fail"),
+    }
+
+    assert!(Decoder::new_at(&buffer, 100).uint16().is_err());
+}
+
+#[test]
+fn clone_decoders() {
+    let buffer = Encoder::new().bool(true).bool(false).bool(true).string("foo").end();
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert!(decoder.bool().unwrap());
+
+    let mut fork = decoder.clone();
+
+    assert!(!fork.bool().unwrap());
+    assert!(fork.bool().unwrap());
+    assert_eq!(fork.string().unwrap(), "foo");
+    assert!(fork.end());
+
+    assert_eq!(decoder.position(), 1);
+    assert!(!decoder.bool().unwrap());
+
+    let (head, tail) = buffer.split_at(2);
+    let mut decoder = Decoder::with_source(Chain::new(vec![head, tail]));
+
+    decoder.bool().unwrap();
+
+    let mut fork = decoder.clone();
+
+    assert_eq!(fork.read::<(bool, bool, String)>().unwrap(), (false, true, "foo".into()));
+    assert_eq!(decoder.read::<(bool, bool, String)>().unwrap(), (false, true, "foo".into()));
+
+    let decoder = Decoder::with_source(Reader::new(&buffer[..]));
+
+    assert_eq!(decoder.clone().read::<(bool, bool, bool, String)>().unwrap().3, "foo");
+}
+
+#[test]
+fn debug_output() {
+    let mut encoder = Encoder::new();
+    encoder.uint16(0xBEEF).bool(true);
+
+    assert_eq!(
+        format!("{:?}", encoder),
+        "Encoder { len: 3, bool_shift: Some(0), profiled: false, window: [be ef 01 |] }"
+    );
+
+    let buffer = encoder.uint32(0xDEADBEEF).end();
+    let mut decoder = Decoder::new(&buffer);
+
+    decoder.uint16().unwrap();
+    decoder.bool().unwrap();
+
+    assert_eq!(
+        format!("{:?}", decoder),
+        "Decoder { source: Slice { position: 3, len: 7, window: [be ef 01 | de ad be ef] }, \
+         bools: Some((2, 1)), version: 65535 }"
+    );
+
+    let (head, tail) = buffer.split_at(4);
+    let decoder = Decoder::with_source(Chain::new(vec![head, tail]));
+
+    assert!(format!("{:?}", decoder).contains("Chain { position: 0, chunks: 2, remaining: 7, window: [| be ef 01 de] }"));
+
+    let counter = SizeCounter::default();
+
+    assert!(format!("{:?}", counter).contains("window: [|]"));
+}
+
+#[test]
+fn hex_dumps() {
+    let mut data: Vec<u8> = (0u8..20).collect();
+    data[17] = b'A';
+
+    let dump = Dump::new(&data).field(17..18, "letter").field(8..20, "tail").field(30..31, "missing");
+
+    assert_eq!(dump.to_string(), "\
+00000000  00 01 02 03 04 05 06 07  08 09 0a 0b 0c 0d 0e 0f  |................|
+                                   ^ tail [8..20]
+00000010  10 41 12 13                                       |.A..|
+             ^ letter [17..18]
+");
+
+    assert_eq!(Dump::new(&[]).to_string(), "");
+}
+
+#[test]
+fn decode_traces() {
+    use bitsparrow::trace::Value;
+
+    let buffer = Encoder::new()
+                    .bool(true)
+                    .bool(false)
+                    .write((-2i8, 1.5f32, vec!["foo".to_string()], &[1u8, 2][..]))
+                    .end();
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+
+    assert!(decoder.take_trace().is_none());
+
+    decoder.bool().unwrap();
+    decoder.trace();
+
+    let value: (bool, i8, f32, Vec<String>, Vec<u8>) = decoder.read().unwrap();
+
+    assert_eq!(value.3, vec!["foo".to_string()]);
+
+    let trace = decoder.take_trace().unwrap();
+    let events: Vec<(&str, usize, usize, Option<Value>)> = trace
+        .into_iter()
+        .map(|event| (event.ty, event.offset, event.len, event.value.clone()))
+        .collect();
+
+    assert_eq!(events, vec![
+        ("bool", 1, 0, Some(Value::Bool(false))),
+        ("i8", 1, 1, Some(Value::Int(-2))),
+        ("f32", 2, 4, Some(Value::Float(1.5))),
+        ("size", 6, 1, Some(Value::Size(1))),
+        ("string", 7, 4, Some(Value::String("foo".into()))),
+        ("bytes", 11, 3, Some(Value::Bytes(vec![1, 2]))),
+    ]);
+    assert!(trace.failure().is_none());
+    assert_eq!(trace.events()[4].bytes(&buffer), b"\x03foo");
+    assert!(trace.to_string().starts_with("       1      0 bool     false\n"));
+
+    let mut decoder = Decoder::new(&buffer[..4]);
+
+    decoder.trace();
+
+    assert!(decoder.read::<(bool, bool, i8, f32)>().is_err());
+
+    let trace = decoder.take_trace().unwrap();
+
+    assert_eq!(trace.events().len(), 4);
+    assert_eq!(trace.failure().unwrap().ty, "f32");
+    assert_eq!(trace.failure().unwrap().offset, 2);
+}
+
+#[test]
+fn sized_envelopes() {
+    let buffer = Encoder::new()
+                    .sized((true, 1u16, "new field"))
+                    .sized(false)
+                    .uint8(7)
+                    .end();
+
+    assert_eq!(buffer[0] as usize, 1 + 2 + 10);
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(decoder.read_sized::<(bool, u16)>().unwrap(), (true, 1));
+
+    // Bools don't stack across envelopes
+    assert!(!decoder.read_sized::<bool>().unwrap());
+    assert_eq!(decoder.uint8().unwrap(), 7);
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+
+    assert_eq!(decoder.read_sized::<(bool, u16, String)>().unwrap().2, "new field");
+
+    match Decoder::new(&Encoder::new().size(1).uint16(1).end()).read_sized::<u16>() {
+        Err(Error::InvalidData) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert!(Decoder::new(&buffer[..5]).read_sized::<(bool, u16)>().is_err());
+}
+
+#[test]
+fn reserved_bytes() {
+    let buffer = Encoder::new().bool(true).reserved(3).bool(true).reserved(0).uint16(9).end();
+
+    assert_eq!(buffer, &[0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x09]);
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert!(decoder.bool().unwrap());
+    assert!(decoder.skip_reserved(3).unwrap().bool().unwrap());
+    assert_eq!(decoder.uint16().unwrap(), 9);
+    assert!(decoder.skip_reserved(1).is_err());
+
+    let mut decoder = Decoder::with_source(Reader::new(&[0xFF, 0xFF, 0x00, 0x02][..]));
+
+    assert_eq!(decoder.skip_reserved(2).unwrap().uint16().unwrap(), 2);
+}
+
+#[test]
+fn endianness_overrides() {
+    let value = (Le(0xDEADBEEFu32), Be(0xDEADBEEFu32), Le(-2i16), Le(1.5f64), true);
+    let buffer = Encoder::encode(value);
+
+    assert_eq!(&buffer[..8], &[0xEF, 0xBE, 0xAD, 0xDE, 0xDE, 0xAD, 0xBE, 0xEF]);
+    assert_eq!(&buffer[8..10], &[0xFE, 0xFF]);
+    assert_eq!(&buffer[10..18], &1.5f64.to_le_bytes());
+    assert_eq!(Decoder::decode::<(Le<u32>, Be<u32>, Le<i16>, Le<f64>, bool)>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(Le<u32>, u32, Le<i16>, Le<f64>, bool)>(&buffer).is_ok());
+    assert!(Decoder::validate::<Le<u64>>(&buffer[..7]).is_err());
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+
+    assert_eq!(decoder.read::<Le<u32>>().unwrap(), Le(0xDEADBEEF));
+    assert_eq!(SizeCounter::count(&[Le(1u64), Le(2)][..]), 17);
+}
+
+#[test]
+fn fixed_strings() {
+    let value = (FixedStr::<4>::new("ab"), FixedStr::<3>::new("żółw"), FixedStr::<2>::new(""), 1u16);
+    let buffer = Encoder::encode(&value);
+
+    assert_eq!(buffer, b"ab\0\0\xc5\xbc\0\0\0\0\x01");
+    assert_eq!(value.1.as_str(), "ż");
+    assert_eq!(Decoder::decode::<(FixedStr<4>, FixedStr<3>, FixedStr<2>, u16)>(&buffer).unwrap(), value);
+    assert!(Decoder::validate::<(FixedStr<4>, FixedStr<3>)>(&buffer[..7]).is_ok());
+    assert!(Decoder::with_source(Reader::new(&buffer[..7])).verify::<(FixedStr<4>, FixedStr<3>)>().is_ok());
+    assert!(Decoder::with_source(Reader::new(&b"\xc5\0\0"[..])).verify::<FixedStr<3>>().is_err());
+    assert!(Decoder::with_source(Reader::new(&buffer[..6])).verify::<(FixedStr<4>, FixedStr<3>)>().is_err());
+    assert_eq!(SizeCounter::count(&value), 11);
+
+    match Decoder::decode::<FixedStr<2>>(&[0xFF, 0x00]) {
+        Err(Error::Utf8Encoding) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert!(Decoder::decode::<FixedStr<4>>(b"abc").is_err());
+    assert_eq!(Decoder::decode::<FixedStr<0>>(&[]).unwrap().to_string(), "");
+}
+
+#[test]
+fn packed_bits() {
+    let mut encoder = Encoder::new();
+
+    encoder
+        .bits(0b1010, 4)
+        .bits(0xFF, 3)
+        .bool(true)
+        .bits(0b10, 2)
+        .uint8(0xAB)
+        .bits(0x5A, 8)
+        .bool(false)
+        .bits(0b1, 1);
+
+    let mark = encoder.mark();
+    encoder.bits(0b11, 2);
+    encoder.rollback(mark).bits(0b10, 2);
+
+    let buffer = encoder.end();
+
+    assert_eq!(buffer, &[0b11111010, 0b00000010, 0xAB, 0x5A, 0b00001010]);
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(decoder.bits(4).unwrap(), 0b1010);
+    assert_eq!(decoder.bits(3).unwrap(), 0b111);
+    assert!(decoder.bool().unwrap());
+    assert_eq!(decoder.bits(2).unwrap(), 0b10);
+    assert_eq!(decoder.uint8().unwrap(), 0xAB);
+    assert_eq!(decoder.bits(8).unwrap(), 0x5A);
+    assert!(!decoder.bool().unwrap());
+    assert_eq!(decoder.bits(1).unwrap(), 1);
+    assert_eq!(decoder.bits(2).unwrap(), 0b10);
+    assert!(decoder.end());
+    assert!(decoder.bits(5).is_err());
+}
+
+#[test]
+fn alignment() {
+    let buffer = Encoder::new()
+                    .bool(true)
+                    .align(8)
+                    .write(&[1u64, 2][..])
+                    .align(8)
+                    .align(1)
+                    .uint16(3)
+                    .end();
+
+    assert_eq!(buffer.len(), 8 + 1 + 16 + 7 + 2);
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert!(decoder.bool().unwrap());
+    assert_eq!(decoder.align(8).unwrap().position(), 8);
+    assert_eq!(decoder.read::<Vec<u64>>().unwrap(), vec![1, 2]);
+    assert_eq!(decoder.align(8).unwrap().align(1).unwrap().uint16().unwrap(), 3);
+
+    // Alignment is relative to the start of the buffer
+    let mut decoder = Decoder::new_at(&buffer, 25);
+
+    assert_eq!(decoder.align(8).unwrap().position(), 32);
+
+    let mut decoder = Decoder::new(&buffer[..4]);
+
+    assert!(decoder.align(8).unwrap().bool().unwrap());
+    assert!(decoder.align(8).is_err());
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn pod_slices() {
+    use std::borrow::Cow;
+
+    let buffer = Encoder::new()
+                    .write(1u32)
+                    .write(2u32)
+                    .write(-3i16)
+                    .write(1.5f64)
+                    .bytes(b"abc")
+                    .end();
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(&*decoder.pod_slice::<u32>(2).unwrap(), &[1, 2]);
+    assert_eq!(&*decoder.pod_slice::<i16>(1).unwrap(), &[-3]);
+    assert_eq!(&*decoder.pod_slice::<f64>(1).unwrap(), &[1.5]);
+    assert_eq!(decoder.uint8().unwrap(), 3);
+
+    match decoder.pod_slice::<u8>(3).unwrap() {
+        Cow::Borrowed(bytes) => assert_eq!(bytes, b"abc"),
+        Cow::Owned(_) => panic!("bytes should be borrowed"),
+    }
+
+    assert!(decoder.end());
+    assert!(decoder.pod_slice::<u16>(1).is_err());
+    assert!(Decoder::new(&buffer).pod_slice::<u64>(usize::MAX).is_err());
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+
+    assert_eq!(&*decoder.pod_slice::<u32>(2).unwrap(), &[1, 2]);
+    assert_eq!(decoder.allocated(), 8);
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+    decoder.set_max_allocation(7);
+
+    match decoder.pod_slice::<u32>(2) {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn string_collections() {
+    let long = "x".repeat(300);
+    let owned = vec![String::from("foo"), String::new(), long.clone()];
+    let borrowed: Vec<&str> = owned.iter().map(String::as_str).collect();
+
+    let buffer = Encoder::new().strings(&owned).end();
+
+    assert_eq!(buffer, Encoder::encode(&owned));
+    assert_eq!(Encoder::new().strings(&borrowed).end(), buffer);
+    assert_eq!(Encoder::new().strings(borrowed.iter()).end(), buffer);
+    assert_eq!(Decoder::decode::<Vec<String>>(&buffer).unwrap(), owned);
+
+    let empty: [&str; 0] = [];
+
+    assert_eq!(Encoder::new().strings(&empty).end(), &[0x00]);
+}
+
+#[test]
+fn recursion_limit() {
+    #[derive(Debug, PartialEq)]
+    struct Node {
+        value: u16,
+        children: Vec<Node>,
+    }
+
+    impl BitEncode for Node {
+        fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+            e.write(self.value).write(&self.children);
+        }
+    }
+
+    impl<'src> bitsparrow::BitDecode<'src> for Node {
+        fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> bitsparrow::Result<Self> {
+            Ok(Node {
+                value: d.read()?,
+                children: d.read()?,
+            })
+        }
+    }
+
+    let tree = Node {
+        value: 1,
+        children: vec![Node { value: 2, children: vec![] }],
+    };
+    let buffer = Encoder::encode(&tree);
+
+    assert_eq!(Decoder::decode::<Node>(&buffer).unwrap(), tree);
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_max_depth(1);
+
+    match decoder.read::<Node>().as_ref().map_err(Error::inner) {
+        Err(&Error::LimitExceeded) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Each level is a `u16` and a single element list, nested far
+    // deeper than the default limit
+    let mut malicious = Vec::new();
+    for _ in 0..100_000 {
+        malicious.extend_from_slice(&[0x00, 0x00, 0x01]);
+    }
+
+    match Decoder::decode::<Node>(&malicious).as_ref().map_err(Error::inner) {
+        Err(&Error::LimitExceeded) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let boxed = Encoder::encode(Box::new(Box::new(7u16)));
+    let mut decoder = Decoder::new(&boxed);
+    decoder.set_max_depth(1);
+
+    assert!(decoder.clone().read::<Box<Box<u16>>>().is_err());
+    assert_eq!(*decoder.read::<Box<u16>>().unwrap(), 7);
+    assert!(Decoder::validate::<Vec<Vec<u16>>>(&[0x01; 1000]).is_err());
+}
+
+#[test]
+fn element_limit() {
+    use std::collections::HashMap;
+
+    let buffer = Encoder::encode(vec![vec![1u16, 2], vec![3, 4, 5]]);
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_max_elements(3);
+
+    assert_eq!(decoder.clone().read::<Vec<Vec<u16>>>().unwrap().len(), 2);
+
+    decoder.set_max_elements(2);
+
+    let err = decoder.read::<Vec<Vec<u16>>>().err().unwrap();
+    assert_eq!(err.path().unwrap(), "[1]");
+
+    match *err.inner() {
+        Error::LimitExceeded => {},
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+
+    let mut map = HashMap::new();
+    map.insert(1u16, true);
+    map.insert(2u16, false);
+    let buffer = Encoder::encode(&map);
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_max_elements(1);
+
+    assert!(decoder.clone().read::<HashMap<u16, bool>>().is_err());
+    assert!(decoder.verify::<HashMap<u16, bool>>().is_err());
+
+    // The limit is checked before anything is allocated for the elements
+    let mut decoder = Decoder::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    decoder.set_max_elements(1_000_000);
+
+    match decoder.read::<Vec<u16>>() {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn timestamps() {
+    use std::time::{Duration, UNIX_EPOCH};
+    use bitsparrow::time::Timestamp;
+
+    for &millis in &[0u64, 127, 128, 1_700_000_000_123, u64::MAX] {
+        let buffer = Encoder::encode(Timestamp(millis));
+
+        assert_eq!(buffer.len(), SizeCounter::count(Timestamp(millis)));
+        assert_eq!(Decoder::decode::<Timestamp>(&buffer).unwrap().millis(), millis);
+        assert!(Decoder::validate::<Timestamp>(&buffer).is_ok());
+    }
+
+    // Same format as `size`
+    assert_eq!(Encoder::encode(Timestamp(300)), Encoder::encode(300usize));
+    assert_eq!(Timestamp::from(UNIX_EPOCH - Duration::from_secs(1)), Timestamp(0));
+    assert!(Timestamp::now() > Timestamp::from_millis(1_700_000_000_000));
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn chrono_timestamps() {
+    use chrono::{DateTime, Utc};
+    use bitsparrow::time::Timestamp;
+
+    let time = DateTime::<Utc>::from_timestamp_millis(1_700_000_000_123).unwrap();
+    let timestamp = Timestamp::from(time);
+
+    assert_eq!(timestamp.millis(), 1_700_000_000_123);
+    assert_eq!(timestamp.to_date_time(), Some(time));
+    assert_eq!(Timestamp(u64::MAX).to_date_time(), None);
+}
+
+#[test]
+fn acknowledgements() {
+    use bitsparrow::seq::{Seq, AckRange};
+
+    assert_eq!(Encoder::encode(Seq(5)), &[0x05]);
+    assert_eq!(Decoder::decode::<Seq>(&Encoder::encode(Seq(u64::MAX))).unwrap(), Seq(u64::MAX));
+    assert_eq!(Seq(5).next(), Seq(6));
+
+    let mut acks = AckRange::new();
+    for &seq in &[5u64, 3, 4, 10, 8, 9, 20, u64::MAX] {
+        acks.insert(Seq(seq));
+    }
+
+    assert_eq!(acks.ranges(), &[3..6, 8..11, 20..21]);
+    assert_eq!(AckRange::from_ranges(vec![8..11, 3..5, 4..6, 7..7, 20..21]), acks);
+
+    let buffer = Encoder::encode(&acks);
+
+    assert_eq!(buffer, &[0x03, 0x03, 0x03, 0x02, 0x03, 0x09, 0x01]);
+    assert_eq!(Decoder::decode::<AckRange>(&buffer).unwrap(), acks);
+    assert!(Decoder::validate::<AckRange>(&buffer).is_ok());
+    assert!(Decoder::decode::<AckRange>(&[0x00]).unwrap().is_empty());
+
+    // Verifying doesn't collect the ranges
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_max_allocation(0);
+
+    assert!(decoder.verify::<AckRange>().is_ok());
+    assert_eq!(decoder.allocated(), 0);
+
+    // Empty, touching and overflowing ranges
+    assert!(Decoder::decode::<AckRange>(&[0x01, 0x03, 0x00]).is_err());
+    assert!(Decoder::decode::<AckRange>(&[0x02, 0x03, 0x01, 0x00, 0x01]).is_err());
+    assert!(Decoder::validate::<AckRange>(&[0x02, 0x03, 0x01, 0x00, 0x01]).is_err());
+
+    let mut overflow = vec![0x01];
+    overflow.extend_from_slice(&Encoder::encode(Seq(u64::MAX)));
+    overflow.push(0x01);
+
+    assert!(Decoder::decode::<AckRange>(&overflow).is_err());
+}
+
+#[test]
+fn vectored_writes() {
+    use std::io;
+
+    // Takes at most 1000 bytes at a time, counting vectored writes
+    struct Chunked {
+        output: Vec<u8>,
+        vectored: usize,
+    }
+
+    impl io::Write for Chunked {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(1000);
+            self.output.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> {
+            self.vectored += 1;
+            let buf = bufs.iter().find(|buf| !buf.is_empty()).map_or(&[][..], |buf| &buf[..]);
+            self.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let payload: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+    let text = "x".repeat(2000);
+    let expected = Encoder::new()
+                    .uint16(1)
+                    .bytes(&payload)
+                    .bool(true)
+                    .string(&text)
+                    .bool(true)
+                    .bool(true)
+                    .end();
+
+    let mut e = Encoder::with_sink(Writer::new(Chunked { output: Vec::new(), vectored: 0 }));
+    e.uint16(1).bytes(&payload).bool(true).string(&text).bool(true).bool(true);
+
+    assert_eq!(e.end().unwrap(), expected.len());
+
+    let writer = e.into_sink().into_inner();
+
+    assert_eq!(writer.output, expected);
+    assert!(writer.vectored >= 2);
+}
+
+#[test]
+fn pooled_buffers() {
+    use bitsparrow::sink::BufferProvider;
+
+    #[derive(Default)]
+    struct Pool {
+        free: Vec<Vec<u8>>,
+        acquired: usize,
+    }
+
+    impl BufferProvider for Pool {
+        fn acquire(&mut self) -> Vec<u8> {
+            self.acquired += 1;
+            self.free.pop().unwrap_or_default()
+        }
+
+        fn release(&mut self, mut buf: Vec<u8>) {
+            buf.clear();
+            self.free.push(buf);
+        }
+    }
+
+    let mut pool = Pool::default();
+
+    {
+        let mut encoder = Encoder::with_provider(&mut pool);
+
+        let first = encoder.uint16(1).bool(true).end();
+        let capacity = first.capacity();
+        let pointer = first.as_ptr();
+
+        assert_eq!(first, Encoder::new().uint16(1).bool(true).end());
+
+        encoder.release(first);
+
+        let second = encoder.write(vec![2u16; 4]).end();
+
+        assert_eq!(second, Encoder::encode(vec![2u16; 4]));
+
+        let third = encoder.bool(true).end();
+
+        assert_eq!(third.as_ptr(), pointer);
+        assert_eq!(third.capacity(), capacity);
+        assert_eq!(third, &[0x01]);
+    }
+
+    assert_eq!(pool.acquired, 4);
+    assert_eq!(pool.free.len(), 0);
+}
+
+#[test]
+fn boxed_and_shrunk() {
+    let buffer = Encoder::encode(("foo", &b"bar"[..], vec![1u16]));
+
+    let (string, bytes, numbers): (Box<str>, Box<[u8]>, Box<[u16]>) = Decoder::decode(&buffer).unwrap();
+
+    assert_eq!(&*string, "foo");
+    assert_eq!(&*bytes, b"bar");
+    assert_eq!(&*numbers, &[1]);
+
+    let buffer = Encoder::encode(vec![vec![1u16], vec![2, 3]]);
+
+    let mut decoder = Decoder::new(&buffer);
+    let grown: Vec<Vec<u16>> = decoder.clone().read().unwrap();
+
+    decoder.set_shrink_to_fit(true);
+
+    let shrunk: Vec<Vec<u16>> = decoder.read().unwrap();
+
+    assert_eq!(shrunk, grown);
+    assert!(shrunk.iter().all(|vec| vec.capacity() == vec.len()));
+}
+
+#[test]
+fn messages_recovery() {
+    use bitsparrow::Recovery;
+
+    let buffer = Encoder::new()
+                    .sized("foo")
+                    .sized(&b"\xFF\xFE"[..])
+                    .sized("bar")
+                    // Value extending past its envelope
+                    .append_raw(&[0x02, 0x05, b'a'])
+                    .sized("baz")
+                    .end();
+
+    let mut decoder = Decoder::new(&buffer);
+    let names: Vec<Option<&str>> = decoder.messages::<&str>()
+                                          .recovery(Recovery::SkipToNextFrame)
+                                          .map(Result::ok)
+                                          .collect();
+
+    assert_eq!(names, vec![Some("foo"), None, Some("bar"), None, Some("baz")]);
+
+    // Invalid UTF-8 is consumed before it fails to decode
+    let unframed = [0x01, 0xFF, 0x03, b'f', b'o', b'o'];
+    let mut decoder = Decoder::new(&unframed);
+
+    assert_eq!(decoder.messages::<&str>().count(), 1);
+
+    let mut decoder = Decoder::new(&unframed);
+    let names: Vec<Option<&str>> = decoder.messages::<&str>()
+                                          .recovery(Recovery::YieldError)
+                                          .map(Result::ok)
+                                          .collect();
+
+    assert_eq!(names, vec![None, Some("foo")]);
+
+    // Failing without consuming anything ends the iteration
+    let mut decoder = Decoder::new(&[0x00, 0x01, 0x02]);
+    let values: Vec<_> = decoder.messages::<u16>()
+                                .recovery(Recovery::YieldError)
+                                .collect();
+
+    assert_eq!(values.len(), 2);
+    assert!(values[1].is_err());
+
+    // Envelope extending past the buffer
+    let truncated = Encoder::new().sized(1u16).append_raw(&[0x05, 0x00]).end();
+    let mut decoder = Decoder::new(&truncated);
+    let mut messages = decoder.messages::<u16>().recovery(Recovery::SkipToNextFrame);
+
+    assert_eq!(messages.next().unwrap().unwrap(), 1);
+    assert!(messages.next().unwrap().is_err());
+    assert_eq!(messages.offset(), 3);
+    assert!(messages.next().is_none());
+}
+
+compat_test!(golden_handshake, Hello, Hello { versions: vec![1, 2] }, &[0x02, 0x00, 0x01, 0x00, 0x02]);
+
+#[test]
+fn golden_buffers() {
+    use std::panic::catch_unwind;
+    use bitsparrow::testing::{assert_compat, assert_decodes, assert_encodes};
+
+    assert_compat(&(true, 5u16), &[0x01, 0x00, 0x05]);
+    assert_decodes(&[0x00, 0x05], &5u16);
+    assert_encodes("foo", b"\x03foo");
+
+    assert!(catch_unwind(|| assert_encodes(&5u32, &[0x00, 0x05])).is_err());
+    assert!(catch_unwind(|| assert_decodes(&[0x00, 0x06], &5u16)).is_err());
+    assert!(catch_unwind(|| assert_decodes(&[0x00, 0x05, 0x00], &5u16)).is_err());
+}
+
+#[test]
+fn slice_edge_cases() {
+    let empty: &[u8] = &[];
+    let mut decoder = Decoder::new(empty);
+
+    assert!(decoder.end());
+    assert!(decoder.uint8().is_err());
+    assert!(decoder.uint64().is_err());
+    assert_eq!(decoder.take_rest(), empty);
+
+    // Zero length reads at the end of a buffer
+    let buffer = Encoder::new().bytes(&[]).string("").end();
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(decoder.bytes().unwrap(), empty);
+    assert_eq!(decoder.read::<Vec<u8>>().unwrap(), empty);
+    assert!(decoder.end());
+
+    // Sizes that overflow when added to the position
+    for offset in &[0, 1, 9] {
+        let mut buffer = vec![0xAA; *offset];
+        buffer.extend_from_slice(&[0xFF; 9]);
+
+        let mut decoder = Decoder::new_at(&buffer, *offset);
+
+        assert!(decoder.bytes().is_err());
+        assert!(Decoder::new_at(&buffer, *offset).read::<Vec<u8>>().is_err());
+        assert!(Decoder::new_at(&buffer, *offset).read::<String>().is_err());
+        assert!(Decoder::new_at(&buffer, *offset).verify::<Vec<u8>>().is_err());
+    }
+
+    // Reading from past the end
+    let buffer = Encoder::encode("foo");
+    let mut decoder = Decoder::new_at(&buffer, usize::MAX);
+
+    assert!(decoder.uint8().is_err());
+    assert!(decoder.take_rest().is_empty());
+
+    let mut decoder = Decoder::new_at(&buffer, 10);
+
+    assert!(decoder.bytes().is_err());
+    assert!(decoder.skip_reserved(0).is_err());
+    assert!(Decoder::new_at(&buffer, 4).skip_reserved(0).is_ok());
+
+    // Borrowed slices stay valid alongside clones and other borrows
+    let buffer = Encoder::new().string("foo").string("bar").end();
+    let mut decoder = Decoder::new(&buffer);
+    let mut peek = decoder.clone();
+
+    let foo = decoder.string().unwrap();
+    let bar = decoder.string().unwrap();
+    let again = peek.string().unwrap();
+
+    assert_eq!((foo, bar, again), ("foo", "bar", "foo"));
+    assert_eq!(foo.as_ptr(), again.as_ptr());
+}
+
+#[test]
+fn bools_and_positions() {
+    // Groups on either side of a rollback don't merge
+    let mut encoder = Encoder::new();
+    encoder.bool(true).uint16(1);
+
+    let mark = encoder.mark();
+    encoder.bool(true).bool(true);
+    encoder.rollback(mark);
+
+    assert_eq!(encoder.bits(0b10, 2).end(), &[0b1, 0x00, 0x01, 0b10]);
+
+    let mut encoder = Encoder::new();
+    encoder.bool(true);
+
+    let mark = encoder.mark();
+    encoder.uint16(1).bool(true);
+    encoder.rollback(mark);
+
+    assert_eq!(encoder.bool(true).end(), &[0b11]);
+
+    // Appending an `Encoder` that ended with other data
+    let mut header = Encoder::new();
+    header.bool(true).uint16(1);
+
+    assert_eq!(Encoder::new().bool(true).append(header).bool(true).end(), &[0b1, 0b1, 0x00, 0x01, 0b1]);
+
+    // Seeking back to a stacked byte starts a new group
+    let buffer = Encoder::new().bool(true).bool(false).bool(true).end();
+    let mut decoder = Decoder::new(&buffer);
+
+    assert!(decoder.bool().unwrap());
+    assert!(!decoder.bool().unwrap());
+    assert!(decoder.seek(0).unwrap().bool().unwrap());
+    assert_eq!(decoder.bits(2).unwrap(), 0b10);
+
+    // No group exists past the end of the buffer
+    assert!(Decoder::new_at(&buffer, usize::MAX).bool().is_err());
+    assert!(Decoder::new_at(&buffer, usize::MAX).bits(3).is_err());
+}
+
+#[test]
+fn owned_byte_buffers() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    let blob: Vec<u8> = (0..=255).cycle().take(4096).collect();
+    let expected = Encoder::new().bytes(&blob).end();
+
+    assert_eq!(Encoder::encode(&blob), expected);
+    assert_eq!(Encoder::encode(blob.clone()), expected);
+    assert_eq!(Encoder::encode(blob.clone().into_boxed_slice()), expected);
+    assert_eq!(Encoder::encode(Rc::<[u8]>::from(&blob[..])), expected);
+    assert_eq!(Encoder::encode(Arc::<[u8]>::from(&blob[..])), expected);
+}
+
+#[test]
+fn repeated_runs() {
+    assert_eq!(Encoder::new().repeat(0xAB, 0).end(), &[] as &[u8]);
+    assert_eq!(Encoder::new().repeat(0xAB, 200).end(), vec![0xAB; 200]);
+    assert_eq!(Encoder::new().reserved(3).end(), &[0, 0, 0]);
+
+    let buffer = Encoder::new()
+        .size(300)
+        .repeat_value(&"foo", 300)
+        .end();
+
+    assert_eq!(buffer, Encoder::encode(vec!["foo"; 300]));
+
+    let mut counter = SizeCounter::default();
+    counter.repeat(0, 5).repeat_value(&(1u16, true), 4);
+
+    assert_eq!(counter.end(), 17);
+}
+
+#[test]
+fn exact_reads_into_buffers() {
+    let key: Vec<u8> = (0..32).collect();
+    let buffer = Encoder::new().uint16(7).append_raw(&key).uint16(8).end();
+
+    let mut decoder = Decoder::new(&buffer);
+    let mut out = [0; 32];
+
+    assert_eq!(decoder.uint16().unwrap(), 7);
+    decoder.read_exact_into(&mut out).unwrap();
+    assert_eq!(&out[..], &key[..]);
+    assert_eq!(decoder.uint16().unwrap(), 8);
+    assert!(decoder.read_exact_into(&mut []).is_ok());
+
+    let mut decoder = Decoder::with_source(Chain::new(buffer.chunks(5)));
+    let mut out = [0; 34];
+
+    decoder.read_exact_into(&mut out).unwrap();
+    assert_eq!(&out[2..], &key[..]);
+
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+    let mut out = [0; 64];
+
+    match decoder.read_exact_into(&mut out) {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn byte_arrays_without_prefix() {
+    let hash = [0xAB; 32];
+    let mac = [0x00, 0x1B, 0x44, 0x11, 0x3A, 0xB7];
+    let buffer = Encoder::encode((hash, mac, 1u16));
+
+    assert_eq!(buffer.len(), 40);
+    assert_eq!(&buffer[..32], &hash[..]);
+    assert_eq!(Decoder::decode::<([u8; 32], [u8; 6], u16)>(&buffer).unwrap(), (hash, mac, 1));
+    assert!(Decoder::validate::<([u8; 32], [u8; 6], u16)>(&buffer).is_ok());
+    assert!(Decoder::decode::<[u8; 64]>(&buffer).is_err());
+    assert_eq!(Encoder::encode([0u8; 64]).len(), 64);
+
+    // Buffers written before wire version 2
+    let legacy = Encoder::new().bytes(&mac).bytes(&[1, 2]).end();
+    let mut decoder = Decoder::new(&legacy);
+
+    decoder.set_wire_version(1).unwrap();
+
+    assert!(decoder.clone().verify::<[u8; 6]>().is_ok());
+    assert_eq!(decoder.read::<[u8; 6]>().unwrap(), mac);
+    assert!(decoder.read::<[u8; 3]>().is_err());
+
+    let container = Container::new(1).wire_version(true).encode(mac);
+
+    assert_eq!(Container::decode::<[u8; 6]>(&container).unwrap().1, mac);
+}
+
+#[test]
+fn streamed_map_entries() {
+    use std::collections::HashMap;
+
+    let mut encoder = Encoder::new();
+    encoder.map_header(1000);
+
+    for id in 0..1000u32 {
+        encoder.write(id).write(id % 7 == 0);
+    }
+
+    let buffer = encoder.end();
+    let map: HashMap<u32, bool> = Decoder::decode(&buffer).unwrap();
+
+    assert_eq!(map.len(), 1000);
+
+    let mut decoder = Decoder::with_source(Chain::new(buffer.chunks(7)));
+    let mut count = 0;
+
+    for entry in decoder.map_entries::<u32, bool>().unwrap() {
+        let (id, flag) = entry.unwrap();
+
+        assert_eq!(flag, map[&id]);
+        count += 1;
+    }
+
+    assert_eq!(count, 1000);
+    assert!(decoder.end());
+
+    // Iteration ends after a broken entry
+    let buffer = Encoder::new().map_header(3).uint16(1).string("foo").uint16(2).bytes(b"\xFF").end();
+    let mut decoder = Decoder::new(&buffer);
+    let mut entries = decoder.map_entries::<u16, &str>().unwrap();
+
+    assert_eq!(entries.next().unwrap().unwrap(), (1, "foo"));
+    assert!(entries.next().unwrap().is_err());
+    assert!(entries.next().is_none());
+    assert!(entries.is_empty());
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_max_elements(2);
+
+    assert!(decoder.map_entries::<u16, &str>().is_err());
+}
+
+#[test]
+fn sorted_maps() {
+    use std::collections::HashMap;
+
+    let mut first = HashMap::new();
+    let mut second = HashMap::with_capacity(512);
+
+    for id in 0..300u32 {
+        first.insert(format!("key-{}", id), vec![id]);
+    }
+
+    for id in (0..300u32).rev() {
+        second.insert(format!("key-{}", id), vec![id]);
+    }
+
+    let buffer = Encoder::encode(SortedMap(&first));
+
+    assert_eq!(buffer, Encoder::new().sorted_map(&second).end());
+    assert_eq!(Decoder::decode::<HashMap<String, Vec<u32>>>(&buffer).unwrap(), first);
+    assert_eq!(SizeCounter::count(SortedMap(&first)), buffer.len());
+
+    // Entries follow the order of keys
+    let mut decoder = Decoder::new(&buffer);
+    let keys: Vec<&str> = decoder.map_entries::<&str, Vec<u32>>().unwrap()
+        .map(|entry| entry.unwrap().0)
+        .collect();
+
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // Keys are compared by value, not by their encoded bytes
+    let signed: HashMap<i32, ()> = (-3..3).map(|key| (key, ())).collect();
+    let buffer = Encoder::encode(SortedMap(&signed));
+    let keys: Vec<i32> = Decoder::new(&buffer).map_entries::<i32, ()>().unwrap()
+        .map(|entry| entry.unwrap().0)
+        .collect();
+
+    assert_eq!(keys, &[-3, -2, -1, 0, 1, 2]);
+
+    let words: HashMap<&str, ()> = vec![("b", ()), ("aa", ())].into_iter().collect();
+    let buffer = Encoder::encode(SortedMap(&words));
+    let keys: Vec<&str> = Decoder::new(&buffer).map_entries::<&str, ()>().unwrap()
+        .map(|entry| entry.unwrap().0)
+        .collect();
+
+    assert_eq!(keys, &["aa", "b"]);
+
+    // Nested maps and bools stacked around them
+    let buffer = Encoder::encode((true, vec![SortedMap(&first)], false));
+
+    assert_eq!(buffer, Encoder::encode((true, vec![SortedMap(&second)], false)));
+    assert_eq!(
+        Decoder::decode::<(bool, Vec<HashMap<String, Vec<u32>>>, bool)>(&buffer).unwrap(),
+        (true, vec![first], false)
+    );
+
+    // Keys without an order are still written in iteration order
+    #[derive(PartialEq, Eq, Hash)]
+    struct Key(u8);
+
+    impl BitEncode for Key {
+        fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+            e.uint8(self.0);
+        }
+    }
+
+    let unordered: HashMap<Key, bool> = vec![(Key(1), true)].into_iter().collect();
+
+    assert_eq!(Encoder::encode(&unordered), &[0x01, 0x01, 0x01]);
+}
+
+#[test]
+fn session_interning() {
+    let names = ["user.login", "user.logout", "user.login", "page.view", "user.login"];
+
+    let mut sender = Interner::new().preload(["page.view"]);
+    let mut receiver = Interner::new().preload(["page.view"]);
+
+    let messages: Vec<Vec<u8>> = names
+        .iter()
+        .map(|&name| Encoder::new().write_with(&Interned::from(name), &mut sender).uint16(1).end())
+        .collect();
+
+    assert_eq!(messages[2], &[0x03, 0x00, 0x01]);
+    assert_eq!(messages[3], &[0x02, 0x00, 0x01]);
+
+    for (buffer, name) in messages.iter().zip(&names) {
+        let mut decoder = Decoder::new(buffer);
+        let interned: Interned = decoder.read_with(&mut receiver).unwrap();
+
+        assert_eq!(&*interned, *name);
+        assert_eq!(decoder.uint16().unwrap(), 1);
+    }
+
+    assert_eq!(receiver.len(), 3);
+    assert_eq!(receiver.id("user.logout"), sender.id("user.logout"));
+
+    // Literals and insertions read from sources that can't be borrowed from
+    let stream = messages.concat();
+    let mut receiver = Interner::new().preload(["page.view"]);
+    let mut decoder = Decoder::with_source(Reader::new(&stream[..]));
+
+    for name in &names {
+        let interned: Interned = decoder.read_with(&mut receiver).unwrap();
+
+        assert_eq!(&*interned, *name);
+        assert_eq!(decoder.uint16().unwrap(), 1);
+    }
+
+    let literal = Encoder::new().size(0).string("foo").end();
+    let mut decoder = Decoder::with_source(Reader::new(&literal[..]));
+
+    assert_eq!(&*Interner::new().read(&mut decoder).unwrap(), "foo");
+
+    // Strings are sent in full once the table is full
+    let mut sender = Interner::with_limit(1);
+    let mut encoder = Encoder::new();
+
+    for name in &names[..3] {
+        sender.write(&mut encoder, name);
+    }
+
+    let buffer = encoder.end();
+    let mut receiver = Interner::with_limit(1);
+    let mut decoder = Decoder::new(&buffer);
+
+    for name in &names[..3] {
+        assert_eq!(&*receiver.read(&mut decoder).unwrap(), *name);
+    }
+
+    assert!(receiver.is_full());
+    assert_eq!(receiver.get(0), Some("user.login"));
+
+    // Unknown references and tables growing past the limit
+    assert!(Interner::new().read(&mut Decoder::new(&[0x05])).is_err());
+
+    match Interner::with_limit(0).read(&mut Decoder::new(&Encoder::new().size(1).string("foo").end())) {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn compressed_containers() {
+    use bitsparrow::compress::{Dictionary, Dictionaries};
+
+    let event = |id: u32| (id, "session.heartbeat", "client", "eu-west-1", id.is_multiple_of(3));
+    let samples: Vec<Vec<u8>> = (0..2000).map(|id| Encoder::encode(event(id))).collect();
+
+    let dictionary = Dictionary::train(9, &samples, 2048).unwrap();
+    let container = Container::new(2).checksum(true).wire_version(true);
+
+    let plain = container.encode(event(4242));
+    let compressed = container.encode_compressed(event(4242), &dictionary).unwrap();
+
+    let payload = Encoder::encode(event(4242));
+
+    assert!(compressed.len() < plain.len());
+    assert!(dictionary.compress(&payload).unwrap().len() < payload.len() * 2 / 3);
+
+    let mut dictionaries = Dictionaries::new();
+    dictionaries.insert(Dictionary::new(9, dictionary.as_bytes().to_vec()));
+
+    let (header, value): (Container, (u32, String, String, String, bool)) =
+        Container::decode_compressed(&compressed, &dictionaries).unwrap();
+
+    assert_eq!(header.version(), 2);
+    assert_eq!(header.dictionary(), Some(9));
+    assert_eq!(value.0, 4242);
+    assert_eq!(value.1, "session.heartbeat");
+
+    // Uncompressed containers decode either way
+    let (header, value): (Container, (u32, String, String, String, bool)) =
+        Container::decode_compressed(&plain, &dictionaries).unwrap();
+
+    assert_eq!(header.dictionary(), None);
+    assert_eq!(value.3, "eu-west-1");
+
+    match Container::decode::<(u32, &str, &str, &str, bool)>(&compressed) {
+        Err(Error::DictionaryRequired) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // Unknown dictionaries and corrupted payloads
+    assert!(Container::decode_compressed::<(u32, String)>(&compressed, &Dictionaries::new()).is_err());
+
+    let corrupted = Container::new(2).encode_compressed(event(1), &dictionary).unwrap();
+    let mut corrupted = corrupted[..corrupted.len() - 4].to_vec();
+    corrupted.extend_from_slice(&[0xFF; 4]);
+
+    assert!(Container::decode_compressed::<(u32, String, String, String, bool)>(&corrupted, &dictionaries).is_err());
+}
+
+#[test]
+fn message_metadata() {
+    use bitsparrow::metadata::{self, Metadata};
+
+    let meta = Metadata::new()
+        .with_flags(0b101)
+        .extension(metadata::MESSAGE_TYPE, "invoice.created")
+        .extension(metadata::PRIORITY, 3u16)
+        .extension(metadata::PRIORITY, 9u16)
+        .extension(1000, vec![1u32, 2, 3]);
+
+    let body = Encoder::encode(("invoice", 99u64));
+    let buffer = meta.encode_raw(&body);
+
+    assert!(Decoder::validate::<(Metadata, &str, u64)>(&buffer).is_ok());
+
+    let (mut read, rest) = Metadata::decode(&buffer).unwrap();
+
+    assert_eq!(read, meta);
+    assert_eq!(rest, &body[..]);
+    assert_eq!(read.flags(), 0b101);
+    assert_eq!(read.get::<u16>(metadata::PRIORITY).unwrap().unwrap(), 9);
+    assert_eq!(read.get::<Vec<u32>>(1000).unwrap().unwrap(), &[1, 2, 3]);
+    assert!(read.get::<u16>(metadata::TENANT).is_none());
+    assert_eq!(read.extensions().map(|(key, _)| key).collect::<Vec<_>>(), &[3, 1, 1000]);
+
+    // Rewriting metadata leaves the body as it is
+    read.remove(1000).unwrap();
+    read.insert(metadata::TENANT, Encoder::encode("acme"));
+
+    let forwarded = read.encode_raw(rest);
+    let (meta, rest) = Metadata::decode(&forwarded).unwrap();
+
+    assert_eq!(meta.raw(metadata::TENANT), Some(&b"\x04acme"[..]));
+    assert_eq!(Decoder::decode::<(&str, u64)>(rest).unwrap(), ("invoice", 99));
+    assert_eq!(Metadata::decode(&Metadata::new().encode(())).unwrap().1, &[] as &[u8]);
+
+    // Duplicate keys are rejected
+    let buffer = Encoder::new().uint8(0).size(2).uint16(1).bytes(&[1]).uint16(1).bytes(&[2]).end();
+
+    assert!(Metadata::decode(&buffer).is_err());
+    assert!(Decoder::validate::<Metadata>(&buffer).is_err());
+
+    // Every possible key, then the last one twice
+    let mut encoder = Encoder::new();
+    encoder.uint8(0).size(0x10000);
+
+    for key in 0..=0xFFFFu16 {
+        encoder.uint16(key).bytes(&[]);
+    }
+
+    let buffer = encoder.end();
+
+    assert_eq!(Decoder::decode::<Metadata>(&buffer).unwrap().extensions().count(), 0x10000);
+    assert!(Decoder::validate::<Metadata>(&buffer).is_ok());
+
+    let mut encoder = Encoder::new();
+    encoder.uint8(0).size(0x10001);
+
+    for key in (0..=0xFFFFu16).chain(Some(0xFFFF)) {
+        encoder.uint16(key).bytes(&[]);
+    }
+
+    let buffer = encoder.end();
+
+    assert!(Decoder::decode::<Metadata>(&buffer).is_err());
+    assert!(Decoder::validate::<Metadata>(&buffer).is_err());
+}
+
+#[test]
+fn chunked_payloads() {
+    use bitsparrow::chunked::{self, Chunk, Reassembler, CHUNK_OVERHEAD};
+    use bitsparrow::seq::Seq;
+
+    let first: Vec<u8> = (0..10_000u32).map(|n| (n * 7) as u8).collect();
+    let second = Encoder::encode(vec!["snapshot"; 500]);
+
+    let first_chunks: Vec<Vec<u8>> = chunked::split(&first, 1024).collect();
+    let second_chunks: Vec<Vec<u8>> = chunked::split(&second, 500).collect();
+
+    assert_eq!(chunked::split(&first, 1024).len(), first_chunks.len());
+    assert!(first_chunks.iter().chain(&second_chunks).all(|chunk| chunk.len() <= 1024));
+    assert!(Decoder::validate::<Chunk>(&first_chunks[0]).is_ok());
+
+    // Interleaved transfers with duplicates
+    let mut reassembler = Reassembler::new(64 * 1024);
+    let mut done = Vec::new();
+
+    for (index, chunk) in first_chunks.iter().chain(&second_chunks[..1]).enumerate() {
+        if let Some(payload) = reassembler.push(chunk).unwrap() {
+            done.push(payload);
+        }
+
+        if index % 3 == 0 {
+            assert_eq!(reassembler.push(chunk).unwrap(), None);
+        }
+    }
+
+    assert_eq!(reassembler.pending(), 1);
+
+    for chunk in second_chunks[1..].iter().rev() {
+        if let Some(payload) = reassembler.push(chunk).unwrap() {
+            done.push(payload);
+        }
+    }
+
+    assert_eq!(done, vec![first.clone(), second]);
+    assert_eq!(reassembler.pending(), 0);
+    assert_eq!(reassembler.buffered(), 0);
+
+    // Empty payloads
+    let empty: Vec<Vec<u8>> = chunked::split(&[], CHUNK_OVERHEAD + 1).collect();
+
+    assert_eq!(empty.len(), 1);
+    assert_eq!(Reassembler::new(0).push(&empty[0]).unwrap(), Some(Vec::new()));
+
+    // Corrupted data, conflicting terminal chunks and memory limits
+    let mut chunk: Chunk = Decoder::decode(&first_chunks[1]).unwrap();
+    let mut reassembler = Reassembler::new(64 * 1024);
+    let corrupted = vec![0xFF; chunk.data.len()];
+
+    chunk.data = &corrupted;
+    reassembler.push_chunk(chunk).unwrap();
+
+    for other in first_chunks.iter().skip(2) {
+        reassembler.push(other).unwrap();
+    }
+
+    assert!(reassembler.push(&first_chunks[0]).is_err());
+    assert_eq!(reassembler.pending(), 0);
+    assert_eq!(reassembler.buffered(), 0);
+
+    let terminal = Chunk { hash: 1, seq: Seq(1), last: true, data: &[1] };
+
+    reassembler.push_chunk(terminal).unwrap();
+    assert!(reassembler.push_chunk(Chunk { seq: Seq(5), last: false, ..terminal }).is_err());
+
+    match Reassembler::new(100).push(&first_chunks[0]) {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // Empty chunks and per-chunk overhead
+    let mut reassembler = Reassembler::new(10 * (1 + CHUNK_OVERHEAD));
+
+    for &(seq, last) in &[(0, false), (3, false), (3, true)] {
+        match reassembler.push_chunk(Chunk { hash: 7, seq: Seq(seq), last, data: &[] }) {
+            Err(Error::InvalidData) => {},
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    for hash in 0..10 {
+        reassembler.push_chunk(Chunk { hash, seq: Seq(1), last: false, data: &[0] }).unwrap();
+    }
+
+    assert_eq!(reassembler.pending(), 10);
+    assert_eq!(reassembler.buffered(), 10 * (1 + CHUNK_OVERHEAD));
+
+    match reassembler.push_chunk(Chunk { hash: 10, seq: Seq(1), last: false, data: &[0] }) {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    reassembler.discard(0);
+    assert_eq!(reassembler.buffered(), 9 * (1 + CHUNK_OVERHEAD));
+}
+
+#[test]
+fn snapshot_replication() {
+    use bitsparrow::{BitDecode, Result};
+    use bitsparrow::replicate::{Diff, Replicator, Applier, Update};
+    use bitsparrow::seq::Seq;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Counters(Vec<u32>);
+
+    impl BitEncode for Counters {
+        fn encode<S: Sink>(&self, e: &mut Encoder<S>) {
+            e.write(&self.0);
+        }
+    }
+
+    impl<'src> BitDecode<'src> for Counters {
+        fn decode<S: Source<'src>>(d: &mut Decoder<'src, S>) -> Result<Self> {
+            d.read().map(Counters)
+        }
+    }
+
+    impl Diff for Counters {
+        // Index and new value of every changed counter
+        type Patch = Vec<(u32, u32)>;
+
+        fn diff(&self, old: &Self) -> Option<Self::Patch> {
+            let patch: Vec<_> = self.0.iter().zip(&old.0).enumerate()
+                .filter(|&(_, (new, old))| new != old)
+                .map(|(index, (&new, _))| (index as u32, new))
+                .collect();
+
+            match patch.is_empty() {
+                true => None,
+                false => Some(patch),
+            }
+        }
+
+        fn apply(&mut self, patch: Self::Patch) -> Result<()> {
+            for (index, value) in patch {
+                *self.0.get_mut(index as usize).ok_or(Error::InvalidData)? = value;
+            }
+            Ok(())
+        }
+    }
+
+    let mut replicator = Replicator::new().snapshot_interval(2);
+    let mut state = Counters(vec![0; 4]);
+
+    let snapshot = replicator.update(&state).unwrap();
+
+    assert_eq!(replicator.update(&state), None);
+    assert_eq!(replicator.next_seq(), Seq(1));
+
+    state.0[1] = 5;
+    let first = replicator.update(&state).unwrap();
+    state.0[3] = 7;
+    let second = replicator.update(&state).unwrap();
+    state.0[0] = 1;
+    let resync = replicator.update(&state).unwrap();
+
+    assert_eq!(Decoder::decode::<Update<Counters, Vec<(u32, u32)>>>(&first).unwrap(), Update::Patch { seq: Seq(1), patch: vec![(1, 5)] });
+    assert_eq!(Decoder::decode::<Update<Counters, Vec<(u32, u32)>>>(&resync).unwrap().seq(), Seq(3));
+
+    let mut applier = Applier::<Counters>::new();
+
+    match applier.apply(&first) {
+        Err(Error::InvalidData) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(applier.apply(&snapshot).unwrap(), &Counters(vec![0; 4]));
+
+    match applier.apply(&second) {
+        Err(Error::OutOfSequence { expected: 1, received: 2 }) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(applier.apply(&first).unwrap(), &Counters(vec![0, 5, 0, 0]));
+    assert_eq!(applier.apply(&second).unwrap(), &Counters(vec![0, 5, 0, 7]));
+    assert_eq!(applier.seq(), Some(Seq(2)));
+
+    // Late joiner catches up with the next snapshot
+    let mut late = Applier::new();
+
+    assert_eq!(late.apply(&resync).unwrap(), &state);
+    assert_eq!(applier.apply(&resync).unwrap(), &state);
+    assert_eq!(late.value(), applier.value());
+}
+
+#[test]
+fn typed_channels() {
+    use bitsparrow::channel::{TypedSender, TypedReceiver};
+
+    let mut sender = TypedSender::new(Vec::new());
+
+    sender.send(&vec![1u32, 2, 3]).unwrap();
+    sender.send(&vec![]).unwrap();
+    sender.send(&vec![42u32; 200]).unwrap();
+
+    let stream = sender.into_inner();
+
+    assert_eq!(&stream[..6], &[13, 3, 0, 0, 0, 1]);
+
+    let mut receiver = TypedReceiver::<Vec<u32>, _>::new(&stream[..]);
+
+    assert_eq!(receiver.recv().unwrap(), Some(vec![1, 2, 3]));
+    assert_eq!(receiver.recv().unwrap(), Some(vec![]));
+    assert_eq!(receiver.recv().unwrap(), Some(vec![42; 200]));
+    assert_eq!(receiver.recv().unwrap(), None);
+
+    // Stream cut off in the middle of a message
+    let mut receiver = TypedReceiver::<Vec<u32>, _>::new(&stream[..3]);
+
+    match receiver.recv() {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let mut receiver = TypedReceiver::<Vec<u32>, _>::with_max_size(&stream[..], 4);
+
+    match receiver.recv() {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // Every frame is written at once
+    struct Writes(Vec<Vec<u8>>);
+
+    impl ::std::io::Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            self.0.push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut sender = TypedSender::new(Writes(Vec::new()));
+
+    sender.send(&vec![1u32, 2, 3]).unwrap();
+    sender.send(&vec![42u32; 200]).unwrap();
+
+    assert_eq!(sender.into_inner().0, vec![stream[..14].to_vec(), stream[16..].to_vec()]);
+
+    #[cfg(feature = "tokio-util")]
+    {
+        use bitsparrow::channel::Codec;
+        use tokio_util::bytes::BytesMut;
+        use tokio_util::codec::{Encoder as _, Decoder as _};
+
+        let mut codec = Codec::<Vec<u32>>::new();
+        let mut buffer = BytesMut::new();
+
+        codec.encode(&vec![1u32, 2, 3], &mut buffer).unwrap();
+        codec.encode(vec![42u32; 200], &mut buffer).unwrap();
+
+        let mut sender = TypedSender::new(Vec::new());
+
+        sender.send(&vec![1u32, 2, 3]).unwrap();
+        sender.send(&vec![42u32; 200]).unwrap();
+
+        assert_eq!(&buffer[..], &sender.into_inner()[..]);
+
+        // Frames arriving a few bytes at a time
+        let mut received = BytesMut::new();
+        let mut messages = Vec::new();
+
+        for part in buffer.chunks(3) {
+            received.extend_from_slice(part);
+
+            while let Some(message) = codec.decode(&mut received).unwrap() {
+                messages.push(message);
+            }
+        }
+
+        assert_eq!(messages, vec![vec![1, 2, 3], vec![42; 200]]);
+        assert!(received.is_empty());
+    }
+}
+
+#[test]
+fn in_memory_duplex() {
+    use std::io::{Read, Write};
+    use std::thread;
+    use bitsparrow::channel;
+
+    let (mut client, mut server) = channel::duplex::<(u32, String), Vec<u32>>();
+
+    assert_eq!(server.try_recv().unwrap(), None);
+
+    client.send(&(3, "ping".into())).unwrap();
+    client.send(&(2, "pong".into())).unwrap();
+
+    assert_eq!(server.try_recv().unwrap(), Some((3, "ping".into())));
+    assert_eq!(server.recv().unwrap(), Some((2, "pong".into())));
+    assert_eq!(server.try_recv().unwrap(), None);
+
+    let handle = thread::spawn(move || {
+        while let Some((count, _)) = server.recv().unwrap() {
+            server.send(&vec![count; count as usize]).unwrap();
+        }
+    });
+
+    for count in 0..50 {
+        client.send(&(count, "echo".into())).unwrap();
+
+        assert_eq!(client.recv().unwrap(), Some(vec![count; count as usize]));
+    }
+
+    drop(client);
+    handle.join().unwrap();
+
+    // Closing either end of a pipe
+    let (mut writer, mut reader) = channel::pipe();
+    let mut buf = [0; 4];
+
+    writer.write_all(b"abc").unwrap();
+    drop(writer);
+
+    assert_eq!(reader.read(&mut buf).unwrap(), 3);
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+    let (mut writer, reader) = channel::pipe();
+
+    drop(reader);
+
+    assert_eq!(writer.write(b"abc").unwrap_err().kind(), std::io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+#[cfg(all(feature = "serde", feature = "bincode", feature = "postcard"))]
+fn serde_migration() {
+    use bitsparrow::migrate;
+
+    type Order<'a> = (u64, &'a str, Vec<u16>, bool);
+
+    let order: Order = (9001, "espresso", vec![3, 1, 4], true);
+    let bitsparrow = Encoder::encode(&order);
+
+    let legacy = bincode::serialize(&order).unwrap();
+
+    assert_eq!(migrate::bincode_to_bitsparrow::<Order>(&legacy).unwrap(), bitsparrow);
+    assert_eq!(migrate::bitsparrow_to_bincode::<Order>(&bitsparrow).unwrap(), legacy);
+
+    let legacy = postcard::to_allocvec(&order).unwrap();
+
+    assert_eq!(migrate::postcard_to_bitsparrow::<Order>(&legacy).unwrap(), bitsparrow);
+    assert_eq!(migrate::bitsparrow_to_postcard::<Order>(&bitsparrow).unwrap(), legacy);
+
+    match migrate::bincode_to_bitsparrow::<Order>(&[1, 2, 3]) {
+        Err(Error::Io(ref err)) if err.kind() == std::io::ErrorKind::InvalidData => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    match migrate::bitsparrow_to_postcard::<Order>(&bitsparrow[..4]) {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn shared_codec_state() {
+    use std::sync::Arc;
+    use std::thread;
+    use bitsparrow::metadata::Metadata;
+    use bitsparrow::chunked::Reassembler;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<Interner>();
+    assert_send_sync::<Protocol<Interner>>();
+    assert_send_sync::<Container>();
+    assert_send_sync::<Metadata>();
+    assert_send_sync::<Reassembler>();
+    #[cfg(feature = "zstd")]
+    assert_send_sync::<bitsparrow::compress::Dictionaries>();
+
+    let mut template = Interner::new().preload(["user.login", "user.logout"]);
+
+    template.write(&mut Encoder::new(), "page.view");
+
+    let template = Arc::new(template.freeze());
+
+    assert_eq!(template.len(), 3);
+    assert_eq!(template.id("page.view"), Some(2));
+
+    let handles: Vec<_> = (0..4).map(|worker| {
+        let template = template.clone();
+
+        thread::spawn(move || {
+            let mut sender = (*template).clone();
+            let mut receiver = (*template).clone();
+            let name = format!("worker.{}", worker);
+
+            for &name in &["user.logout", &name, &name, "page.view"] {
+                let buffer = Encoder::new().write_with(&Interned::from(name), &mut sender).end();
+                let interned: Interned = Decoder::new(&buffer).read_with(&mut receiver).unwrap();
+
+                assert_eq!(&*interned, name);
+            }
+
+            assert_eq!(sender.id(&name), Some(3));
+            assert_eq!(receiver.get(3), Some(&*name));
+        })
+    }).collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // Clones don't see strings added by each other
+    assert_eq!(template.len(), 3);
+    assert_eq!(template.get(3), None);
+
+    let refrozen = (*template).clone().preload(["page.click"]).freeze();
+
+    assert_eq!(refrozen.id("page.click"), Some(3));
+    assert_eq!(refrozen.get(0), Some("user.login"));
+}
+
+#[test]
+fn scratch_buffers() {
+    use bitsparrow::scratch;
+
+    let buffer = Encoder::encode(("sparrow", &b"bytes"[..], ""));
+
+    let mut string = String::with_capacity(64);
+    string.push_str("recycled");
+    let ptr = string.as_ptr();
+
+    scratch::recycle(string);
+    assert_eq!(scratch::pooled(), 1);
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_scratch_buffers(true);
+
+    let (name, bytes, empty): (String, Vec<u8>, String) = decoder.read().unwrap();
+
+    assert_eq!((&*name, &*bytes, &*empty), ("sparrow", &b"bytes"[..], ""));
+    assert_eq!(name.as_ptr(), ptr);
+    assert_eq!(scratch::pooled(), 0);
+
+    scratch::recycle(name);
+    scratch::recycle_bytes(bytes);
+    scratch::recycle(empty);
+
+    assert_eq!(scratch::pooled(), 2);
+
+    // Invalid strings are rejected before taking a buffer
+    let invalid = Encoder::new().bytes(&[0xff, 0xfe]).end();
+    let mut decoder = Decoder::new(&invalid);
+    decoder.set_scratch_buffers(true);
+
+    assert!(decoder.read::<String>().is_err());
+    assert_eq!(scratch::pooled(), 2);
+
+    // Sources that can't be borrowed from fall back to allocating
+    let mut decoder = Decoder::with_source(source::Reader::new(&buffer[..]));
+    decoder.set_scratch_buffers(true);
+
+    assert_eq!(decoder.read::<(String, Vec<u8>, String)>().unwrap().0, "sparrow");
+    assert_eq!(scratch::pooled(), 2);
+
+    // Large buffers aren't retained
+    scratch::recycle_bytes(Vec::with_capacity(scratch::MAX_CAPACITY + 1));
+    assert_eq!(scratch::pooled(), 2);
+
+    scratch::reserve(scratch::MAX_POOLED + 1, 16);
+    assert_eq!(scratch::pooled(), scratch::MAX_POOLED);
+}
+
+#[cfg(all(feature = "bytemuck", not(feature = "forbid-unsafe")))]
+#[test]
+fn pod_into_uninit_memory() {
+    use std::mem::MaybeUninit;
+
+    let values: Vec<u32> = (0..3000).map(|n| n * 7).collect();
+    let mut encoder = Encoder::new();
+
+    for value in &values {
+        encoder.write(value);
+    }
+
+    let buffer = encoder.uint16(0xabcd).end();
+    let mut staging = vec![MaybeUninit::<u32>::uninit(); values.len()];
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(decoder.pod_into_uninit(&mut staging).unwrap(), &values[..]);
+    assert_eq!(decoder.uint16().unwrap(), 0xabcd);
+
+    // Unbuffered sources go through several chunks on the stack
+    let mut staging = vec![MaybeUninit::<u32>::uninit(); values.len()];
+    let mut decoder = Decoder::with_source(Reader::new(&buffer[..]));
+
+    assert_eq!(decoder.pod_into_uninit(&mut staging).unwrap(), &values[..]);
+    assert_eq!(decoder.uint16().unwrap(), 0xabcd);
+
+    let mut staging = [MaybeUninit::<i64>::uninit(); 1];
+
+    match Decoder::new(&buffer[..4]).pod_into_uninit(&mut staging) {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(Decoder::new(&[]).pod_into_uninit::<f64>(&mut []).unwrap(), &[]);
+}
+
+#[test]
+fn size_limits() {
+    let huge = Encoder::new().write(bitsparrow::seq::Seq(1 << 53)).end();
+
+    #[cfg(feature = "wasm-opt")]
+    match Decoder::new(&huge).size() {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    #[cfg(not(feature = "wasm-opt"))]
+    assert_eq!(Decoder::new(&huge).size().unwrap() as u64, 1 << 53);
+
+    let safe = Encoder::new().write(bitsparrow::seq::Seq((1 << 53) - 1)).end();
+
+    assert_eq!(Decoder::new(&safe).size().unwrap() as u64, (1 << 53) - 1);
+
+    // Reads past the end fail the same way with either feature
+    let buffer = Encoder::new().size(4).append_raw(b"ab").end();
+
+    match Decoder::new(&buffer).bytes() {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(Decoder::new(&Encoder::encode("abc")).bytes().unwrap(), b"abc");
+}
+
+#[test]
+fn js_safe_integers() {
+    use bitsparrow::MAX_SAFE_INTEGER;
+
+    let mut encoder = Encoder::new();
+
+    encoder.js_safe_uint(0).unwrap().js_safe_uint(300).unwrap().js_safe_uint(MAX_SAFE_INTEGER).unwrap();
+
+    match encoder.js_safe_uint(MAX_SAFE_INTEGER + 1) {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+
+    let buffer = encoder.end();
+
+    assert_eq!(&buffer[..3], &[0, 0x81, 0x2c]);
+    assert_eq!(buffer, Encoder::new().size(0).size(300).size(MAX_SAFE_INTEGER as usize).end());
+
+    let mut decoder = Decoder::new(&buffer);
+
+    assert_eq!(decoder.js_safe_uint().unwrap(), 0);
+    assert_eq!(decoder.js_safe_uint().unwrap(), 300);
+    assert_eq!(decoder.js_safe_uint().unwrap(), MAX_SAFE_INTEGER);
+    assert!(decoder.end());
+
+    let unsafe_value = Encoder::new().write(bitsparrow::seq::Seq(MAX_SAFE_INTEGER + 1)).end();
+
+    match Decoder::new(&unsafe_value).js_safe_uint() {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn streaming_frames() {
+    use bitsparrow::channel::{TypedSender, StreamDecoder};
+
+    let mut sender = TypedSender::new(Vec::new());
+    let messages: Vec<(u32, String)> = (0..50).map(|id| (id, "x".repeat(id as usize * 3))).collect();
+
+    for message in &messages {
+        sender.send(message).unwrap();
+    }
+
+    let body = sender.into_inner();
+
+    for chunk_size in &[1, 3, 64, body.len()] {
+        let mut decoder = StreamDecoder::<(u32, String)>::new();
+        let mut received = Vec::new();
+
+        for chunk in body.chunks(*chunk_size) {
+            decoder.push(chunk);
+
+            while let Some(message) = decoder.pop().unwrap() {
+                received.push(message);
+            }
+        }
+
+        assert_eq!(received, messages);
+        assert_eq!(decoder.buffered(), 0);
+        decoder.finish().unwrap();
+    }
+
+    let mut truncated = StreamDecoder::<(u32, String)>::new();
+
+    truncated.push(&body[..body.len() - 1]);
+    while truncated.pop().unwrap().is_some() {}
+
+    match truncated.finish() {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // A frame that fails to decode is skipped
+    let mut skipping = StreamDecoder::<(u32, String)>::new();
+
+    skipping.push(&[2, 0xFF, 0xFF]).push(&body);
+
+    assert!(skipping.pop().is_err());
+
+    let mut received = Vec::new();
+
+    while let Some(message) = skipping.pop().unwrap() {
+        received.push(message);
+    }
+
+    assert_eq!(received, messages);
+
+    let mut limited = StreamDecoder::<(u32, String)>::with_max_size(6);
+
+    limited.push(&body);
+
+    assert!(limited.pop().unwrap().is_some());
+    match limited.pop() {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn parallel_sections() {
+    use std::thread;
+    use bitsparrow::container::decode_section;
+
+    let mut buffer = Vec::new();
+    let mut ranges = Vec::new();
+
+    for id in 0..16u32 {
+        let start = buffer.len();
+        buffer.extend_from_slice(&Encoder::encode((id, format!("section {}", id))));
+        ranges.push(start..buffer.len());
+    }
+
+    let decoded: Vec<(u32, &str)> = thread::scope(|scope| {
+        let buffer = &buffer;
+        let handles: Vec<_> = ranges
+            .iter()
+            .map(|range| scope.spawn(move || decode_section::<(u32, &str)>(buffer, range.clone())))
+            .collect();
+
+        handles.into_iter().map(|handle| handle.join().unwrap().unwrap()).collect()
+    });
+
+    assert_eq!(decoded[9], (9, "section 9"));
+
+    match decode_section::<(u32, &str)>(&buffer, ranges[0].start..ranges[1].end) {
+        Err(Error::BufferNotEmpty) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    match decode_section::<(u32, &str)>(&buffer, 0..buffer.len() + 1) {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    #[cfg(feature = "rayon")]
+    {
+        use bitsparrow::container::decode_sections;
+
+        let sections = decode_sections::<(u32, String)>(&buffer, &ranges).unwrap();
+
+        assert_eq!(sections.len(), 16);
+        assert!(sections.iter().enumerate().all(|(i, &(id, ref name))| {
+            id == i as u32 && *name == format!("section {}", i)
+        }));
+
+        let mut broken = ranges.clone();
+        broken[3].end -= 1;
+
+        assert!(decode_sections::<(u32, String)>(&buffer, &broken).is_err());
+    }
+}
+
+#[test]
+fn container_sections() {
+    use bitsparrow::container::decode_section;
+
+    let buffer = Container::new(2)
+        .wire_version(true)
+        .sections()
+        .section("header", ("world", 3u32))
+        .section("entities", vec![1u32, 2, 3, 4])
+        .section("empty", ())
+        .section("names", vec!["a", "bb", "ccc"])
+        .end();
+
+    let (container, index) = Container::read_index(&buffer).unwrap();
+
+    assert_eq!(container.version(), 2);
+    assert!(container.has_index());
+    assert_eq!(index.len(), 4);
+    assert_eq!(index.names().collect::<Vec<_>>(), ["header", "entities", "empty", "names"]);
+
+    let names: Vec<&str> = index.decode(&buffer, "names").unwrap().unwrap();
+    assert_eq!(names, ["a", "bb", "ccc"]);
+
+    let range = index.get("entities").unwrap();
+    assert_eq!(&buffer[range.clone()], &Encoder::encode(vec![1u32, 2, 3, 4])[..]);
+    assert_eq!(decode_section::<Vec<u32>>(&buffer, range).unwrap(), [1, 2, 3, 4]);
+
+    assert_eq!(index.get("empty").map(|range| range.len()), Some(0));
+    assert!(index.decode::<u32>(&buffer, "missing").unwrap().is_none());
+
+    // Sections of containers written before wire version 2
+    let mac = [0x00, 0x1B, 0x44, 0x11, 0x3A, 0xB7];
+    let mut legacy = Container::new(2).wire_version(true).sections().section("mac", &mac[..]).end();
+    legacy[7..9].copy_from_slice(&Encoder::encode(1u16));
+
+    let (_, index) = Container::read_index(&legacy).unwrap();
+    assert_eq!(index.decode::<[u8; 6]>(&legacy, "mac").unwrap(), Some(mac));
+
+    match Container::decode::<(&str, u32)>(&buffer) {
+        Err(Error::BufferNotEmpty) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    match Container::read_index(&Container::new(2).encode(42u32)) {
+        Err(Error::InvalidData) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let empty = Container::new(0).checksum(true).sections().end();
+    let (_, index) = Container::read_index(&empty).unwrap();
+    assert!(index.is_empty());
+
+    let mut corrupted = buffer.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] = 0xff;
+
+    assert!(Container::read_index(&corrupted).is_err());
+}
+
+#[test]
+fn field_projection() {
+    use bitsparrow::project::Layout;
+
+    let layout = Layout::new()
+        .field::<u32>("id")
+        .field::<String>("name")
+        .field::<Vec<u64>>("history")
+        .field::<(bool, bool)>("flags")
+        .field::<String>("comment");
+
+    assert_eq!(layout.len(), 5);
+
+    let projection = layout.select(&["comment", "id", "flags"]).unwrap();
+
+    let rows = Encoder::new()
+        .write((7u32, "seven", vec![1u64, 2, 3], (true, false), "lucky"))
+        .write((13u32, "thirteen", Vec::<u64>::new(), (false, true), ""))
+        .end();
+
+    let mut decoder = Decoder::new(&rows);
+
+    let first = projection.read(&mut decoder).unwrap();
+    assert_eq!(first.len(), 3);
+    assert_eq!(first.get::<&str>(0).unwrap().unwrap(), "lucky");
+    assert_eq!(first.get::<u32>(1).unwrap().unwrap(), 7);
+    assert_eq!(first.get::<(bool, bool)>(2).unwrap().unwrap(), (true, false));
+    assert!(first.get::<u32>(3).is_none());
+
+    let second = projection.read(&mut decoder).unwrap();
+    assert_eq!(second.get::<u32>(1).unwrap().unwrap(), 13);
+    assert_eq!(second.raw(0), Some(&[0u8][..]));
+    assert!(decoder.end());
+
+    let none = layout.select(&[]).unwrap().read(&mut Decoder::new(&rows)).unwrap();
+    assert!(none.is_empty());
+
+    for names in &[&["id", "missing"][..], &["id", "id"][..]] {
+        let err = layout.select(names).unwrap_err();
+        assert_eq!(err.path().unwrap(), names[1]);
+    }
+
+    let broken = Encoder::new().write((1u32, "one")).size(3).end();
+    let err = projection.read(&mut Decoder::new(&broken)).unwrap_err();
+
+    assert_eq!(err.path().unwrap(), "history");
+}
+
+#[test]
+fn lazy_views() {
+    use bitsparrow::project::Layout;
+
+    let layout = Layout::new()
+        .field::<u32>("id")
+        .field::<String>("name")
+        .field::<Vec<String>>("aliases")
+        .field::<u64>("created");
+
+    let buffer = Encoder::encode((9u32, "sparrow", vec!["passer", "sparrer"], 1700000000u64));
+    let mut view = layout.view(&buffer);
+
+    assert_eq!(view.get::<u64>("created").unwrap(), 1700000000);
+    assert_eq!(view.get::<Vec<&str>>("aliases").unwrap(), ["passer", "sparrer"]);
+    assert_eq!(view.get::<u32>("id").unwrap(), 9);
+    assert_eq!(view.raw("name").unwrap(), &Encoder::encode("sparrow")[..]);
+    assert_eq!(view.size().unwrap(), buffer.len());
+
+    assert_eq!(view.get::<u32>("missing").unwrap_err().path().unwrap(), "missing");
+    assert_eq!(view.get::<String>("id").unwrap_err().path().unwrap(), "id");
+
+    // Fields past the truncation point only fail once accessed
+    let mut truncated = layout.view(&buffer[..8]);
+
+    assert_eq!(truncated.get::<u32>("id").unwrap(), 9);
+    assert_eq!(truncated.get::<&str>("name").unwrap_err().path().unwrap(), "name");
+    assert!(truncated.size().is_err());
+
+    assert_eq!(Layout::new().view(&[]).size().unwrap(), 0);
+}
+
+#[test]
+fn allocation_accounting() {
+    use std::collections::HashMap;
+    use std::mem::size_of;
+    use std::ops::Range;
+    use bitsparrow::metadata::Metadata;
+    use bitsparrow::seq::AckRange;
+
+    let buffer = Encoder::encode((vec!["ab", "cde"], Box::new(7u64), &b"xyz"[..]));
+
+    let mut decoder = Decoder::new(&buffer);
+    let _: (Vec<String>, Box<u64>, Vec<u8>) = decoder.read().unwrap();
+
+    let expected = 2 * size_of::<String>() + 5 + size_of::<u64>() + 3;
+    assert_eq!(decoder.allocated(), expected);
+
+    // Borrowed values don't allocate
+    let mut decoder = Decoder::new(&buffer);
+    let _: (Vec<&str>, u64, &[u8]) = decoder.read().unwrap();
+    assert_eq!(decoder.allocated(), 2 * size_of::<&str>());
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_max_allocation(expected);
+    assert!(decoder.read::<(Vec<String>, Box<u64>, Vec<u8>)>().is_ok());
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_max_allocation(expected - 1);
+
+    match decoder.read::<(Vec<String>, Box<u64>, Vec<u8>)>() {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // The cap applies across values, not just within one
+    let many = Encoder::new().write("a".repeat(600)).write("b".repeat(600)).end();
+    let mut decoder = Decoder::new(&many);
+    decoder.set_max_allocation(1000);
+
+    assert!(decoder.read::<String>().is_ok());
+    match decoder.read::<String>() {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let map = Encoder::encode(vec![(1u32, 2u32)].into_iter().collect::<HashMap<_, _>>());
+    let mut decoder = Decoder::new(&map);
+    decoder.set_max_allocation(size_of::<(u32, u32)>() - 1);
+
+    assert!(decoder.read::<HashMap<u32, u32>>().is_err());
+
+    // Owned values of the crate's own types are counted too
+    let meta = Metadata::new().extension(300, true);
+    let ranges = AckRange::from_ranges(vec![1..3, 5..8]);
+    let own = Encoder::encode((meta, ranges, FixedStr::<8>::new("abc")));
+
+    let mut decoder = Decoder::new(&own);
+    let _: (Metadata, AckRange, FixedStr<8>) = decoder.read().unwrap();
+
+    let expected = size_of::<(u16, Vec<u8>)>() + 1 + 2 * size_of::<Range<u64>>() + 3;
+    assert_eq!(decoder.allocated(), expected);
+
+    let mut decoder = Decoder::new(&own);
+    decoder.set_max_allocation(expected - 1);
+
+    match decoder.read::<(Metadata, AckRange, FixedStr<8>)>() {
+        Err(Error::LimitExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn time_budget() {
+    use std::time::{Duration, Instant};
+
+    let huge = Encoder::new().size(1 << 40).end();
+
+    let mut decoder = Decoder::new(&huge);
+    decoder.set_time_budget(Duration::from_millis(10));
+
+    let started = Instant::now();
+
+    match decoder.read::<Vec<()>>() {
+        Err(Error::BudgetExceeded) => {},
+        other => panic!("unexpected {:?}", other.map(|vec| vec.len())),
+    }
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let mut decoder = Decoder::new(&huge);
+    decoder.set_deadline(Some(Instant::now()));
+
+    match decoder.verify::<Vec<()>>() {
+        Err(Error::BudgetExceeded) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let buffer = Encoder::encode(vec![vec![1u32, 2], vec![3]]);
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_time_budget(Duration::from_secs(60));
+
+    assert_eq!(decoder.read::<Vec<Vec<u32>>>().unwrap(), [vec![1, 2], vec![3]]);
+
+    let mut decoder = Decoder::new(&buffer);
+    decoder.set_deadline(Some(Instant::now())).set_deadline(None);
+
+    assert!(decoder.read::<Vec<Vec<u32>>>().is_ok());
+    assert_eq!(Error::BudgetExceeded.to_string(), "Decoding time budget exceeded");
+}
+
+bit_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(u16)]
+    enum Opcode {
+        Nop = 0,
+        Load = 0x10,
+        Store = 0x11,
+        /// Far outside of a single byte
+        Halt = 0xffff,
+    }
+}
+
+#[test]
+fn explicit_discriminants() {
+    assert_eq!(Encoder::encode(Opcode::Nop), &[0x00]);
+    assert_eq!(Encoder::encode(Opcode::Store), &[0x11]);
+    assert_eq!(Encoder::encode(Opcode::Halt), Encoder::new().size(0xffff).end());
+
+    let program = vec![Opcode::Load, Opcode::Store, Opcode::Nop, Opcode::Halt];
+    let buffer = Encoder::encode(&program);
+
+    assert_eq!(Decoder::decode::<Vec<Opcode>>(&buffer).unwrap(), program);
+    assert!(Decoder::validate::<Vec<Opcode>>(&buffer).is_ok());
+
+    match Decoder::decode::<Opcode>(&[0x12]) {
+        Err(Error::InvalidEnumVariant { tag: 0x12 }) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert!(Decoder::validate::<Opcode>(&[0x01]).is_err());
+}
+
+#[test]
+fn huge_size_prefix_over_chain() {
+    // Source relying on the default `read_vec`
+    struct Bytes<'a>(&'a [u8], usize);
+
+    impl<'a> Source<'a> for Bytes<'a> {
+        fn position(&self) -> usize {
+            self.1
+        }
+
+        fn read_into(&mut self, buf: &mut [u8]) -> bitsparrow::Result<()> {
+            let bytes = self.0.get(self.1..self.1 + buf.len()).ok_or(Error::ReadingOutOfBounds)?;
+
+            buf.copy_from_slice(bytes);
+            self.1 += buf.len();
+            Ok(())
+        }
+    }
+
+    let buffer = Encoder::new().size(1 << 40).append_raw(b"abc").end();
+
+    assert_eq!(buffer.len(), 9);
+
+    let (head, tail) = buffer.split_at(4);
+
+    match Decoder::with_source(Chain::new(vec![head, tail])).read::<Vec<u8>>() {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    match Decoder::with_source(Chain::new(vec![head, tail])).read::<String>() {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    match Decoder::with_source(Bytes(&buffer, 0)).read::<Vec<u8>>() {
+        Err(Error::ReadingOutOfBounds) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let data: Vec<u8> = (0..10000u32).map(|i| i as u8).collect();
+    let buffer = Encoder::encode(&data);
+
+    assert_eq!(Decoder::with_source(Bytes(&buffer, 0)).read::<Vec<u8>>().unwrap(), data);
+}